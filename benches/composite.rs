@@ -0,0 +1,73 @@
+//! Compares 30 reads and 10 writes of DE 48 sub-elements through `IsoMsg::composite` with
+//! rescanning the field through `pds::get_pds_values` and setting it after every write.
+//! Run with `cargo bench --bench composite`.
+use iso8583::iso_msg::IsoMsg;
+use iso8583::iso_specs::IsoSpecs;
+use iso8583::pds;
+use std::collections::BTreeMap;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const ROUNDS: u32 = 2_000;
+const SUB_ELEMENTS: usize = 70;
+
+fn main() {
+    let spec = IsoSpecs::new();
+    let mut payload = b"1644".to_vec();
+    payload.extend_from_slice(&[0x70, 0, 0x01, 0, 0, 0, 0, 0]);
+    payload.extend_from_slice(b"165555444433332222000000000000001000200");
+    let mut iso_msg = IsoMsg::new(&spec, &payload);
+    let de48: String = (0..SUB_ELEMENTS)
+        .map(|tag| format!("{:04}{:03}{}", tag * 2, 5, "VALUE"))
+        .collect();
+    iso_msg.set_field(48, de48.as_bytes()).unwrap();
+    let read_tags: Vec<String> = (0..30).map(|tag| format!("{:04}", tag * 6)).collect();
+    let write_tags: Vec<String> = (0..10).map(|tag| format!("{:04}", tag * 20 + 1)).collect();
+
+    let naive = time(|| {
+        let mut msg = iso_msg.clone();
+        for tag in &read_tags {
+            let values = pds::get_pds_values(&msg.get_field_slice(48).unwrap().to_vec());
+            black_box(values.unwrap().unwrap().get(tag).cloned());
+        }
+        for tag in &write_tags {
+            let values = pds::get_pds_values(&msg.get_field_slice(48).unwrap().to_vec());
+            let mut values: BTreeMap<_, _> = values.unwrap().unwrap().into_iter().collect();
+            values.insert(tag.clone(), "ADDED".to_string());
+            let value: String = values
+                .iter()
+                .map(|(tag, value)| format!("{}{:03}{}", tag, value.len(), value))
+                .collect();
+            msg.set_field(48, value.as_bytes()).unwrap();
+        }
+        black_box(msg);
+    });
+
+    let composite = time(|| {
+        let mut msg = iso_msg.clone();
+        let mut handle = msg.composite(48).unwrap();
+        for tag in &read_tags {
+            black_box(handle.get(tag));
+        }
+        for tag in &write_tags {
+            handle.set(tag, "ADDED").unwrap();
+        }
+        handle.flush(&mut msg).unwrap();
+        black_box(msg);
+    });
+
+    println!(
+        "{} sub-elements, 30 reads and 10 writes per round",
+        SUB_ELEMENTS
+    );
+    println!("naive:     {:?} per round", naive / ROUNDS);
+    println!("composite: {:?} per round", composite / ROUNDS);
+}
+
+fn time<F: FnMut()>(mut round: F) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        round();
+    }
+    start.elapsed()
+}
@@ -0,0 +1,68 @@
+//! Compares validating, projecting and decorating 10k records with three separate passes
+//! against one `Pipeline` walk. Run with `cargo bench --bench pipeline`.
+use iso8583::iso_msg::IsoMsg;
+use iso8583::iso_specs::IsoSpecs;
+use iso8583::pipeline::Pipeline;
+use iso8583::validator::{DefaultValidator, FieldValidator};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const ROUNDS: u32 = 20;
+const RECORDS: usize = 10_000;
+const PROJECTED: [usize; 4] = [2, 4, 24, 49];
+
+fn main() {
+    let spec = IsoSpecs::new();
+    let mut payload = vec![];
+    for record in 0..RECORDS {
+        payload.extend_from_slice(b"1644");
+        payload.extend_from_slice(&[0x70, 0, 0x01, 0, 0, 0, 0x80, 0]);
+        payload.extend_from_slice(b"165555444433332222000000");
+        payload.extend_from_slice(format!("{:012}", record).as_bytes());
+        payload.extend_from_slice(b"200840");
+    }
+
+    let separate = time(|| {
+        let mut offset = 0;
+        while offset < payload.len() {
+            let iso_msg = IsoMsg::try_new(&spec, &payload[offset..]).unwrap();
+            offset += iso_msg.length();
+            let issues: Vec<_> = iso_msg
+                .iter()
+                .filter(|field| Some(field.index()) != spec.bitmap_index())
+                .filter_map(|field| {
+                    DefaultValidator
+                        .validate(field.index(), field.iso_field(), field.value())
+                        .err()
+                })
+                .collect();
+            let projection: Vec<_> = PROJECTED
+                .iter()
+                .map(|&index| iso_msg.get_field_slice(index).ok().map(|value| value.to_vec()))
+                .collect();
+            black_box((issues, projection, iso_msg.length()));
+        }
+    });
+
+    let mut pipeline = Pipeline::new(&spec)
+        .validate(&DefaultValidator)
+        .project(&PROJECTED)
+        .decorate(|iso_msg: &IsoMsg| iso_msg.length());
+    let fused = time(|| {
+        for output in pipeline.run(&payload) {
+            black_box(output.unwrap());
+        }
+    });
+
+    println!("{} records per round", RECORDS);
+    println!("separate passes: {:?} per round", separate / ROUNDS);
+    println!("pipeline:        {:?} per round", fused / ROUNDS);
+}
+
+fn time<F: FnMut()>(mut round: F) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        round();
+    }
+    start.elapsed()
+}
@@ -0,0 +1,109 @@
+use crate::iso_error::IsoError;
+
+/// Bounds on the memory reserved from sizes read out of untrusted input, such as frame
+/// length headers, checked before the reservation is attempted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AllocBudget {
+    /// largest single allocation
+    pub max_allocation: usize,
+    /// largest amount allocated while reading one file
+    pub max_total: usize,
+}
+
+impl AllocBudget {
+    pub const DEFAULT_MAX_ALLOCATION: usize = 16 * 1024 * 1024;
+    pub const DEFAULT_MAX_TOTAL: usize = 256 * 1024 * 1024;
+
+    pub fn new(max_allocation: usize, max_total: usize) -> AllocBudget {
+        AllocBudget {
+            max_allocation,
+            max_total,
+        }
+    }
+
+    /// Fails when a single allocation of `requested` bytes exceeds `max_allocation`,
+    /// `context` names what was being allocated
+    pub fn check(&self, requested: usize, context: &'static str) -> Result<(), IsoError> {
+        if requested > self.max_allocation {
+            return Err(IsoError::AllocationBudgetExceeded {
+                requested,
+                limit: self.max_allocation,
+                context,
+            });
+        }
+        Ok(())
+    }
+
+    /// Starts accounting the allocations made while reading one file
+    pub fn tracker(&self) -> AllocTracker {
+        AllocTracker {
+            budget: *self,
+            spent: 0,
+        }
+    }
+}
+
+impl Default for AllocBudget {
+    fn default() -> Self {
+        AllocBudget::new(
+            AllocBudget::DEFAULT_MAX_ALLOCATION,
+            AllocBudget::DEFAULT_MAX_TOTAL,
+        )
+    }
+}
+
+/// Running total of the allocations charged against an `AllocBudget`
+#[derive(Debug, Clone)]
+pub struct AllocTracker {
+    budget: AllocBudget,
+    spent: usize,
+}
+
+impl AllocTracker {
+    /// Checks `requested` against both limits and records it when it fits
+    pub fn charge(&mut self, requested: usize, context: &'static str) -> Result<(), IsoError> {
+        self.budget.check(requested, context)?;
+        let total = self.spent.saturating_add(requested);
+        if total > self.budget.max_total {
+            return Err(IsoError::AllocationBudgetExceeded {
+                requested: total,
+                limit: self.budget.max_total,
+                context,
+            });
+        }
+
+        self.spent = total;
+        Ok(())
+    }
+
+    /// Bytes charged so far
+    pub fn spent(&self) -> usize {
+        self.spent
+    }
+}
+
+#[test]
+fn tracker_enforces_both_limits() {
+    let budget = AllocBudget::new(10, 25);
+    assert_eq!(
+        budget.check(11, "frame"),
+        Err(IsoError::AllocationBudgetExceeded {
+            requested: 11,
+            limit: 10,
+            context: "frame"
+        })
+    );
+
+    let mut tracker = budget.tracker();
+    tracker.charge(10, "record").unwrap();
+    tracker.charge(10, "record").unwrap();
+    assert_eq!(
+        tracker.charge(10, "record"),
+        Err(IsoError::AllocationBudgetExceeded {
+            requested: 30,
+            limit: 25,
+            context: "record"
+        })
+    );
+    assert_eq!(tracker.spent(), 20);
+}
@@ -0,0 +1,82 @@
+use crate::context::Context;
+use crate::iso_error::IsoError;
+use crate::iso_msg::IsoMsg;
+use crate::iso_specs::IsoSpecs;
+use crate::numeric;
+
+/// Composes a message from nothing, field by field
+///
+/// Every value goes through `IsoMsg::set_field` as it is given, the first error is kept
+/// and returned by `build` or `build_into`, the values given after it are ignored. The
+/// bitmap is computed from the fields set when the message is serialized.
+pub struct IsoMsgBuilder<'b> {
+    iso_msg: IsoMsg<'static, 'b>,
+    error: Option<IsoError>,
+}
+
+impl<'b> IsoMsgBuilder<'b> {
+    pub fn new(iso_spec: &'b IsoSpecs) -> IsoMsgBuilder<'b> {
+        IsoMsgBuilder {
+            iso_msg: IsoMsg::empty(iso_spec),
+            error: None,
+        }
+    }
+
+    pub fn set(mut self, index: usize, value: &[u8]) -> IsoMsgBuilder<'b> {
+        if self.error.is_none() {
+            self.error = self.iso_msg.set_field(index, value).err();
+        }
+        self
+    }
+
+    pub fn set_str(self, index: usize, value: &str) -> IsoMsgBuilder<'b> {
+        self.set(index, value.as_bytes())
+    }
+
+    /// Sets a numeric field, zero padded to its length, see `IsoMsg::set_field_num`
+    pub fn set_num(mut self, index: usize, value: u64) -> IsoMsgBuilder<'b> {
+        if self.error.is_none() {
+            self.error = self.iso_msg.set_field_num(index, value).err();
+        }
+        self
+    }
+
+    /// Sets DE 11, systems trace audit number, to the next STAN of `context`
+    pub fn set_stan(mut self, context: &Context) -> IsoMsgBuilder<'b> {
+        if self.error.is_none() {
+            let stan = numeric::format_fixed(context.next_stan(), 6);
+            self.error = stan
+                .map_err(IsoError::from)
+                .and_then(|stan| self.iso_msg.set_de(11, &stan))
+                .err();
+        }
+        self
+    }
+
+    /// Sets DE 7, transmission date and time, to the current time of `context`
+    pub fn set_transmission_date_time(mut self, context: &Context) -> IsoMsgBuilder<'b> {
+        if self.error.is_none() {
+            self.error = context
+                .transmission_date_time()
+                .map_err(IsoError::from)
+                .and_then(|date_time| self.iso_msg.set_de(7, &date_time))
+                .err();
+        }
+        self
+    }
+
+    pub fn build(self) -> Result<IsoMsg<'static, 'b>, IsoError> {
+        match self.error {
+            Some(error) => Err(error),
+            None => Ok(self.iso_msg),
+        }
+    }
+
+    /// Serializes the message into `buffer`, see `IsoMsg::to_byte_array`
+    pub fn build_into(&self, buffer: &mut [u8]) -> Result<usize, IsoError> {
+        match &self.error {
+            Some(error) => Err(error.clone()),
+            None => self.iso_msg.to_byte_array(buffer),
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use crate::context::Context;
+use crate::iso_error::IsoError;
+use crate::iso_msg::IsoMsg;
+use crate::iso_specs::IsoSpecs;
+use std::time::{Duration, UNIX_EPOCH};
+
+/// A record of the file along with its position
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Record<'a> {
+    pub offset: usize,
+    pub payload: &'a [u8],
+}
+
+/// Where a record was found and when, as captured by a `RecordReader`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CaptureEntry {
+    pub offset: usize,
+    pub length: usize,
+    /// time of the context when the reader reached the record, since the unix epoch
+    pub arrival: Duration,
+    pub parse_duration: Duration,
+}
+
+/// Timing of a file processing run, replayed by `Replayer`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Capture {
+    pub records: Vec<CaptureEntry>,
+}
+
+/// Walks the records of a payload holding them back to back, such as a deblocked RDW file
+pub struct RecordReader<'a, 'b, 'c> {
+    iso_spec: &'b IsoSpecs,
+    payload: &'a [u8],
+    offset: usize,
+    capture: Option<(&'c Context, Capture)>,
+}
+
+impl<'a, 'b, 'c> RecordReader<'a, 'b, 'c> {
+    pub fn new(iso_spec: &'b IsoSpecs, payload: &'a [u8]) -> RecordReader<'a, 'b, 'c> {
+        RecordReader {
+            iso_spec,
+            payload,
+            offset: 0,
+            capture: None,
+        }
+    }
+
+    /// Records the offset, length and timing of every record read, timed with the clock
+    /// of `context`
+    pub fn with_capture(mut self, context: &'c Context) -> RecordReader<'a, 'b, 'c> {
+        self.capture = Some((context, Capture::default()));
+        self
+    }
+
+    /// The capture of the records read so far, `None` unless enabled with `with_capture`
+    pub fn capture(&self) -> Option<&Capture> {
+        self.capture.as_ref().map(|(_, capture)| capture)
+    }
+
+    /// Next record along with the message parsed to find its length, for callers such as
+    /// `Pipeline::run` which would otherwise parse it again
+    pub(crate) fn next_message(
+        &mut self,
+    ) -> Option<Result<(Record<'a>, IsoMsg<'a, 'b>), IsoError>> {
+        if self.offset >= self.payload.len() {
+            return None;
+        }
+
+        let arrival = self.capture.as_ref().map(|(context, _)| since_epoch(context));
+        let iso_msg = match IsoMsg::try_new(self.iso_spec, &self.payload[self.offset..]) {
+            Ok(iso_msg) => iso_msg,
+            Err(error) => {
+                // nothing after a malformed record can be located
+                self.offset = self.payload.len();
+                return Some(Err(error));
+            }
+        };
+        let length = iso_msg.length();
+
+        if let (Some((context, capture)), Some(arrival)) = (self.capture.as_mut(), arrival) {
+            capture.records.push(CaptureEntry {
+                offset: self.offset,
+                length,
+                arrival,
+                parse_duration: since_epoch(context).saturating_sub(arrival),
+            });
+        }
+
+        let record = Record {
+            offset: self.offset,
+            payload: &self.payload[self.offset..self.offset + length],
+        };
+        self.offset += length;
+        Some(Ok((record, iso_msg)))
+    }
+}
+
+impl<'a> Iterator for RecordReader<'a, '_, '_> {
+    type Item = Result<Record<'a>, IsoError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_message().map(|read| read.map(|(record, _)| record))
+    }
+}
+
+/// Yields the records of a capture from the original payload, paced as they were captured
+///
+/// A `speed` of 2.0 replays twice as fast as the capture, the first record is yielded
+/// right away and each next one once its scaled arrival time is reached on the clock of
+/// `context`, a fixed context replays on virtual time without blocking. `new` refuses a
+/// speed which isn't positive.
+pub struct Replayer<'a, 'c> {
+    capture: &'a Capture,
+    payload: &'a [u8],
+    context: &'c Context,
+    speed: f64,
+    position: usize,
+    start: Option<Duration>,
+}
+
+impl<'a, 'c> Replayer<'a, 'c> {
+    pub fn new(
+        capture: &'a Capture,
+        payload: &'a [u8],
+        context: &'c Context,
+        speed: f64,
+    ) -> Result<Replayer<'a, 'c>, IsoError> {
+        if speed.is_nan() || speed <= 0.0 {
+            return Err(IsoError::InvalidReplaySpeed);
+        }
+        Ok(Replayer {
+            capture,
+            payload,
+            context,
+            speed,
+            position: 0,
+            start: None,
+        })
+    }
+}
+
+impl<'a> Iterator for Replayer<'a, '_> {
+    type Item = Result<Record<'a>, IsoError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entry = self.capture.records.get(self.position)?;
+        self.position += 1;
+
+        let first_arrival = self.capture.records[0].arrival;
+        let start = *self.start.get_or_insert_with(|| since_epoch(self.context));
+        let due = entry
+            .arrival
+            .saturating_sub(first_arrival)
+            .div_f64(self.speed);
+        let elapsed = since_epoch(self.context).saturating_sub(start);
+        if due > elapsed {
+            self.context.sleep(due - elapsed);
+        }
+
+        let end = entry.offset + entry.length;
+        if end > self.payload.len() {
+            return Some(Err(IsoError::TruncatedFrame {
+                needed: end,
+                available: self.payload.len(),
+            }));
+        }
+        Some(Ok(Record {
+            offset: entry.offset,
+            payload: &self.payload[entry.offset..end],
+        }))
+    }
+}
+
+fn since_epoch(context: &Context) -> Duration {
+    context.now().duration_since(UNIX_EPOCH).unwrap_or_default()
+}
@@ -0,0 +1,55 @@
+use crate::iso_error::IsoError;
+use crate::iso_field::FieldCharType;
+use std::collections::HashMap;
+
+/// Behaviour of a proprietary char type, referenced on the spec as `FieldCharType::Custom(id)`
+#[derive(Debug, Clone, Copy)]
+pub struct CustomCharType {
+    pub name: &'static str,
+    /// position of the first byte refused on a value as found on the payload
+    pub validate: fn(&[u8]) -> Option<usize>,
+    /// turns a user value into its payload representation
+    pub encode: fn(&[u8]) -> Vec<u8>,
+    /// turns a payload value back into the user value, `None` when it can't be decoded
+    pub decode: fn(&[u8]) -> Option<Vec<u8>>,
+}
+
+/// Custom char types known by a spec, keyed by their id
+#[derive(Debug, Clone, Default)]
+pub struct CharTypeRegistry {
+    custom_types: HashMap<u16, CustomCharType>,
+}
+
+impl CharTypeRegistry {
+    /// Registers a custom char type, replacing any type previously registered with `id`
+    pub fn register(&mut self, id: u16, custom_type: CustomCharType) {
+        self.custom_types.insert(id, custom_type);
+    }
+
+    pub fn get(&self, id: u16) -> Option<&CustomCharType> {
+        self.custom_types.get(&id)
+    }
+
+    /// Checks a payload value against the char type, custom types dispatch to their
+    /// registered validation
+    pub fn validate_char_type(
+        &self,
+        index: usize,
+        char_type: &FieldCharType,
+        value: &[u8],
+    ) -> Result<(), IsoError> {
+        let invalid_position = match char_type {
+            FieldCharType::Custom(id) => (self.custom_type(index, *id)?.validate)(value),
+            _ => value.iter().position(|&byte| !char_type.accepts(byte)),
+        };
+
+        match invalid_position {
+            Some(position) => Err(IsoError::InvalidCharacter { index, position }),
+            None => Ok(()),
+        }
+    }
+
+    pub(crate) fn custom_type(&self, index: usize, id: u16) -> Result<&CustomCharType, IsoError> {
+        self.get(id).ok_or(IsoError::UnknownCharType { index, id })
+    }
+}
@@ -0,0 +1,142 @@
+use crate::iso_error::NumericError;
+use crate::numeric;
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const STAN: &str = "stan";
+const FILE_ID: &str = "file_id";
+/// STANs run from 1 to 999999 and wrap around
+const MAX_STAN: u64 = 999_999;
+
+/// Ambient values needed while composing messages, the current time, sequence numbers
+/// such as the STAN and file ids, injected so that `Context::fixed` makes every output
+/// reproducible. It is also the clock timing captures and replays, see `capture`.
+///
+/// `Context::system` needs the `std` feature. The rest of the crate still links std, so
+/// without it `SystemTime` remains the time type, only the system clock is unavailable.
+pub struct Context {
+    now: Option<Cell<SystemTime>>,
+    sequences: RefCell<BTreeMap<String, u64>>,
+}
+
+impl Context {
+    /// Reads the system clock, file ids start at the current unix time in seconds so that
+    /// runs don't reuse them, other sequences start at 1
+    #[cfg(feature = "std")]
+    pub fn system() -> Context {
+        let seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        Context {
+            now: None,
+            sequences: RefCell::new(BTreeMap::from([(FILE_ID.to_string(), seconds)])),
+        }
+    }
+
+    /// Stays at `now` until `advance` is called, every sequence starts at 1
+    pub fn fixed(now: SystemTime) -> Context {
+        Context {
+            now: Some(Cell::new(now)),
+            sequences: RefCell::new(BTreeMap::new()),
+        }
+    }
+
+    /// Same context whose sequence `name` hands out `next` first
+    pub fn with_sequence(self, name: &str, next: u64) -> Context {
+        self.sequences.borrow_mut().insert(name.to_string(), next);
+        self
+    }
+
+    pub fn now(&self) -> SystemTime {
+        match &self.now {
+            Some(now) => now.get(),
+            None => SystemTime::now(),
+        }
+    }
+
+    /// Moves a fixed context forward, the system clock moves on its own
+    pub fn advance(&self, duration: Duration) {
+        if let Some(now) = &self.now {
+            now.set(now.get() + duration);
+        }
+    }
+
+    /// Waits for `duration` on the system clock, a fixed context moves forward instead
+    pub fn sleep(&self, duration: Duration) {
+        match &self.now {
+            Some(now) => now.set(now.get() + duration),
+            None => std::thread::sleep(duration),
+        }
+    }
+
+    /// Next value of the sequence `name`, each name counting on its own from 1
+    pub fn next_sequence(&self, name: &str) -> u64 {
+        let mut sequences = self.sequences.borrow_mut();
+        let next = sequences.entry(name.to_string()).or_insert(1);
+        let value = *next;
+        *next += 1;
+        value
+    }
+
+    /// Next systems trace audit number, from 1 to 999999 then back to 1
+    pub fn next_stan(&self) -> u64 {
+        (self.next_sequence(STAN) - 1) % MAX_STAN + 1
+    }
+
+    pub fn next_file_id(&self) -> u64 {
+        self.next_sequence(FILE_ID)
+    }
+
+    /// `now` in UTC as the MMDDhhmmss of DE 7, transmission date and time
+    pub fn transmission_date_time(&self) -> Result<Vec<u8>, NumericError> {
+        let seconds = self
+            .now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        let (_, month, day) = civil_from_days(seconds / 86_400);
+        let seconds_of_day = seconds % 86_400;
+
+        let mut value = Vec::with_capacity(10);
+        for part in [
+            month,
+            day,
+            seconds_of_day / 3_600,
+            seconds_of_day % 3_600 / 60,
+            seconds_of_day % 60,
+        ] {
+            value.extend_from_slice(&numeric::format_fixed(part, 2)?);
+        }
+        Ok(value)
+    }
+}
+
+/// Year, month and day of the day `days` after 1970-01-01, in the proleptic gregorian
+/// calendar
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    // shifted so that years start in March and end with the leap day
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+#[test]
+fn civil_dates_follow_the_gregorian_calendar() {
+    assert_eq!(civil_from_days(0), (1970, 1, 1));
+    assert_eq!(civil_from_days(59), (1970, 3, 1));
+    assert_eq!(civil_from_days(11_016), (2000, 2, 29));
+    assert_eq!(civil_from_days(19_782), (2024, 2, 29));
+    assert_eq!(civil_from_days(20_743), (2026, 10, 17));
+}
@@ -0,0 +1,86 @@
+use crate::iso_error::ConversionError;
+use crate::numeric::divide_rounded;
+pub use crate::numeric::RoundingMode;
+
+/// Largest amount a 12 digits amount field can hold
+pub const MAX_AMOUNT: u64 = 999_999_999_999;
+
+/// Conversion rate as found on DE 9 and DE 10, the leftmost digit is the amount of decimal
+/// places and the remaining 7 digits are the rate, e.g `69972522` is 9.972522
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConversionRate {
+    pub scale: u32,
+    pub rate: u32,
+    pub rounding: RoundingMode,
+}
+
+impl ConversionRate {
+    pub fn parse(value: &[u8; 8]) -> Result<ConversionRate, ConversionError> {
+        if let Some(position) = value.iter().position(|byte| !byte.is_ascii_digit()) {
+            return Err(ConversionError::InvalidCharacter { position });
+        }
+
+        let scale = u32::from(value[0] - b'0');
+        if scale > 7 {
+            return Err(ConversionError::InvalidScale { scale });
+        }
+        let rate = value[1..]
+            .iter()
+            .fold(0u32, |rate, byte| rate * 10 + u32::from(byte - b'0'));
+        if rate == 0 {
+            return Err(ConversionError::ZeroRate);
+        }
+
+        Ok(ConversionRate {
+            scale,
+            rate,
+            rounding: RoundingMode::default(),
+        })
+    }
+
+    pub fn with_rounding(mut self, rounding: RoundingMode) -> ConversionRate {
+        self.rounding = rounding;
+        self
+    }
+
+    /// Converts an amount in minor units, both currencies are expected to share the same
+    /// amount of decimal places
+    pub fn apply(&self, amount_minor: u64) -> u64 {
+        let product = u128::from(amount_minor) * u128::from(self.rate);
+        let converted = divide_rounded(product, 10u128.pow(self.scale), self.rounding);
+
+        // a 12 digits amount times a 7 digits rate always fits in 64 bits
+        converted as u64
+    }
+}
+
+#[test]
+fn test_conversion_rate_parse_and_apply() {
+    let rate = ConversionRate::parse(b"69972522").unwrap();
+    assert_eq!((rate.scale, rate.rate), (6, 9972522));
+    // 15.00 at 9.972522 is 149.58783
+    assert_eq!(rate.apply(1500), 14959);
+
+    assert_eq!(
+        ConversionRate::parse(b"00000000"),
+        Err(ConversionError::ZeroRate)
+    );
+    assert_eq!(
+        ConversionRate::parse(b"91000000"),
+        Err(ConversionError::InvalidScale { scale: 9 })
+    );
+    assert_eq!(
+        ConversionRate::parse(b"6997252A"),
+        Err(ConversionError::InvalidCharacter { position: 7 })
+    );
+}
+
+#[test]
+fn test_conversion_rate_rounding() {
+    // 0.5
+    let half = ConversionRate::parse(b"10000005").unwrap();
+    assert_eq!(half.apply(3), 2);
+    assert_eq!(half.apply(5), 2);
+    assert_eq!(half.with_rounding(RoundingMode::HalfUp).apply(5), 3);
+    assert_eq!(half.with_rounding(RoundingMode::Down).apply(3), 1);
+}
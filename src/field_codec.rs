@@ -0,0 +1,258 @@
+use crate::iso_error::CodecError;
+use crate::numeric;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str;
+use std::sync::Arc;
+
+/// Structured value of a composite field, as produced by a `FieldCodec`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum FieldValue {
+    Text(String),
+    List(Vec<String>),
+    SubFields(BTreeMap<String, String>),
+    /// value of a field without codec, as found on the payload
+    Bytes(Vec<u8>),
+}
+
+/// Turns the value of a composite field into a `FieldValue` and back, registered per field
+/// on `IsoSpecs::field_codecs`
+pub trait FieldCodec: Send + Sync {
+    fn decode(&self, value: &[u8]) -> Result<FieldValue, CodecError>;
+    fn encode(&self, value: &FieldValue) -> Result<Vec<u8>, CodecError>;
+}
+
+/// Codecs used by a spec, keyed by field index
+#[derive(Clone, Default)]
+pub struct FieldCodecs {
+    codecs: HashMap<usize, Arc<dyn FieldCodec>>,
+}
+
+impl fmt::Debug for FieldCodecs {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut indexes: Vec<&usize> = self.codecs.keys().collect();
+        indexes.sort();
+        f.debug_struct("FieldCodecs")
+            .field("indexes", &indexes)
+            .finish()
+    }
+}
+
+impl FieldCodecs {
+    /// Registers the codec of a field, replacing any codec previously registered for `index`
+    pub fn register<C: FieldCodec + 'static>(&mut self, index: usize, codec: C) {
+        self.codecs.insert(index, Arc::new(codec));
+    }
+
+    pub fn get(&self, index: usize) -> Option<&dyn FieldCodec> {
+        self.codecs.get(&index).map(|codec| codec.as_ref())
+    }
+}
+
+fn utf8(value: &[u8]) -> Result<&str, CodecError> {
+    str::from_utf8(value).map_err(|error| CodecError::Utf8 {
+        position: error.valid_up_to(),
+    })
+}
+
+/// Sub-field of a `SubFieldTable` layout
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubField {
+    pub name: String,
+    pub length: usize,
+}
+
+impl SubField {
+    pub fn new(name: &str, length: usize) -> SubField {
+        SubField {
+            name: name.to_string(),
+            length,
+        }
+    }
+}
+
+/// Fixed length sub-fields laid out back to back, decoded as `FieldValue::SubFields`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubFieldTable {
+    pub layout: Vec<SubField>,
+}
+
+impl SubFieldTable {
+    pub fn new(layout: Vec<SubField>) -> SubFieldTable {
+        SubFieldTable { layout }
+    }
+}
+
+impl FieldCodec for SubFieldTable {
+    fn decode(&self, value: &[u8]) -> Result<FieldValue, CodecError> {
+        let expected = self.layout.iter().map(|sub_field| sub_field.length).sum();
+        if value.len() != expected {
+            return Err(CodecError::InvalidLength {
+                expected,
+                got: value.len(),
+            });
+        }
+
+        let mut sub_fields = BTreeMap::new();
+        let mut offset = 0;
+        for sub_field in self.layout.iter() {
+            let text = utf8(&value[offset..offset + sub_field.length])?;
+            sub_fields.insert(sub_field.name.clone(), text.to_string());
+            offset += sub_field.length;
+        }
+        Ok(FieldValue::SubFields(sub_fields))
+    }
+
+    fn encode(&self, value: &FieldValue) -> Result<Vec<u8>, CodecError> {
+        let sub_fields = match value {
+            FieldValue::SubFields(sub_fields) => sub_fields,
+            _ => return Err(CodecError::UnexpectedValue),
+        };
+        if let Some(name) = sub_fields
+            .keys()
+            .find(|name| !self.layout.iter().any(|sub_field| &sub_field.name == *name))
+        {
+            return Err(CodecError::UnknownSubField { name: name.clone() });
+        }
+
+        let mut encoded = vec![];
+        for sub_field in self.layout.iter() {
+            let text =
+                sub_fields
+                    .get(&sub_field.name)
+                    .ok_or_else(|| CodecError::MissingSubField {
+                        name: sub_field.name.clone(),
+                    })?;
+            if text.len() != sub_field.length {
+                return Err(CodecError::SubFieldLength {
+                    name: sub_field.name.clone(),
+                    expected: sub_field.length,
+                    got: text.len(),
+                });
+            }
+            encoded.extend_from_slice(text.as_bytes());
+        }
+        Ok(encoded)
+    }
+}
+
+/// Items separated by a delimiter byte, decoded as `FieldValue::List`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DelimitedList {
+    pub delimiter: u8,
+}
+
+impl DelimitedList {
+    pub fn new(delimiter: u8) -> DelimitedList {
+        DelimitedList { delimiter }
+    }
+}
+
+impl FieldCodec for DelimitedList {
+    fn decode(&self, value: &[u8]) -> Result<FieldValue, CodecError> {
+        if value.is_empty() {
+            return Ok(FieldValue::List(vec![]));
+        }
+        let text = utf8(value)?;
+        let items = text
+            .split(char::from(self.delimiter))
+            .map(str::to_string)
+            .collect();
+        Ok(FieldValue::List(items))
+    }
+
+    fn encode(&self, value: &FieldValue) -> Result<Vec<u8>, CodecError> {
+        let items = match value {
+            FieldValue::List(items) => items,
+            _ => return Err(CodecError::UnexpectedValue),
+        };
+        if let Some(item) = items
+            .iter()
+            .find(|item| item.as_bytes().contains(&self.delimiter))
+        {
+            return Err(CodecError::ContainsDelimiter { item: item.clone() });
+        }
+        Ok(items
+            .join(&char::from(self.delimiter).to_string())
+            .into_bytes())
+    }
+}
+
+/// Sub-fields each carrying a two digits length prefix, laid out back to back in layout
+/// order and decoded as `FieldValue::SubFields`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LlVarSubFields {
+    pub names: Vec<String>,
+}
+
+impl LlVarSubFields {
+    pub fn new(names: &[&str]) -> LlVarSubFields {
+        LlVarSubFields {
+            names: names.iter().map(|name| name.to_string()).collect(),
+        }
+    }
+}
+
+impl FieldCodec for LlVarSubFields {
+    fn decode(&self, value: &[u8]) -> Result<FieldValue, CodecError> {
+        let text = utf8(value)?;
+        let mut sub_fields = BTreeMap::new();
+        let mut offset = 0;
+        for name in self.names.iter() {
+            let prefix = text
+                .get(offset..offset + 2)
+                .ok_or_else(|| CodecError::MissingSubField { name: name.clone() })?;
+            let length = prefix.parse::<usize>().map_err(|_| CodecError::Invalid {
+                reason: format!("sub-field {} has the length prefix {:?}", name, prefix),
+            })?;
+            let sub_value = text.get(offset + 2..offset + 2 + length).ok_or_else(|| {
+                CodecError::InvalidLength {
+                    expected: offset + 2 + length,
+                    got: value.len(),
+                }
+            })?;
+            sub_fields.insert(name.clone(), sub_value.to_string());
+            offset += 2 + length;
+        }
+        if offset != value.len() {
+            return Err(CodecError::InvalidLength {
+                expected: offset,
+                got: value.len(),
+            });
+        }
+        Ok(FieldValue::SubFields(sub_fields))
+    }
+
+    fn encode(&self, value: &FieldValue) -> Result<Vec<u8>, CodecError> {
+        let sub_fields = match value {
+            FieldValue::SubFields(sub_fields) => sub_fields,
+            _ => return Err(CodecError::UnexpectedValue),
+        };
+        if let Some(name) = sub_fields.keys().find(|name| !self.names.contains(name)) {
+            return Err(CodecError::UnknownSubField { name: name.clone() });
+        }
+
+        let mut encoded = vec![];
+        for name in self.names.iter() {
+            let text = sub_fields
+                .get(name)
+                .ok_or_else(|| CodecError::MissingSubField { name: name.clone() })?;
+            if text.len() > 99 {
+                return Err(CodecError::SubFieldLength {
+                    name: name.clone(),
+                    expected: 99,
+                    got: text.len(),
+                });
+            }
+            let length = numeric::format_fixed(text.len() as u64, 2).map_err(|error| {
+                CodecError::Invalid {
+                    reason: error.to_string(),
+                }
+            })?;
+            encoded.extend_from_slice(&length);
+            encoded.extend_from_slice(text.as_bytes());
+        }
+        Ok(encoded)
+    }
+}
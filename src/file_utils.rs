@@ -1,8 +1,97 @@
+use crate::iso_error::IsoError;
+use crate::iso_specs::IsoSpecs;
 use eyre::{eyre, Result};
 use std::convert::TryFrom;
 use std::fs::File;
 use std::io::Read;
 
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// How records are laid out inside a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Framing {
+    /// Records prefixed by a 4 bytes RDW, possibly split into 1014 bytes blocks (IPM files)
+    Rdw,
+    /// One record per line, a record ends with any of the terminators and is written with
+    /// the first one
+    LineDelimited { terminators: Vec<Vec<u8>> },
+}
+
+impl Framing {
+    /// Line delimited framing accepting both `\r\n` and `\n`, writing `\n`
+    pub fn lines() -> Framing {
+        Framing::LineDelimited {
+            terminators: vec![b"\n".to_vec(), b"\r\n".to_vec()],
+        }
+    }
+}
+
+/// Removes the UTF-8 BOM some processors put in front of the first record
+pub fn strip_bom(payload: &[u8]) -> &[u8] {
+    payload.strip_prefix(UTF8_BOM).unwrap_or(payload)
+}
+
+/// Length of the line terminator found at the start of `payload`, the longest match wins so
+/// `\r\n` is not read as a lone `\r`
+pub fn terminator_length(payload: &[u8], terminators: &[Vec<u8>]) -> Option<usize> {
+    terminators
+        .iter()
+        .filter(|terminator| !terminator.is_empty() && payload.starts_with(terminator))
+        .map(|terminator| terminator.len())
+        .max()
+}
+
+/// Joins serialized records into a file payload following the framing
+pub fn frame_records(records: &[Vec<u8>], framing: &Framing) -> Vec<u8> {
+    let mut payload = vec![];
+
+    for record in records {
+        match framing {
+            Framing::Rdw => {
+                payload.extend_from_slice(&(record.len() as u32).to_be_bytes());
+                payload.extend_from_slice(record);
+            }
+            Framing::LineDelimited { terminators } => {
+                payload.extend_from_slice(record);
+                if let Some(terminator) = terminators.first() {
+                    payload.extend_from_slice(terminator);
+                }
+            }
+        }
+    }
+
+    payload
+}
+
+/// Same as `frame_records`, padding each record with spaces up to the fixed record length
+/// of the spec when it has one
+pub fn frame_records_for(
+    spec: &IsoSpecs,
+    records: &[Vec<u8>],
+    framing: &Framing,
+) -> Result<Vec<u8>, IsoError> {
+    let record_len = match spec.fixed_record_len() {
+        Some(record_len) => record_len,
+        None => return Ok(frame_records(records, framing)),
+    };
+
+    let mut padded = Vec::with_capacity(records.len());
+    for (record, encoded) in records.iter().enumerate() {
+        if encoded.len() > record_len {
+            return Err(IsoError::RecordTooLong {
+                record,
+                max: record_len,
+                got: encoded.len(),
+            });
+        }
+        let mut encoded = encoded.clone();
+        encoded.resize(record_len, b' ');
+        padded.push(encoded);
+    }
+
+    Ok(frame_records(&padded, framing))
+}
+
 /// Receives a filename string and returns the payload vec
 pub fn read_file(file_name: &str) -> Vec<u8> {
     let mut file = File::open(file_name).expect("no file found");
@@ -10,7 +99,7 @@ pub fn read_file(file_name: &str) -> Vec<u8> {
 
     let mut payload = vec![0; metadata.len() as usize];
 
-    file.read(&mut payload).expect("buffer overflow");
+    file.read_exact(&mut payload).expect("buffer overflow");
 
     payload.to_vec()
 }
@@ -48,7 +137,7 @@ pub fn deblock_and_remove_rdw_from(payload: Vec<u8>) -> Result<Vec<u8>> {
     Ok(new_payload)
 }
 
-fn remove_blocking_chunks<'a>(payload: Vec<u8>) -> Vec<u8> {
+fn remove_blocking_chunks(payload: Vec<u8>) -> Vec<u8> {
     // removing @@ signs (1024 blockings)
     let mut deblocked_payload: Vec<u8> = vec![];
     let trailing_block_size = payload
@@ -63,8 +152,8 @@ fn remove_blocking_chunks<'a>(payload: Vec<u8>) -> Vec<u8> {
     let payload_in_chunks = payload.chunks(2).enumerate();
 
     for (pos, two_bytes) in payload_in_chunks {
-        let is_not_a_zero_block = !(pos > 0 && (pos % 507 == 506) && two_bytes == &[0u8, 0u8]);
-        let is_not_a_40_block = !(pos > 0 && (pos % 507 == 506) && two_bytes == &[b'@', b'@']);
+        let is_not_a_zero_block = !(pos > 0 && (pos % 507 == 506) && two_bytes == [0u8, 0u8]);
+        let is_not_a_40_block = !(pos > 0 && (pos % 507 == 506) && two_bytes == b"@@");
         let is_not_a_trailing_block = trailing_block_position >= pos * 2;
 
         if is_not_a_40_block && is_not_a_trailing_block && is_not_a_zero_block {
@@ -102,7 +191,7 @@ fn rdw_to_size(raw_rdw_buffer: &[u8], position: usize) -> Option<usize> {
         .map(|(index, rdw_number)| -> u64 {
             let index_translation = i8::abs(i8::try_from(index).unwrap() - 3i8);
             let index_power: u64 = 256u64.pow(u32::try_from(index_translation).unwrap());
-            u64::try_from(*rdw_number).unwrap() * index_power
+            u64::from(*rdw_number) * index_power
         })
         .sum();
 
@@ -0,0 +1,270 @@
+use crate::alloc_budget::AllocBudget;
+use crate::iso_error::IsoError;
+use crate::numeric;
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
+
+/// Length header placed in front of each message when several messages share one buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LengthHeader {
+    /// 2 bytes big endian binary length, common on TCP links
+    Binary2,
+    /// 2 bytes little endian binary length
+    Binary2Le,
+    /// 4 bytes big endian binary length, the layout of an IPM RDW
+    Binary4,
+    /// 4 ascii digits, e.g `0042`
+    Ascii4,
+}
+
+impl LengthHeader {
+    /// How many bytes the header takes
+    pub fn header_len(&self) -> usize {
+        match self {
+            LengthHeader::Binary2 | LengthHeader::Binary2Le => 2,
+            LengthHeader::Binary4 | LengthHeader::Ascii4 => 4,
+        }
+    }
+
+    /// The largest message length the header can describe
+    pub fn max_len(&self) -> usize {
+        match self {
+            LengthHeader::Binary2 | LengthHeader::Binary2Le => u16::MAX as usize,
+            LengthHeader::Binary4 => u32::MAX as usize,
+            LengthHeader::Ascii4 => 9999,
+        }
+    }
+
+    pub fn encode(&self, len: usize) -> Result<Vec<u8>, IsoError> {
+        if len > self.max_len() {
+            return Err(IsoError::MessageTooLong {
+                max: self.max_len(),
+                got: len,
+            });
+        }
+
+        Ok(match self {
+            LengthHeader::Binary2 => (len as u16).to_be_bytes().to_vec(),
+            LengthHeader::Binary2Le => (len as u16).to_le_bytes().to_vec(),
+            LengthHeader::Binary4 => (len as u32).to_be_bytes().to_vec(),
+            LengthHeader::Ascii4 => numeric::format_fixed(len as u64, 4)?,
+        })
+    }
+
+    /// Reads the message length from the start of `payload`, `None` when the header is
+    /// incomplete or not made of digits
+    pub fn decode(&self, payload: &[u8]) -> Option<usize> {
+        let header = payload.get(..self.header_len())?;
+
+        match self {
+            LengthHeader::Binary2 | LengthHeader::Binary4 => Some(
+                header
+                    .iter()
+                    .fold(0usize, |len, &byte| (len << 8) | usize::from(byte)),
+            ),
+            LengthHeader::Binary2Le => {
+                Some(usize::from(u16::from_le_bytes([header[0], header[1]])))
+            }
+            LengthHeader::Ascii4 => std::str::from_utf8(header).ok()?.parse().ok(),
+        }
+    }
+}
+
+/// Reads one message preceded by its length header, e.g. from a socket, within the
+/// default `AllocBudget`
+pub fn read_framed<R: Read>(reader: &mut R, header: LengthHeader) -> io::Result<Vec<u8>> {
+    read_framed_with(reader, header, &AllocBudget::default())
+}
+
+/// Same as `read_framed`, a header announcing more than `budget` allows fails with
+/// `InvalidData` wrapping `IsoError::AllocationBudgetExceeded` before the frame is allocated
+pub fn read_framed_with<R: Read>(
+    reader: &mut R,
+    header: LengthHeader,
+    budget: &AllocBudget,
+) -> io::Result<Vec<u8>> {
+    let mut header_bytes = vec![0u8; header.header_len()];
+    reader.read_exact(&mut header_bytes)?;
+    let len = header.decode(&header_bytes).ok_or_else(|| {
+        let message = format!("invalid length header {:02X?}", header_bytes);
+        io::Error::new(io::ErrorKind::InvalidData, message)
+    })?;
+    check_frame_len(len, budget)?;
+
+    let mut payload = vec![0u8; len];
+    reader.read_exact(&mut payload)?;
+    Ok(payload)
+}
+
+/// Same as `read_framed`, failing with `TimedOut` when the whole frame doesn't arrive
+/// within `timeout`, within the default `AllocBudget`
+///
+/// The deadline is checked between reads, a blocking reader such as a `TcpStream` needs its
+/// own read timeout, shorter than `timeout`, for a stalled peer to be noticed.
+pub fn read_message_timeout<R: Read>(
+    reader: &mut R,
+    header: LengthHeader,
+    timeout: Duration,
+) -> io::Result<Vec<u8>> {
+    read_message_timeout_with(reader, header, timeout, &AllocBudget::default())
+}
+
+/// Same as `read_message_timeout` within `budget`, checked like `read_framed_with`
+pub fn read_message_timeout_with<R: Read>(
+    reader: &mut R,
+    header: LengthHeader,
+    timeout: Duration,
+    budget: &AllocBudget,
+) -> io::Result<Vec<u8>> {
+    let deadline = Instant::now() + timeout;
+
+    let mut header_bytes = vec![0u8; header.header_len()];
+    read_exact_until(reader, &mut header_bytes, deadline)?;
+    let len = header.decode(&header_bytes).ok_or_else(|| {
+        let message = format!("invalid length header {:02X?}", header_bytes);
+        io::Error::new(io::ErrorKind::InvalidData, message)
+    })?;
+    check_frame_len(len, budget)?;
+
+    let mut payload = vec![0u8; len];
+    read_exact_until(reader, &mut payload, deadline)?;
+    Ok(payload)
+}
+
+fn check_frame_len(len: usize, budget: &AllocBudget) -> io::Result<()> {
+    budget
+        .check(len, "frame")
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+/// `read_exact` giving up once `deadline` is passed, reader timeouts are retried until then
+fn read_exact_until<R: Read>(
+    reader: &mut R,
+    buffer: &mut [u8],
+    deadline: Instant,
+) -> io::Result<()> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]) {
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(len) => filled += len,
+            Err(error)
+                if matches!(
+                    error.kind(),
+                    io::ErrorKind::Interrupted
+                        | io::ErrorKind::WouldBlock
+                        | io::ErrorKind::TimedOut
+                ) => {}
+            Err(error) => return Err(error),
+        }
+
+        if Instant::now() > deadline {
+            let message = format!(
+                "{} of {} bytes read before the deadline",
+                filled,
+                buffer.len()
+            );
+            return Err(io::Error::new(io::ErrorKind::TimedOut, message));
+        }
+    }
+    Ok(())
+}
+
+/// Writes one message preceded by its length header
+pub fn write_framed<W: Write>(
+    writer: &mut W,
+    header: LengthHeader,
+    payload: &[u8],
+) -> io::Result<()> {
+    let header_bytes = header
+        .encode(payload.len())
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))?;
+    writer.write_all(&header_bytes)?;
+    writer.write_all(payload)
+}
+
+#[test]
+fn test_length_header_round_trip() {
+    for header in [
+        LengthHeader::Binary2,
+        LengthHeader::Binary2Le,
+        LengthHeader::Binary4,
+        LengthHeader::Ascii4,
+    ] {
+        let encoded = header.encode(258).unwrap();
+        assert_eq!(encoded.len(), header.header_len());
+        assert_eq!(header.decode(&encoded), Some(258));
+    }
+
+    assert_eq!(LengthHeader::Binary2.encode(258).unwrap(), vec![1, 2]);
+    assert_eq!(LengthHeader::Binary2Le.encode(258).unwrap(), vec![2, 1]);
+    assert_eq!(LengthHeader::Ascii4.encode(258).unwrap(), b"0258".to_vec());
+    assert_eq!(
+        LengthHeader::Ascii4.encode(10000),
+        Err(IsoError::MessageTooLong {
+            max: 9999,
+            got: 10000
+        })
+    );
+    assert_eq!(LengthHeader::Ascii4.decode(b"02"), None);
+}
+
+#[test]
+fn test_framed_round_trip() {
+    let mut stream = vec![];
+    write_framed(&mut stream, LengthHeader::Binary2Le, b"0100 first").unwrap();
+    write_framed(&mut stream, LengthHeader::Binary2Le, b"0110").unwrap();
+    assert_eq!(&stream[..2], &[10, 0]);
+
+    let mut reader = io::Cursor::new(stream);
+    let first = read_framed(&mut reader, LengthHeader::Binary2Le).unwrap();
+    let second = read_framed(&mut reader, LengthHeader::Binary2Le).unwrap();
+    assert_eq!(
+        (first.as_slice(), second.as_slice()),
+        (&b"0100 first"[..], &b"0110"[..])
+    );
+
+    let error = read_framed(&mut reader, LengthHeader::Binary2Le).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+
+    let mut reader = io::Cursor::new(b"00X4abcd".to_vec());
+    let error = read_framed(&mut reader, LengthHeader::Ascii4).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_read_message_timeout() {
+    /// Delivers the header right away and the body after `delay`
+    struct SlowReader {
+        chunks: Vec<Vec<u8>>,
+        delay: Duration,
+    }
+
+    impl Read for SlowReader {
+        fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+            if self.chunks.is_empty() {
+                return Ok(0);
+            }
+            if self.chunks.len() == 1 {
+                std::thread::sleep(self.delay);
+            }
+            let chunk = self.chunks.remove(0);
+            buffer[..chunk.len()].copy_from_slice(&chunk);
+            Ok(chunk.len())
+        }
+    }
+
+    let slow_reader = |delay| SlowReader {
+        chunks: vec![b"0004".to_vec(), b"0110".to_vec()],
+        delay,
+    };
+
+    let mut reader = slow_reader(Duration::ZERO);
+    let payload = read_message_timeout(&mut reader, LengthHeader::Ascii4, Duration::from_secs(5));
+    assert_eq!(payload.unwrap(), b"0110".to_vec());
+
+    let mut reader = slow_reader(Duration::from_millis(50));
+    let timeout = Duration::from_millis(10);
+    let error = read_message_timeout(&mut reader, LengthHeader::Ascii4, timeout).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+}
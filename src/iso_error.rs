@@ -0,0 +1,566 @@
+use std::fmt;
+
+/// Errors raised while reading, validating or writing an `IsoMsg`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IsoError {
+    /// A fixed field holds a value whose length differs from the spec
+    InvalidLength {
+        index: usize,
+        expected: usize,
+        got: usize,
+    },
+    /// A variable field holds a value longer than the spec maximum
+    ValueTooLong {
+        index: usize,
+        max: usize,
+        got: usize,
+    },
+    /// A field holds a byte which is not allowed by its char type
+    InvalidCharacter { index: usize, position: usize },
+    /// A hex bitmap holds a byte which is not a hexadecimal digit, `position` counts from
+    /// the start of the bitmaps
+    InvalidBitmap { position: usize },
+    /// Fewer bytes than the bitmaps they announce, a primary bitmap takes 8 bytes
+    TruncatedBitmap { needed: usize, available: usize },
+    /// The field is not present on the message
+    FieldNotSet { index: usize },
+    /// The index is not part of the spec or is the bitmap, which is generated
+    InvalidField { index: usize },
+    /// Fields up to the bitmap are always serialized and can't be removed
+    PositionalField { index: usize },
+    /// A `FieldValidator` or a custom char type refused the value
+    Rejected { index: usize, reason: String },
+    /// The field uses a custom char type missing from the spec registry
+    UnknownCharType { index: usize, id: u16 },
+    /// The payload ends before the end of a field
+    UnexpectedEof {
+        field_index: usize,
+        needed: usize,
+        available: usize,
+    },
+    /// A variable length prefix is truncated, holds a non digit or exceeds the spec maximum
+    ///
+    /// Deviates from the `{ index, raw }` shape once asked for: `IsoMsg::get_field_length`
+    /// is given an `IsoField` without its position in the spec, so the field is named by
+    /// `label_id` and `prefix` holds the raw prefix bytes.
+    InvalidLengthPrefix { label_id: String, prefix: Vec<u8> },
+    /// A variable field length prefix announces a length other than the value it holds
+    PrefixMismatch {
+        index: usize,
+        announced: usize,
+        got: usize,
+    },
+    /// A message does not fit in its length header
+    MessageTooLong { max: usize, got: usize },
+    /// The output buffer can't hold everything to be written
+    BufferTooSmall { needed: usize, available: usize },
+    /// The input ends before the frame announced by its length header
+    TruncatedFrame { needed: usize, available: usize },
+    /// A `Replayer` speed which is zero, negative or NaN
+    InvalidReplaySpeed,
+    /// The amounts or the conversion rate of the message can't be used
+    Conversion(ConversionError),
+    /// A text accessor was used on a binary field, as opposed to `Utf8` for a text field
+    /// holding corrupted bytes
+    NotTextField { index: usize },
+    /// A text field holds bytes which are not valid UTF-8
+    Utf8 {
+        index: usize,
+        error: std::str::Utf8Error,
+    },
+    /// A number can't be written in or read from its fixed amount of digits
+    Numeric(NumericError),
+    /// The codec registered for the field can't decode or encode its value
+    Codec { index: usize, error: CodecError },
+    /// A track 2 field doesn't follow the track grammar
+    Track2 { index: usize, error: Track2Error },
+    /// A patch can only carry present fields, not the removal of a field of its base
+    PatchRemovesField { index: usize },
+    /// The file was required to end with a trailer, its last record is not one
+    MissingTrailer { records: usize },
+    /// The file was read with a policy forbidding trailers and holds one
+    UnexpectedTrailer { record: usize },
+    /// A field disagrees with the field it repeats, e.g. the PAN of a DE 35 track differs
+    /// from DE 2
+    LinkedFieldMismatch { index: usize, linked: usize },
+    /// Two messages can't be compared field by field, their specs differ in field count
+    SpecMismatch { fields: usize, other_fields: usize },
+    /// No spec is registered for the version digit of the mti
+    UnknownMtiVersion { version: u8 },
+    /// A record doesn't fit in the fixed record length of the spec
+    RecordTooLong { record: usize, max: usize, got: usize },
+    /// A `CompositeHandle` is flushed over a field set since it was parsed
+    StaleComposite { index: usize },
+    /// DE 0 and DE 1 stand for the mti and the bitmap, which are not data fields
+    ReservedDataElement { de: u8 },
+    /// The spec has no field for the data element
+    UnknownDataElement { de: u8 },
+    /// A size read from the input asks for more memory than the `AllocBudget` allows
+    AllocationBudgetExceeded {
+        requested: usize,
+        limit: usize,
+        context: &'static str,
+    },
+    /// No field of the spec is named after the label
+    UnknownLabel { label: String },
+    /// Several fields of the spec share the label, `indexes` lists them
+    AmbiguousLabel { label: String, indexes: Vec<usize> },
+}
+
+impl fmt::Display for IsoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IsoError::InvalidLength {
+                index,
+                expected,
+                got,
+            } => write!(
+                f,
+                "field {} should be {} bytes long but is {} bytes long",
+                index, expected, got
+            ),
+            IsoError::ValueTooLong { index, max, got } => write!(
+                f,
+                "field {} accepts at most {} bytes but got {} bytes",
+                index, max, got
+            ),
+            IsoError::InvalidCharacter { index, position } => write!(
+                f,
+                "field {} has an invalid character at position {}",
+                index, position
+            ),
+            IsoError::InvalidBitmap { position } => {
+                write!(f, "bitmap has an invalid character at position {}", position)
+            }
+            IsoError::TruncatedBitmap { needed, available } => write!(
+                f,
+                "bitmaps need {} bytes but only {} bytes are given",
+                needed, available
+            ),
+            IsoError::FieldNotSet { index } => write!(f, "field {} is not set", index),
+            IsoError::InvalidField { index } => write!(f, "field {} can't be accessed", index),
+            IsoError::PositionalField { index } => {
+                write!(f, "field {} is positional and can't be removed", index)
+            }
+            IsoError::Rejected { index, reason } => {
+                write!(f, "field {} was rejected: {}", index, reason)
+            }
+            IsoError::UnknownCharType { index, id } => {
+                write!(f, "field {} uses the unregistered char type {}", index, id)
+            }
+            IsoError::UnexpectedEof {
+                field_index,
+                needed,
+                available,
+            } => write!(
+                f,
+                "field {} ends at byte {} but the payload only has {} bytes",
+                field_index, needed, available
+            ),
+            IsoError::InvalidLengthPrefix { label_id, prefix } => write!(
+                f,
+                "field {} has an invalid length prefix {:02X?}",
+                label_id, prefix
+            ),
+            IsoError::MessageTooLong { max, got } => write!(
+                f,
+                "message is {} bytes long but the length header allows {} bytes",
+                got, max
+            ),
+            IsoError::PrefixMismatch {
+                index,
+                announced,
+                got,
+            } => write!(
+                f,
+                "field {} length prefix announces {} bytes but the value has {}",
+                index, announced, got
+            ),
+            IsoError::BufferTooSmall { needed, available } => write!(
+                f,
+                "buffer holds {} bytes but {} bytes are needed",
+                available, needed
+            ),
+            IsoError::TruncatedFrame { needed, available } => write!(
+                f,
+                "frame needs {} bytes but only {} bytes are left",
+                needed, available
+            ),
+            IsoError::InvalidReplaySpeed => write!(f, "replay speed must be positive"),
+            IsoError::Conversion(error) => write!(f, "{}", error),
+            IsoError::NotTextField { index } => write!(
+                f,
+                "field {} is a binary field, read it with get_field_slice",
+                index
+            ),
+            IsoError::Utf8 { index, error } => {
+                write!(f, "field {} is not valid utf-8: {}", index, error)
+            }
+            IsoError::Numeric(error) => write!(f, "{}", error),
+            IsoError::Codec { index, error } => write!(f, "field {}: {}", index, error),
+            IsoError::Track2 { index, error } => write!(f, "field {}: {}", index, error),
+            IsoError::PatchRemovesField { index } => write!(
+                f,
+                "field {} was removed from the base message, a patch can't carry removals",
+                index
+            ),
+            IsoError::MissingTrailer { records } => write!(
+                f,
+                "file ends after {} records without a trailer, it may be truncated",
+                records
+            ),
+            IsoError::UnexpectedTrailer { record } => {
+                write!(f, "record {} is a trailer but trailers are forbidden", record)
+            }
+            IsoError::LinkedFieldMismatch { index, linked } => {
+                write!(f, "field {} disagrees with field {}", index, linked)
+            }
+            IsoError::SpecMismatch {
+                fields,
+                other_fields,
+            } => write!(
+                f,
+                "specs of {} and {} fields can't be compared",
+                fields, other_fields
+            ),
+            IsoError::UnknownMtiVersion { version } => {
+                write!(f, "no spec is registered for mti version {}", version)
+            }
+            IsoError::RecordTooLong { record, max, got } => write!(
+                f,
+                "record {} is {} bytes long but records are {} bytes long",
+                record, got, max
+            ),
+            IsoError::StaleComposite { index } => write!(
+                f,
+                "field {} was set since its sub-elements were parsed, parse it again",
+                index
+            ),
+            IsoError::ReservedDataElement { de } => write!(
+                f,
+                "data element {} is the {}, not a data field",
+                de,
+                if *de == 0 { "mti" } else { "bitmap" }
+            ),
+            IsoError::UnknownDataElement { de } => {
+                write!(f, "data element {} is not part of the spec", de)
+            }
+            IsoError::AllocationBudgetExceeded {
+                requested,
+                limit,
+                context,
+            } => write!(
+                f,
+                "{} needs {} bytes but the allocation budget allows {} bytes",
+                context, requested, limit
+            ),
+            IsoError::UnknownLabel { label } => write!(f, "no field is labelled {:?}", label),
+            IsoError::AmbiguousLabel { label, indexes } => write!(
+                f,
+                "fields {:?} are all labelled {:?}, access them by index",
+                indexes, label
+            ),
+        }
+    }
+}
+
+impl std::error::Error for IsoError {}
+
+impl From<ConversionError> for IsoError {
+    fn from(error: ConversionError) -> Self {
+        IsoError::Conversion(error)
+    }
+}
+
+impl From<NumericError> for IsoError {
+    fn from(error: NumericError) -> Self {
+        IsoError::Numeric(error)
+    }
+}
+
+/// What is wrong with a track 2
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Track2Violation {
+    /// The content exceeds 37 characters
+    TooLong,
+    /// Neither `=` nor `D` separates the PAN from the rest of the track
+    MissingSeparator,
+    ExtraSeparator,
+    MissingPan,
+    /// The PAN exceeds 19 digits
+    PanTooLong,
+    NonDigit,
+    /// The expiry date or service code is cut short
+    Truncated,
+}
+
+/// A track 2 grammar violation along with the position of the offending character
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Track2Error {
+    pub position: usize,
+    pub violation: Track2Violation,
+}
+
+impl Track2Error {
+    pub fn new(position: usize, violation: Track2Violation) -> Track2Error {
+        Track2Error {
+            position,
+            violation,
+        }
+    }
+}
+
+impl fmt::Display for Track2Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let violation = match self.violation {
+            Track2Violation::TooLong => "track is longer than 37 characters",
+            Track2Violation::MissingSeparator => "track has no separator",
+            Track2Violation::ExtraSeparator => "track has more than one separator",
+            Track2Violation::MissingPan => "track has no PAN",
+            Track2Violation::PanTooLong => "track PAN is longer than 19 digits",
+            Track2Violation::NonDigit => "track holds a non digit",
+            Track2Violation::Truncated => "track expiry date or service code is incomplete",
+        };
+        write!(f, "{} at position {}", violation, self.position)
+    }
+}
+
+impl std::error::Error for Track2Error {}
+
+/// Errors raised while writing or reading a number of fixed width
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NumericError {
+    /// The value has more digits than the width
+    TooWide { value: u64, width: usize },
+    /// A byte which is not an ascii digit
+    InvalidDigit { position: usize },
+    /// The digits describe a number above `u64::MAX`, or `i64::MAX` for a signed amount
+    Overflow,
+    Empty,
+    /// A negative amount for a field which can't carry a sign
+    Negative { value: i64 },
+    /// A packed BCD nibble above 9 where a digit is expected, positions count nibbles
+    InvalidNibble { position: usize, nibble: u8 },
+    /// A packed BCD sign nibble which is neither the positive nor the negative one
+    InvalidSign { position: usize, nibble: u8 },
+}
+
+impl fmt::Display for NumericError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NumericError::TooWide { value, width } => {
+                write!(f, "{} does not fit in {} digits", value, width)
+            }
+            NumericError::InvalidDigit { position } => {
+                write!(f, "number has a non digit at position {}", position)
+            }
+            NumericError::Overflow => write!(f, "number does not fit in 64 bits"),
+            NumericError::Empty => write!(f, "number has no digits"),
+            NumericError::Negative { value } => {
+                write!(f, "{} is negative but the field carries no sign", value)
+            }
+            NumericError::InvalidNibble { position, nibble } => write!(
+                f,
+                "packed number has the non digit nibble {:X} at position {}",
+                nibble, position
+            ),
+            NumericError::InvalidSign { position, nibble } => write!(
+                f,
+                "packed number has the invalid sign nibble {:X} at position {}",
+                nibble, position
+            ),
+        }
+    }
+}
+
+impl std::error::Error for NumericError {}
+
+/// Errors raised while converting amounts with a DE 9 or DE 10 conversion rate
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConversionError {
+    /// The rate holds a non digit character
+    InvalidCharacter { position: usize },
+    /// The decimal places digit is above 7
+    InvalidScale { scale: u32 },
+    ZeroRate,
+    /// The converted amount does not fit in 12 digits
+    Overflow { amount: u64 },
+    /// The received amount is too far from the recomputed one
+    Mismatch {
+        recomputed: u64,
+        received: u64,
+        tolerance: u64,
+    },
+}
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConversionError::InvalidCharacter { position } => write!(
+                f,
+                "conversion rate has an invalid character at position {}",
+                position
+            ),
+            ConversionError::InvalidScale { scale } => write!(
+                f,
+                "conversion rate has {} decimal places, at most 7 are allowed",
+                scale
+            ),
+            ConversionError::ZeroRate => write!(f, "conversion rate is zero"),
+            ConversionError::Overflow { amount } => {
+                write!(f, "converted amount {} does not fit in 12 digits", amount)
+            }
+            ConversionError::Mismatch {
+                recomputed,
+                received,
+                tolerance,
+            } => write!(
+                f,
+                "received amount {} differs from the recomputed {} by more than {}",
+                received, recomputed, tolerance
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConversionError {}
+
+/// Errors raised by a `FieldCodec` while decoding or encoding a composite field
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CodecError {
+    /// The value is not valid UTF-8 from this position on
+    Utf8 { position: usize },
+    /// The value length differs from what the layout describes
+    InvalidLength { expected: usize, got: usize },
+    /// A sub-field value length differs from its layout length
+    SubFieldLength {
+        name: String,
+        expected: usize,
+        got: usize,
+    },
+    /// A sub-field of the layout has no value
+    MissingSubField { name: String },
+    /// A value names a sub-field missing from the layout
+    UnknownSubField { name: String },
+    /// A list item holds the list delimiter
+    ContainsDelimiter { item: String },
+    /// The value shape is not the one produced by the codec
+    UnexpectedValue,
+    /// A codec specific failure
+    Invalid { reason: String },
+}
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CodecError::Utf8 { position } => {
+                write!(f, "value is not valid utf-8 from position {}", position)
+            }
+            CodecError::InvalidLength { expected, got } => {
+                write!(f, "value should have {} bytes but has {}", expected, got)
+            }
+            CodecError::SubFieldLength {
+                name,
+                expected,
+                got,
+            } => write!(
+                f,
+                "sub-field {} should have {} bytes but has {}",
+                name, expected, got
+            ),
+            CodecError::MissingSubField { name } => write!(f, "sub-field {} is missing", name),
+            CodecError::UnknownSubField { name } => {
+                write!(f, "sub-field {} is not part of the layout", name)
+            }
+            CodecError::ContainsDelimiter { item } => {
+                write!(f, "list item {:?} holds the delimiter", item)
+            }
+            CodecError::UnexpectedValue => write!(f, "value shape is not handled by the codec"),
+            CodecError::Invalid { reason } => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl std::error::Error for CodecError {}
+
+/// Errors raised while loading or composing a spec definition
+#[derive(Debug)]
+pub enum SpecError {
+    Io(std::io::Error),
+    Yaml(serde_yaml::Error),
+    #[cfg(feature = "json-spec")]
+    Json(serde_json::Error),
+    #[cfg(feature = "toml-spec")]
+    Toml(toml::de::Error),
+    /// A char type, size type or bitmap encoding name which is not known
+    UnknownType { label_id: String, value: String },
+    /// An overlay references a field which does not exist on the spec
+    UnknownField { label_id: String },
+    /// An overlay adds a field which already exists on the spec
+    DuplicatedField { label_id: String },
+    /// Two overlays with the same priority touch the same field
+    Conflict {
+        label_id: String,
+        overlays: (usize, usize),
+    },
+    /// A field index is repeated or leaves a gap, indexes must run from 0 without holes
+    UnexpectedIndex { index: usize, expected: usize },
+}
+
+impl fmt::Display for SpecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SpecError::Io(error) => write!(f, "unable to read spec: {}", error),
+            SpecError::Yaml(error) => write!(f, "unable to parse yaml spec: {}", error),
+            #[cfg(feature = "json-spec")]
+            SpecError::Json(error) => write!(f, "unable to parse json spec: {}", error),
+            #[cfg(feature = "toml-spec")]
+            SpecError::Toml(error) => write!(f, "unable to parse toml spec: {}", error),
+            SpecError::UnknownType { label_id, value } => {
+                write!(f, "field {} has an unknown type {:?}", label_id, value)
+            }
+            SpecError::UnknownField { label_id } => {
+                write!(f, "field {} does not exist on the spec", label_id)
+            }
+            SpecError::DuplicatedField { label_id } => {
+                write!(f, "field {} already exists on the spec", label_id)
+            }
+            SpecError::Conflict { label_id, overlays } => write!(
+                f,
+                "overlays {} and {} both change field {}, give them distinct priorities",
+                overlays.0, overlays.1, label_id
+            ),
+            SpecError::UnexpectedIndex { index, expected } => {
+                write!(f, "field index {} found where {} was expected", index, expected)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SpecError {}
+
+impl From<std::io::Error> for SpecError {
+    fn from(error: std::io::Error) -> Self {
+        SpecError::Io(error)
+    }
+}
+
+impl From<serde_yaml::Error> for SpecError {
+    fn from(error: serde_yaml::Error) -> Self {
+        SpecError::Yaml(error)
+    }
+}
+
+#[cfg(feature = "json-spec")]
+impl From<serde_json::Error> for SpecError {
+    fn from(error: serde_json::Error) -> Self {
+        SpecError::Json(error)
+    }
+}
+
+#[cfg(feature = "toml-spec")]
+impl From<toml::de::Error> for SpecError {
+    fn from(error: toml::de::Error) -> Self {
+        SpecError::Toml(error)
+    }
+}
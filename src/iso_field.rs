@@ -7,10 +7,12 @@
 // except according to those terms.
 
 use super::*;
+use crate::iso_error::NumericError;
+use crate::numeric::{self, PackedAmount};
 use serde::Serializer;
 use strum_macros;
 
-#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, strum_macros::EnumProperty)]
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize, Clone, strum_macros::EnumProperty)]
 pub enum FieldCharType {
     #[strum(props(content_type = "number"))]
     Iso8583_n,
@@ -33,18 +35,24 @@ pub enum FieldCharType {
     #[strum(props(content_type = "binary"))]
     Iso8583_z,
     #[strum(props(content_type = "binary"))]
+    Iso8583_bmp,
+    #[strum(props(content_type = "binary"))]
     Iso8583_bmps,
     #[strum(props(content_type = "string"))]
     Iso8583_mti,
+    /// numeric field packed as BCD, two digits per byte, the spec length counts digits
+    #[strum(props(content_type = "number"))]
+    Iso8583_n_bcd,
+    /// proprietary char type whose behaviour is registered on the spec `CharTypeRegistry`
+    #[strum(props(content_type = "custom"))]
+    Custom(u16),
+    #[default]
     #[strum(props(content_type = "binary"))]
     Iso8583_undefined,
 }
 
-impl Default for FieldCharType {
-    fn default() -> Self { FieldCharType::Iso8583_undefined }
-}
-
 impl FieldCharType {
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Option<FieldCharType> {
         match s {
             "n" => Some(FieldCharType::Iso8583_n),
@@ -57,8 +65,14 @@ impl FieldCharType {
             "anp" => Some(FieldCharType::Iso8583_anp),
             "b" => Some(FieldCharType::Iso8583_b),
             "z" => Some(FieldCharType::Iso8583_z),
+            "bmp" => Some(FieldCharType::Iso8583_bmp),
             "bmps" => Some(FieldCharType::Iso8583_bmps),
             "mti" => Some(FieldCharType::Iso8583_mti),
+            "n_bcd" => Some(FieldCharType::Iso8583_n_bcd),
+            custom if custom.starts_with("custom:") => custom["custom:".len()..]
+                .parse()
+                .ok()
+                .map(FieldCharType::Custom),
             "undefined" => Some(FieldCharType::Iso8583_undefined),
             _ => None,
         }
@@ -66,33 +80,55 @@ impl FieldCharType {
 
     pub fn as_str(&self) -> &'static str {
         match self {
-            &FieldCharType::Iso8583_n => "n",
-            &FieldCharType::Iso8583_ns => "ns",
-            &FieldCharType::Iso8583_xn => "xs",
-            &FieldCharType::Iso8583_a => "a",
-            &FieldCharType::Iso8583_an => "an",
-            &FieldCharType::Iso8583_ans => "ans",
-            &FieldCharType::Iso8583_ansb => "ansb",
-            &FieldCharType::Iso8583_anp => "anp",
-            &FieldCharType::Iso8583_b => "b",
-            &FieldCharType::Iso8583_z => "z",
-            &FieldCharType::Iso8583_bmps => "bmps",
-            &FieldCharType::Iso8583_mti => "mti",
-            &FieldCharType::Iso8583_undefined => "undefined",
+            FieldCharType::Iso8583_n => "n",
+            FieldCharType::Iso8583_ns => "ns",
+            FieldCharType::Iso8583_xn => "xs",
+            FieldCharType::Iso8583_a => "a",
+            FieldCharType::Iso8583_an => "an",
+            FieldCharType::Iso8583_ans => "ans",
+            FieldCharType::Iso8583_ansb => "ansb",
+            FieldCharType::Iso8583_anp => "anp",
+            FieldCharType::Iso8583_b => "b",
+            FieldCharType::Iso8583_z => "z",
+            FieldCharType::Iso8583_bmp => "bmp",
+            FieldCharType::Iso8583_bmps => "bmps",
+            FieldCharType::Iso8583_mti => "mti",
+            FieldCharType::Iso8583_n_bcd => "n_bcd",
+            FieldCharType::Custom(_) => "custom",
+            FieldCharType::Iso8583_undefined => "undefined",
+        }
+    }
+
+    /// Whether the byte belongs to the character class of this char type, binary types
+    /// accept any byte and custom types are checked through their `CharTypeRegistry`
+    pub fn accepts(&self, byte: u8) -> bool {
+        match self {
+            FieldCharType::Iso8583_n
+            | FieldCharType::Iso8583_ns
+            | FieldCharType::Iso8583_mti
+            | FieldCharType::Iso8583_n_bcd => byte.is_ascii_digit(),
+            FieldCharType::Iso8583_xn => byte.is_ascii_digit() || byte == b'C' || byte == b'D',
+            FieldCharType::Iso8583_a => byte.is_ascii_alphabetic(),
+            FieldCharType::Iso8583_an => byte.is_ascii_alphanumeric(),
+            FieldCharType::Iso8583_anp => byte.is_ascii_alphanumeric() || byte == b' ',
+            FieldCharType::Iso8583_ans => byte == b' ' || byte.is_ascii_graphic(),
+            _ => true,
         }
     }
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
 pub enum FieldSizeType {
     Fixed,
     LlVar,
     LllVar,
     LlllVar,
+    LlllllVar,
     BitMap,
 }
 
 impl FieldSizeType {
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Option<FieldSizeType> {
         let s_lower = s.to_lowercase();
         match s_lower.as_str() {
@@ -100,6 +136,7 @@ impl FieldSizeType {
             "llvar" => Some(FieldSizeType::LlVar),
             "lllvar" => Some(FieldSizeType::LllVar),
             "llllvar" => Some(FieldSizeType::LlllVar),
+            "llllllvar" => Some(FieldSizeType::LlllllVar),
             "bitmap" => Some(FieldSizeType::BitMap),
             _ => None,
         }
@@ -107,23 +144,104 @@ impl FieldSizeType {
 
     pub fn as_str(&self) -> &'static str {
         match self {
-            &FieldSizeType::Fixed => "fixed",
-            &FieldSizeType::LlVar => "llvar",
-            &FieldSizeType::LllVar => "lllvar",
-            &FieldSizeType::LlllVar => "llllvar",
-            &FieldSizeType::BitMap => "bitmap",
+            FieldSizeType::Fixed => "fixed",
+            FieldSizeType::LlVar => "llvar",
+            FieldSizeType::LllVar => "lllvar",
+            FieldSizeType::LlllVar => "llllvar",
+            FieldSizeType::LlllllVar => "llllllvar",
+            FieldSizeType::BitMap => "bitmap",
+        }
+    }
+
+    /// Amount of digits of the length prefix, 0 for fields without one
+    pub fn prefix_digits(&self) -> usize {
+        match self {
+            FieldSizeType::LlVar => 2,
+            FieldSizeType::LllVar => 3,
+            FieldSizeType::LlllVar => 4,
+            FieldSizeType::LlllllVar => 6,
+            FieldSizeType::Fixed | FieldSizeType::BitMap => 0,
         }
     }
 }
 
+/// How the length prefix of a variable field is written
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LengthEncoding {
+    /// one ascii digit per byte, e.g. `16` for an LLVAR of 16 bytes
+    #[default]
+    Ascii,
+    /// packed BCD, two digits per byte left padded with a zero nibble, e.g. `0x16`
+    Bcd,
+}
+
+impl LengthEncoding {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<LengthEncoding> {
+        match s.to_lowercase().as_str() {
+            "ascii" => Some(LengthEncoding::Ascii),
+            "bcd" => Some(LengthEncoding::Bcd),
+            _ => None,
+        }
+    }
+
+    /// Bytes taken by a prefix of `digits` digits
+    pub fn prefix_len(&self, digits: usize) -> usize {
+        match self {
+            LengthEncoding::Ascii => digits,
+            LengthEncoding::Bcd => (digits + 1) / 2,
+        }
+    }
+
+    /// Length held by `prefix`, `None` when it holds anything but digits
+    pub fn decode(&self, prefix: &[u8]) -> Option<usize> {
+        let digits = match self {
+            LengthEncoding::Ascii => prefix.to_vec(),
+            LengthEncoding::Bcd => bcd_to_ascii(prefix).ok()?,
+        };
+        if !digits.iter().all(u8::is_ascii_digit) {
+            return None;
+        }
+
+        Some(
+            digits
+                .iter()
+                .fold(0usize, |len, digit| len * 10 + usize::from(digit - b'0')),
+        )
+    }
+
+    /// Writes `len` as a prefix of `digits` digits
+    pub fn encode(&self, len: usize, digits: usize) -> Result<Vec<u8>, NumericError> {
+        let ascii = numeric::format_fixed(len as u64, digits)?;
+        Ok(match self {
+            LengthEncoding::Ascii => ascii,
+            LengthEncoding::Bcd => ascii_to_bcd(&ascii)?,
+        })
+    }
+}
+
 /// `IsoField` defination
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
 pub struct IsoField {
     pub label: String,
     pub label_id: String,
     pub char_type: FieldCharType,
     pub size_type: FieldSizeType,
     pub length: usize,
+    /// mandatory fields are reported by `IsoMsg::validate_mandatory_fields` when absent
+    #[serde(default)]
+    pub required: bool,
+    /// padding `IsoMsg::set_field` applies to short values of fixed fields, with `None`
+    /// the padding detected at parse time is kept
+    #[serde(default)]
+    pub padding: FieldPadding,
+    /// layout of the signed amount the field packs as BCD, read and written by
+    /// `IsoMsg::get_signed_amount` and `IsoMsg::set_signed_amount`
+    #[serde(default)]
+    pub packed_amount: Option<PackedAmount>,
+    /// encoding of the length prefix of variable fields
+    #[serde(default)]
+    pub length_encoding: LengthEncoding,
 }
 
 /// `IsoField` implementation
@@ -141,6 +259,46 @@ impl IsoField {
             char_type,
             length,
             size_type,
+            required: false,
+            padding: FieldPadding::None,
+            packed_amount: None,
+            length_encoding: LengthEncoding::Ascii,
+        }
+    }
+
+    /// Same field padding short values with `padding`, e.g. `LeadingZeros` on numbers
+    pub fn with_padding(self, padding: FieldPadding) -> IsoField {
+        IsoField { padding, ..self }
+    }
+
+    /// Same field holding a signed amount packed as BCD with the `packed_amount` layout,
+    /// its length then counts bytes
+    pub fn with_packed_amount(self, packed_amount: PackedAmount) -> IsoField {
+        IsoField {
+            packed_amount: Some(packed_amount),
+            ..self
+        }
+    }
+
+    /// Same field with its length prefix written as `length_encoding`
+    pub fn with_length_encoding(self, length_encoding: LengthEncoding) -> IsoField {
+        IsoField {
+            length_encoding,
+            ..self
+        }
+    }
+
+    /// Same as `new` for a field every message must carry
+    pub fn new_required(
+        label: &str,
+        label_id: &str,
+        char_type: FieldCharType,
+        length: usize,
+        size_type: FieldSizeType,
+    ) -> IsoField {
+        IsoField {
+            required: true,
+            ..IsoField::new(label, label_id, char_type, length, size_type)
         }
     }
 }
@@ -175,8 +333,99 @@ impl serde::Serialize for IPMValue {
     }
 }
 
+/// Everything known about a single field of an `IsoMsg`, from the spec metadata to its decoded value
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldDescription {
+    pub index: usize,
+    pub label: String,
+    pub char_type: FieldCharType,
+    pub size_type: FieldSizeType,
+    pub max_len: usize,
+    pub present: bool,
+    /// the field bytes as found on the payload, including the length prefix
+    pub raw_hex: String,
+    pub decoded: Option<IPMValue>,
+    pub byte_offset: usize,
+    pub byte_len: usize,
+}
+
+/// How a fixed field value is padded to the spec length
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FieldPadding {
+    #[default]
+    None,
+    LeadingZeros,
+    LeadingSpaces,
+    TrailingSpaces,
+    TrailingNulls,
+}
+
+impl FieldPadding {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<FieldPadding> {
+        match s.to_lowercase().as_str() {
+            "none" => Some(FieldPadding::None),
+            "leading_zeros" => Some(FieldPadding::LeadingZeros),
+            "leading_spaces" => Some(FieldPadding::LeadingSpaces),
+            "trailing_spaces" => Some(FieldPadding::TrailingSpaces),
+            "trailing_nulls" => Some(FieldPadding::TrailingNulls),
+            _ => None,
+        }
+    }
+
+    /// Guesses the padding from the first and last byte of a fixed field value
+    pub fn detect(value: &[u8]) -> FieldPadding {
+        match (value.first(), value.last()) {
+            (_, Some(b' ')) => FieldPadding::TrailingSpaces,
+            (_, Some(0)) => FieldPadding::TrailingNulls,
+            (Some(b'0'), _) => FieldPadding::LeadingZeros,
+            (Some(b' '), _) => FieldPadding::LeadingSpaces,
+            _ => FieldPadding::None,
+        }
+    }
+
+    /// Strips the padding bytes from the value
+    pub fn trim<'a>(&self, value: &'a [u8]) -> &'a [u8] {
+        let pad_byte = match self {
+            FieldPadding::None => return value,
+            FieldPadding::LeadingZeros => b'0',
+            FieldPadding::LeadingSpaces | FieldPadding::TrailingSpaces => b' ',
+            FieldPadding::TrailingNulls => 0,
+        };
+
+        if self.is_leading() {
+            let start = value.iter().position(|&byte| byte != pad_byte).unwrap_or(value.len());
+            &value[start..]
+        } else {
+            let end = value.iter().rposition(|&byte| byte != pad_byte).map_or(0, |end| end + 1);
+            &value[..end]
+        }
+    }
+
+    /// Pads the value up to `length`, values already long enough are returned as is
+    pub fn pad(&self, value: &[u8], length: usize) -> Vec<u8> {
+        let pad_byte = match self {
+            FieldPadding::None => return value.to_vec(),
+            FieldPadding::LeadingZeros => b'0',
+            FieldPadding::LeadingSpaces | FieldPadding::TrailingSpaces => b' ',
+            FieldPadding::TrailingNulls => 0,
+        };
+        let padding = vec![pad_byte; length.saturating_sub(value.len())];
+
+        if self.is_leading() {
+            [padding.as_slice(), value].concat()
+        } else {
+            [value, padding.as_slice()].concat()
+        }
+    }
+
+    fn is_leading(&self) -> bool {
+        matches!(self, FieldPadding::LeadingZeros | FieldPadding::LeadingSpaces)
+    }
+}
+
 /// Field Payload is used inside IsoMsg to represent the field label, length and location
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct FieldPayload {
     pub iso_field_label: Option<String>,
     pub iso_field_label_id: String,
@@ -185,15 +434,29 @@ pub struct FieldPayload {
     pub index: usize,
     pub len: usize,
     pub tag_len: usize, // the length of the tag e.g LLLVar = 3
+    pub new_payload: Option<Vec<u8>>, // the field bytes, tag included, written by `set_field`
+    pub detected_pad: FieldPadding, // padding found at parse time, restored by `set_field`
 }
 
 impl FieldPayload {
-    pub fn iso_field_value<'a>(&self, buffer: &'a [u8]) -> Vec<u8> {
-        buffer[self.index + self.tag_len..self.index + self.len].to_vec()
+    /// The field bytes including the length tag, read from `new_payload` when the field was overwritten
+    pub fn raw_value<'a>(&'a self, buffer: &'a [u8]) -> &'a [u8] {
+        match &self.new_payload {
+            Some(new_payload) => new_payload,
+            None => &buffer[self.index..self.index + self.len],
+        }
+    }
+
+    pub fn iso_field_value(&self, buffer: &[u8]) -> Vec<u8> {
+        self.raw_value(buffer)[self.tag_len..].to_vec()
     }
 
     pub fn get_ipm_value(&self, buffer: &[u8]) -> eyre::Result<IPMValue> {
-        let bytes = self.iso_field_value(buffer);
+        let mut bytes = self.iso_field_value(buffer);
+        if self.char_type == FieldCharType::Iso8583_n_bcd {
+            // the left padding digit of odd lengths is a zero, harmless for the number
+            bytes = bcd_to_ascii(&bytes)?;
+        }
 
         if self.char_type.get_str("content_type") == Some("string") {
             let utf8_string = String::from_utf8_lossy(&bytes).to_string();
@@ -210,3 +473,54 @@ impl FieldPayload {
         }
     }
 }
+
+/// Expands packed BCD into ascii digits, a nibble above 9 can't be expanded and is reported
+/// as `InvalidNibble`
+pub(crate) fn bcd_to_ascii(input: &[u8]) -> Result<Vec<u8>, NumericError> {
+    input
+        .iter()
+        .flat_map(|byte| [byte >> 4, byte & 0x0F])
+        .enumerate()
+        .map(|(position, nibble)| match nibble {
+            0..=9 => Ok(b'0' + nibble),
+            _ => Err(NumericError::InvalidNibble { position, nibble }),
+        })
+        .collect()
+}
+
+/// Packs ascii digits as BCD, anything but a digit can't be packed and is reported as
+/// `InvalidDigit`
+pub(crate) fn ascii_to_bcd(input: &[u8]) -> Result<Vec<u8>, NumericError> {
+    if let Some(position) = input.iter().position(|byte| !byte.is_ascii_digit()) {
+        return Err(NumericError::InvalidDigit { position });
+    }
+
+    let nibble = |digit: &u8| digit - b'0';
+    let digits: Vec<u8> = if input.len() % 2 == 1 {
+        std::iter::once(0).chain(input.iter().map(nibble)).collect()
+    } else {
+        input.iter().map(nibble).collect()
+    };
+
+    Ok(digits
+        .chunks(2)
+        .map(|pair| (pair[0] << 4) | pair[1])
+        .collect())
+}
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02X}", byte)).collect()
+}
+
+pub(crate) fn from_hex(hex: &[u8]) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+
+    hex.chunks(2)
+        .map(|hex_pair| {
+            let hex_str = std::str::from_utf8(hex_pair).ok()?;
+            u8::from_str_radix(hex_str, 16).ok()
+        })
+        .collect()
+}
@@ -6,146 +6,1612 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use crate::conversion_rate::{ConversionRate, MAX_AMOUNT};
+use crate::field_codec::{FieldCodec, FieldValue};
+use crate::framing::LengthHeader;
+use crate::iso_error::{CodecError, ConversionError, IsoError, NumericError};
+use crate::iso_field;
 use crate::iso_field::FieldCharType;
+use crate::iso_field::FieldDescription;
+use crate::iso_field::FieldPadding;
 use crate::iso_field::FieldPayload;
 use crate::iso_field::FieldSizeType;
 use crate::iso_field::IsoField;
-use crate::iso_specs::IsoSpecs;
+use crate::iso_specs::{BitmapEncoding, IsoSpecs, LabelKeys, TrailerMarker};
+use crate::message_codes::{FunctionCode, MessageReasonCode};
+use crate::mti::Mti;
+use crate::numeric;
+use crate::pds::CompositeHandle;
+use crate::track2::Track2;
+use crate::transaction::MsgTransaction;
+use crate::validator::{FieldValidator, ValidationMode, ValidationReport, NO_VALIDATOR};
 use bit_array::BitArray;
+#[cfg(feature = "serde")]
+use serde::de::{DeserializeSeed, Error};
+#[cfg(feature = "serde")]
+use serde::ser::SerializeMap;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize, Serializer};
 use std::borrow::Cow;
-use std::fmt;
+use std::collections::BTreeMap;
+use std::fmt::{self, Write as _};
+use std::hash::{Hash, Hasher};
 use std::ops::Deref;
-use std::str;
-use typenum::U128;
+use std::str::{self, FromStr};
+use strum::EnumProperty;
+use typenum::{U128, U192};
+
+/// Bit of the secondary bitmap announcing a tertiary bitmap, on specs allowing one
+const TERTIARY_BIT: usize = 64;
+
+/// Length of a DE 54 additional amount: account type (2), amount type (2), currency (3),
+/// sign C or D (1) and amount (12)
+const ADDITIONAL_AMOUNT_LEN: usize = 20;
+/// DE 54 amount types restating the DE 4 amount, `57` being the original amount
+const TRANSACTION_AMOUNT_TYPES: [&[u8]; 1] = [b"57"];
 
 /// `IsoMsg`
 pub struct IsoMsg<'a, 'b> {
     payload: Cow<'a, [u8]>,
     iso_spec: &'b IsoSpecs,
     fields: Vec<FieldPayload>,
+    bitmap_encoding: BitmapEncoding,
+    validator: &'b dyn FieldValidator,
 }
 
+/// A message owning its payload, as built by `from_owned` or `clone_owned`
+pub type OwnedIsoMsg<'b> = IsoMsg<'static, 'b>;
+
+/// Same lines as the alternate `Display` form, `{:#}`
 impl fmt::Debug for IsoMsg<'_, '_> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let result: String = self
-            .present_fields()
+        self.write_field_lines(f)
+    }
+}
+
+/// Width of the value column of the `Display` table unless the format sets one, e.g `{:48}`
+const DISPLAY_VALUE_WIDTH: usize = 32;
+
+/// Renders the present fields as a table, binary fields as hexadecimal, values wider than
+/// the value column are cut with `…`
+///
+/// ```text
+/// | Field   | Label                  | Value            |
+/// |---------|------------------------|------------------|
+/// | 002     | Primary Account Number | 5555444433332222 |
+/// ```
+///
+/// The alternate form, `{:#}`, writes one line per present field instead, with its spec
+/// index, label, value length in bytes and whole value, for dump tools to parse:
+///
+/// ```text
+/// DE  2 (Primary Account Number)  [16]: 5555444433332222
+/// ```
+impl fmt::Display for IsoMsg<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if f.alternate() {
+            return self.write_field_lines(f);
+        }
+
+        let value_width = f.width().unwrap_or(DISPLAY_VALUE_WIDTH).max(1);
+        let present = || {
+            self.fields
+                .iter()
+                .enumerate()
+                .filter(|(_, field)| field.exist)
+                .map(|(index, field)| (index, field, &self.iso_spec.specs[index]))
+        };
+        let column_width = |header: &str, cell: fn(&IsoField) -> &str| {
+            present()
+                .map(|(_, _, iso_field)| cell(iso_field).chars().count())
+                .fold(header.len(), usize::max)
+        };
+        let field_width = column_width("Field", |iso_field| &iso_field.label_id);
+        let label_width = column_width("Label", |iso_field| &iso_field.label);
+
+        write!(f, "| ")?;
+        write_cell(f, "Field".chars(), field_width)?;
+        write!(f, " | ")?;
+        write_cell(f, "Label".chars(), label_width)?;
+        write!(f, " | ")?;
+        write_cell(f, "Value".chars(), value_width)?;
+        writeln!(f, " |")?;
+        for width in [field_width, label_width, value_width] {
+            write!(f, "|")?;
+            write_cell(f, std::iter::repeat('-').take(width + 2), width + 2)?;
+        }
+        writeln!(f, "|")?;
+
+        for (index, field, iso_field) in present() {
+            write!(f, "| ")?;
+            write_cell(f, iso_field.label_id.chars(), field_width)?;
+            write!(f, " | ")?;
+            write_cell(f, iso_field.label.chars(), label_width)?;
+            write!(f, " | ")?;
+            let value = self.field_text(index, field);
+            write_cell(f, display_value(iso_field, &value).chars(), value_width)?;
+            writeln!(f, " |")?;
+        }
+        Ok(())
+    }
+}
+
+/// Value as shown by `Display` and `Debug`, binary fields as uppercase hexadecimal
+fn display_value<'v>(iso_field: &IsoField, value: &'v [u8]) -> Cow<'v, str> {
+    if iso_field.char_type.get_str("content_type") != Some("binary") {
+        return String::from_utf8_lossy(value);
+    }
+
+    let digit = |nibble: u8| char::from_digit(u32::from(nibble), 16).unwrap();
+    Cow::Owned(
+        value
             .iter()
-            .fold("".to_string(), |acc, &x| {
-                format!(
-                    "{} \n {:?} \n values: {:?} \n",
-                    acc,
-                    x.iso_field_label.clone().expect("cannot open field label"),
-                    String::from_utf8_lossy(&x.iso_field_value(self.payload.deref()))
-                )
-            });
-        write!(f, "{}", result)
+            .flat_map(|byte| [digit(byte >> 4), digit(byte & 0x0F)])
+            .map(|digit| digit.to_ascii_uppercase())
+            .collect(),
+    )
+}
+
+/// Writes `content` on exactly `width` characters, padded with spaces or cut with `…`
+fn write_cell(
+    f: &mut fmt::Formatter,
+    content: impl Iterator<Item = char> + Clone,
+    width: usize,
+) -> fmt::Result {
+    let len = content.clone().take(width + 1).count();
+    if len > width {
+        for c in content.take(width - 1) {
+            f.write_char(c)?;
+        }
+        return f.write_char('…');
+    }
+
+    for c in content {
+        f.write_char(c)?;
+    }
+    for _ in len..width {
+        f.write_char(' ')?;
+    }
+    Ok(())
+}
+
+/// Messages are equal when they hold the same present fields with the same values, as
+/// compared by `IsoMsg::diff`. The comparison is value based: the bitmaps, the offsets and
+/// whether a value was parsed or set don't matter, so equal messages may still serialize
+/// differently, e.g. with another bitmap encoding.
+///
+/// This deliberately departs from comparing the serialized bytes, which would tell the
+/// same fields apart by their bitmap encoding. Compare `to_vec` results for byte equality.
+impl PartialEq for IsoMsg<'_, '_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.diff(other).is_ok_and(|diffs| diffs.is_empty())
+    }
+}
+
+impl Eq for IsoMsg<'_, '_> {}
+
+/// Hashes what `PartialEq` compares, the spec field count and the present fields with their
+/// values, so equal messages hash the same whatever their bitmap encoding or origin
+impl Hash for IsoMsg<'_, '_> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.iso_spec.specs.len().hash(state);
+        let bitmap_index = self.iso_spec.bitmap_index();
+        for (index, field) in self.fields.iter().enumerate() {
+            if field.exist && Some(index) != bitmap_index {
+                index.hash(state);
+                self.field_text(index, field).hash(state);
+            }
+        }
+    }
+}
+
+/// Clones detach the copy from the parsed buffer, see `IsoMsg::clone_owned`
+impl Clone for IsoMsg<'_, '_> {
+    fn clone(&self) -> Self {
+        self.clone_owned()
+    }
+}
+
+/// Serializes the present data elements as a map keyed by field index, binary fields
+/// are written as uppercase hexadecimal, fields with a codec as their decoded structure
+/// and every other field as text
+#[cfg(feature = "serde")]
+impl Serialize for IsoMsg<'_, '_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let fields: Vec<(usize, FieldValue)> = self
+            .fields
+            .iter()
+            .enumerate()
+            .filter(|(index, field)| {
+                field.exist && self.iso_spec.specs[*index].size_type != FieldSizeType::BitMap
+            })
+            .map(|(index, field)| match self.iso_spec.field_codecs.get(index) {
+                Some(codec) => self.decode_value(index, field, codec).map(|value| (index, value)),
+                None => Ok((index, FieldValue::Text(self.export_value(index, field)))),
+            })
+            .collect::<Result<_, IsoError>>()
+            .map_err(serde::ser::Error::custom)?;
+
+        let mut map = serializer.serialize_map(Some(fields.len()))?;
+        for (index, value) in fields {
+            map.serialize_entry(&index.to_string(), &value)?;
+        }
+        map.end()
+    }
+}
+
+/// Deserializes a message from the map written by `IsoMsg` serialization, since a
+/// message can't live without its spec the spec is given as the seed
+///
+/// `IsoMsgSeed(&spec).deserialize(&mut serde_json::Deserializer::from_str(json))`
+#[cfg(feature = "serde")]
+pub struct IsoMsgSeed<'b>(pub &'b IsoSpecs);
+
+#[cfg(feature = "serde")]
+impl<'de, 'b> DeserializeSeed<'de> for IsoMsgSeed<'b> {
+    type Value = IsoMsg<'static, 'b>;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let iso_spec = self.0;
+        let values: BTreeMap<String, FieldValue> = BTreeMap::deserialize(deserializer)?;
+        let mut iso_msg = IsoMsg::empty(iso_spec);
+
+        for (key, value) in values {
+            let iso_field = key
+                .parse::<usize>()
+                .ok()
+                .and_then(|index| Some((index, iso_spec.specs.get(index)?)));
+            let (index, iso_field) = match iso_field {
+                Some(iso_field) => iso_field,
+                None => {
+                    let message = format!("{} is not a valid field index", key);
+                    return Err(D::Error::custom(message));
+                }
+            };
+
+            if iso_spec.field_codecs.get(index).is_some() {
+                iso_msg.set_value(index, &value).map_err(D::Error::custom)?;
+                continue;
+            }
+            let value = match value {
+                FieldValue::Text(value) => value,
+                _ => return Err(D::Error::custom(format!("field {} should be text", index))),
+            };
+
+            let bytes = if iso_field.char_type.get_str("content_type") == Some("binary") {
+                iso_field::from_hex(value.as_bytes()).ok_or_else(|| {
+                    D::Error::custom(format!("field {} should be hexadecimal", index))
+                })?
+            } else {
+                value.into_bytes()
+            };
+
+            if bytes.len() > iso_field.length {
+                return Err(D::Error::custom(IsoError::ValueTooLong {
+                    index,
+                    max: iso_field.length,
+                    got: bytes.len(),
+                }));
+            }
+            iso_msg.set_field(index, &bytes).map_err(D::Error::custom)?;
+        }
+
+        Ok(iso_msg)
+    }
+}
+
+/// A present field of a message, as yielded by `IsoMsg::iter`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FieldView<'m> {
+    index: usize,
+    iso_field: &'m IsoField,
+    value: &'m [u8],
+}
+
+impl<'m> FieldView<'m> {
+    /// Spec index of the field
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn label(&self) -> &'m str {
+        &self.iso_field.label
+    }
+
+    pub fn iso_field(&self) -> &'m IsoField {
+        self.iso_field
+    }
+
+    /// Value without its length prefix, packed BCD stays packed
+    pub fn value(&self) -> &'m [u8] {
+        self.value
+    }
+}
+
+/// Iterator over the present fields of a message, see `IsoMsg::iter`
+pub struct FieldViews<'m, 'a, 'b> {
+    iso_msg: &'m IsoMsg<'a, 'b>,
+    fields: std::iter::Enumerate<std::slice::Iter<'m, FieldPayload>>,
+}
+
+impl<'m> Iterator for FieldViews<'m, '_, '_> {
+    type Item = FieldView<'m>;
+
+    fn next(&mut self) -> Option<FieldView<'m>> {
+        let iso_msg = self.iso_msg;
+        self.fields
+            .find(|(_, field)| field.exist)
+            .map(|(index, field)| FieldView {
+                index,
+                iso_field: &iso_msg.iso_spec.specs[index],
+                value: iso_msg.field_value(field),
+            })
+    }
+}
+
+impl<'m, 'a, 'b> IntoIterator for &'m IsoMsg<'a, 'b> {
+    type Item = FieldView<'m>;
+    type IntoIter = FieldViews<'m, 'a, 'b>;
+
+    fn into_iter(self) -> FieldViews<'m, 'a, 'b> {
+        self.iter()
+    }
+}
+
+/// A field differing between two messages, as listed by `IsoMsg::diff`, values are given
+/// without their length prefix
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldDiff<'c> {
+    /// present on the other message only
+    Added {
+        index: usize,
+        iso_field: &'c IsoField,
+        other_value: Cow<'c, [u8]>,
+    },
+    /// present on this message only
+    Removed {
+        index: usize,
+        iso_field: &'c IsoField,
+        value: Cow<'c, [u8]>,
+    },
+    Changed {
+        index: usize,
+        iso_field: &'c IsoField,
+        value: Cow<'c, [u8]>,
+        other_value: Cow<'c, [u8]>,
+    },
+}
+
+impl FieldDiff<'_> {
+    pub fn index(&self) -> usize {
+        match self {
+            FieldDiff::Added { index, .. }
+            | FieldDiff::Removed { index, .. }
+            | FieldDiff::Changed { index, .. } => *index,
+        }
+    }
+
+    pub fn label(&self) -> &str {
+        match self {
+            FieldDiff::Added { iso_field, .. }
+            | FieldDiff::Removed { iso_field, .. }
+            | FieldDiff::Changed { iso_field, .. } => &iso_field.label,
+        }
+    }
+}
+
+/// One line per field, e.g `~ 039 Response Code: 00 => 05`, binary values are written as
+/// uppercase hexadecimal
+impl fmt::Display for FieldDiff<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = |iso_field: &IsoField, value: &[u8]| {
+            if iso_field.char_type.get_str("content_type") == Some("binary") {
+                iso_field::to_hex(value)
+            } else {
+                String::from_utf8_lossy(value).to_string()
+            }
+        };
+
+        match self {
+            FieldDiff::Added {
+                iso_field,
+                other_value,
+                ..
+            } => write!(
+                f,
+                "+ {} {}: {}",
+                iso_field.label_id,
+                iso_field.label,
+                text(iso_field, other_value)
+            ),
+            FieldDiff::Removed {
+                iso_field, value, ..
+            } => write!(
+                f,
+                "- {} {}: {}",
+                iso_field.label_id,
+                iso_field.label,
+                text(iso_field, value)
+            ),
+            FieldDiff::Changed {
+                iso_field,
+                value,
+                other_value,
+                ..
+            } => write!(
+                f,
+                "~ {} {}: {} => {}",
+                iso_field.label_id,
+                iso_field.label,
+                text(iso_field, value),
+                text(iso_field, other_value)
+            ),
+        }
     }
 }
 
 impl<'a, 'b> IsoMsg<'a, 'b> {
+    /// Parses a message, panicking when the payload doesn't follow the spec, see `try_new`
     pub fn new(iso_spec: &'b IsoSpecs, payload: &'a [u8]) -> IsoMsg<'a, 'b> {
-        let fields = IsoMsg::from_byte_array(iso_spec, payload);
+        IsoMsg::new_with_validator(iso_spec, payload, &NO_VALIDATOR)
+    }
 
-        IsoMsg {
+    /// Parses a message, reporting a malformed length prefix, a bitmap or field running past
+    /// the payload instead of panicking so a stream reader can skip the message and go on
+    pub fn try_new(iso_spec: &'b IsoSpecs, payload: &'a [u8]) -> Result<IsoMsg<'a, 'b>, IsoError> {
+        IsoMsg::try_new_with_validator(iso_spec, payload, &NO_VALIDATOR)
+    }
+
+    /// Same as `new`, with `validator` checking every value given to `set_field`
+    pub fn new_with_validator(
+        iso_spec: &'b IsoSpecs,
+        payload: &'a [u8],
+        validator: &'b dyn FieldValidator,
+    ) -> IsoMsg<'a, 'b> {
+        IsoMsg::try_new_with_validator(iso_spec, payload, validator)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Same as `try_new`, with `validator` checking every value given to `set_field`
+    pub fn try_new_with_validator(
+        iso_spec: &'b IsoSpecs,
+        payload: &'a [u8],
+        validator: &'b dyn FieldValidator,
+    ) -> Result<IsoMsg<'a, 'b>, IsoError> {
+        let (fields, bitmap_encoding) = IsoMsg::parse_fields(iso_spec, payload)?;
+
+        Ok(IsoMsg {
             iso_spec,
             payload: Cow::Borrowed(payload),
             fields,
+            bitmap_encoding,
+            validator,
+        })
+    }
+
+    /// Parses a message taking ownership of its payload, so it can outlive the buffer it
+    /// was read into, panicking like `new` on a malformed payload, see `try_from_owned`
+    pub fn from_owned(iso_spec: &'b IsoSpecs, payload: Vec<u8>) -> IsoMsg<'static, 'b> {
+        IsoMsg::try_from_owned(iso_spec, payload).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Same as `from_owned`, reporting a malformed payload like `try_new`
+    pub fn try_from_owned(
+        iso_spec: &'b IsoSpecs,
+        payload: Vec<u8>,
+    ) -> Result<IsoMsg<'static, 'b>, IsoError> {
+        let (fields, bitmap_encoding) = IsoMsg::parse_fields(iso_spec, &payload)?;
+
+        Ok(IsoMsg {
+            iso_spec,
+            payload: Cow::Owned(payload),
+            fields,
+            bitmap_encoding,
+            validator: &NO_VALIDATOR,
+        })
+    }
+
+    /// Creates a message without any field, meant to be filled through `set_field`
+    pub fn empty(iso_spec: &'b IsoSpecs) -> IsoMsg<'static, 'b> {
+        let fields = iso_spec.specs.iter().map(|_| FieldPayload::default()).collect();
+
+        IsoMsg {
+            iso_spec,
+            payload: Cow::Owned(vec![]),
+            fields,
+            bitmap_encoding: iso_spec.bitmap_encoding,
+            validator: &NO_VALIDATOR,
+        }
+    }
+
+    /// Same message, with `validator` checking every value given to `set_field` from now on,
+    /// e.g `IsoMsg::empty(&spec).with_validator(&DefaultValidator)`
+    pub fn with_validator(self, validator: &'b dyn FieldValidator) -> IsoMsg<'a, 'b> {
+        IsoMsg { validator, ..self }
+    }
+
+    /// Copies the message into one owning its payload, detached from the parsed buffer
+    ///
+    /// The whole payload is copied once along with every field, values written by
+    /// `set_field` included. Later changes on either message don't affect the other,
+    /// only the spec and validator remain shared.
+    pub fn clone_owned(&self) -> IsoMsg<'static, 'b> {
+        IsoMsg {
+            payload: Cow::Owned(self.payload.to_vec()),
+            iso_spec: self.iso_spec,
+            fields: self.fields.clone(),
+            bitmap_encoding: self.bitmap_encoding,
+            validator: self.validator,
         }
     }
 
+    /// The bitmap encoding used when serializing, detected on parse when the spec asks for it
+    pub fn bitmap_encoding(&self) -> BitmapEncoding {
+        self.bitmap_encoding
+    }
+
+    /// Chooses the bitmap encoding used when serializing this message
+    pub fn set_bitmap_encoding(&mut self, bitmap_encoding: BitmapEncoding) {
+        self.bitmap_encoding = bitmap_encoding;
+    }
+
     pub fn length(&self) -> usize {
         self.present_fields().iter().map(|&x| x.len).sum()
     }
 
-    pub fn remove_field(&mut self, index: usize) -> Result<(), &str> {
-        assert!(index < self.fields.len());
-        assert!(index < self.iso_spec.specs.len());
+    /// Whether the message is the trailer record described by a trailer spec, always false
+    /// for specs without a `TrailerMarker`
+    pub fn is_trailer(&self) -> bool {
+        let raw_value = |index: usize| {
+            self.fields
+                .get(index)
+                .filter(|field| field.exist)
+                .map(|field| field.raw_value(self.payload.deref()))
+        };
+
+        match &self.iso_spec.trailer {
+            Some(TrailerMarker::Mti(mti)) => {
+                self.mti_index().and_then(raw_value) == Some(mti.as_slice())
+            }
+            Some(TrailerMarker::Sentinel(sentinel)) => {
+                raw_value(0).is_some_and(|value| value.starts_with(sentinel))
+            }
+            None => false,
+        }
+    }
+
+    /// Position of the mti on the spec
+    fn mti_index(&self) -> Option<usize> {
+        self.iso_spec.specs.iter().position(|spec| spec.label_id == "mti")
+    }
+
+    /// The parsed mti, e.g. to tell an authorization request from a reversal
+    pub fn mti(&self) -> Result<Mti, IsoError> {
+        let index = self.mti_index().ok_or(IsoError::InvalidField { index: 0 })?;
+        Mti::from_bytes(self.get_field_slice(index)?)
+    }
+
+    #[deprecated(since = "1.1.0", note = "please use `IsoMsg::mti` instead")]
+    pub fn get_mti(&self) -> Result<Mti, IsoError> {
+        self.mti()
+    }
+
+    pub fn set_mti(&mut self, mti: Mti) -> Result<(), IsoError> {
+        let index = self.mti_index().ok_or(IsoError::InvalidField { index: 0 })?;
+        self.set_field(index, &mti.to_bytes())
+    }
+
+    /// The DE 24 function code, e.g. `FullReversal` for 400
+    pub fn function_code(&self) -> Result<FunctionCode, IsoError> {
+        self.get_field_num::<u16>(self.de_index(24)).map(FunctionCode::from_code)
+    }
+
+    /// The DE 25 message reason code, e.g. `TimeoutWaitingForResponse` for 4021
+    pub fn message_reason_code(&self) -> Result<MessageReasonCode, IsoError> {
+        self.get_field_num::<u16>(self.de_index(25)).map(MessageReasonCode::from_code)
+    }
+
+    /// Removes a field governed by the bitmap, fields up to the bitmap are positional and
+    /// can't be removed without shifting every following offset
+    pub fn remove_field(&mut self, index: usize) -> Result<(), IsoError> {
+        if index >= self.fields.len() {
+            return Err(IsoError::InvalidField { index });
+        }
+        if let Some(bitmap_index) = self.iso_spec.bitmap_index() {
+            if index <= bitmap_index {
+                return Err(IsoError::PositionalField { index });
+            }
+        }
         self.fields[index].exist = false;
+        self.fields[index].new_payload = None;
         Ok(())
     }
 
-    pub fn set_field(&mut self, index: usize, buffer: &[u8]) -> Result<(), &str> {
+    /// Rewrites the stored bitmap from the present fields, dropping a secondary bitmap
+    /// left without bits once its fields were removed along with its indicator
+    pub fn compact(&mut self) {
+        if let Some(bitmap_index) = self.iso_spec.bitmap_index() {
+            let bitmap = self.bitmap_encoding.encode(&self.build_bitmap());
+            let field = &mut self.fields[bitmap_index];
+            if field.exist {
+                field.len = bitmap.len();
+                field.new_payload = Some(bitmap);
+            }
+        }
+    }
+
+    /// Encodes the fields differing from `base` as a message of the same spec, the fields
+    /// placed before the bitmap, such as the mti, are always carried
+    ///
+    /// A field present on `base` and missing here can't be marked on a bitmap, so such a
+    /// removal is refused with `PatchRemovesField`.
+    pub fn to_patch(&self, base: &IsoMsg) -> Result<Vec<u8>, IsoError> {
+        let bitmap_index = self.iso_spec.bitmap_index();
+        let mut patch = IsoMsg::empty(self.iso_spec);
+        patch.bitmap_encoding = self.bitmap_encoding;
+
+        for (index, field) in self.fields.iter().enumerate() {
+            if Some(index) == bitmap_index {
+                continue;
+            }
+            let is_positional = bitmap_index.is_some_and(|bitmap_index| index < bitmap_index);
+            let value = field.exist.then(|| field.raw_value(self.payload.deref()));
+            let base_value = base
+                .fields
+                .get(index)
+                .filter(|base_field| base_field.exist)
+                .map(|base_field| base_field.raw_value(base.payload.deref()));
+
+            match value {
+                Some(value) if is_positional || Some(value) != base_value => {
+                    patch.fields[index] = IsoMsg::detached_field(field, value);
+                }
+                None if base_value.is_some() && !is_positional => {
+                    return Err(IsoError::PatchRemovesField { index });
+                }
+                _ => {}
+            }
+        }
+
+        patch.to_vec()
+    }
+
+    /// Lists the fields differing from `other` in field order, e.g. to compare a request
+    /// with its response. The bitmaps follow the present fields and are left out.
+    ///
+    /// Both specs must have the same amount of fields, otherwise `SpecMismatch` is returned.
+    pub fn diff<'c>(&'c self, other: &'c IsoMsg) -> Result<Vec<FieldDiff<'c>>, IsoError> {
+        if self.iso_spec.specs.len() != other.iso_spec.specs.len() {
+            return Err(IsoError::SpecMismatch {
+                fields: self.iso_spec.specs.len(),
+                other_fields: other.iso_spec.specs.len(),
+            });
+        }
+
+        let bitmap_index = self.iso_spec.bitmap_index();
+        let value = |iso_msg: &'c IsoMsg, index: usize| {
+            let field = iso_msg.fields.get(index).filter(|field| field.exist)?;
+            Some(iso_msg.field_text(index, field))
+        };
+
+        let mut diffs = vec![];
+        for (index, iso_field) in self.iso_spec.specs.iter().enumerate() {
+            if Some(index) == bitmap_index {
+                continue;
+            }
+            let diff = match (value(self, index), value(other, index)) {
+                (None, Some(other_value)) => FieldDiff::Added {
+                    index,
+                    iso_field,
+                    other_value,
+                },
+                (Some(value), None) => FieldDiff::Removed {
+                    index,
+                    iso_field,
+                    value,
+                },
+                (Some(value), Some(other_value)) if value != other_value => FieldDiff::Changed {
+                    index,
+                    iso_field,
+                    value,
+                    other_value,
+                },
+                _ => continue,
+            };
+            diffs.push(diff);
+        }
+
+        Ok(diffs)
+    }
+
+    /// Applies a patch written by `to_patch` on the message it was computed against
+    pub fn apply_patch(&mut self, patch: &[u8]) -> Result<(), IsoError> {
+        let patch = IsoMsg::try_new(self.iso_spec, patch)?;
+        let bitmap_index = self.iso_spec.bitmap_index();
+
+        for (index, field) in patch.fields.iter().enumerate() {
+            if field.exist && Some(index) != bitmap_index {
+                let value = field.raw_value(patch.payload.deref());
+                self.fields[index] = IsoMsg::detached_field(field, value);
+            }
+        }
+        Ok(())
+    }
+
+    /// Copy of a field holding its own bytes, `value` being its raw value
+    fn detached_field(field: &FieldPayload, value: &[u8]) -> FieldPayload {
+        FieldPayload {
+            len: value.len(),
+            new_payload: Some(value.to_vec()),
+            ..field.clone()
+        }
+    }
+
+    /// Sets the value of a field, the length prefix is added according to the spec
+    ///
+    /// Fields positioned before the bitmap are always serialized in place and never get a
+    /// presence bit, setting one that was absent is supported since serialization rebuilds
+    /// every offset. Fields after the bitmap get their bit on serialization. The bitmap
+    /// itself is regenerated from the present fields and can't be set, neither can the
+    /// field matching the tertiary bitmap bit.
+    ///
+    /// Values are checked by the message validator before being stored, which accepts
+    /// anything fitting the spec length unless one is installed with `with_validator`.
+    pub fn set_field(&mut self, index: usize, buffer: &[u8]) -> Result<(), IsoError> {
         trace!(
             "set_field: index:{}, buffer:{}",
             index,
-            str::from_utf8(buffer).unwrap()
+            String::from_utf8_lossy(buffer)
         );
-        assert!(index < self.fields.len());
-        assert!(index < self.iso_spec.specs.len());
-        assert!(buffer.len() <= self.iso_spec.specs[index].length);
+        if index >= self.fields.len()
+            || index >= self.iso_spec.specs.len()
+            || self.iso_spec.bitmap_index() == Some(index)
+            || IsoMsg::tertiary_bit_index(self.iso_spec) == Some(index)
+        {
+            return Err(IsoError::InvalidField { index });
+        }
+        self.validator.validate(index, &self.iso_spec.specs[index], buffer)?;
+        if let FieldCharType::Custom(_) = self.iso_spec.specs[index].char_type {
+            let char_type = &self.iso_spec.specs[index].char_type;
+            self.iso_spec.char_types.validate_char_type(index, char_type, buffer)?;
+        }
+        // custom validators may skip the length check, the value must fit in any case
+        if buffer.len() > self.iso_spec.specs[index].length {
+            return Err(IsoError::ValueTooLong {
+                index,
+                max: self.iso_spec.specs[index].length,
+                got: buffer.len(),
+            });
+        }
 
         let len_prefix = self.get_field_length_prefix(index);
-        let total_lenth = buffer.len() + len_prefix;
-        let mut v = Vec::with_capacity(total_lenth);
+        let total_length = buffer.len() + len_prefix;
+        let mut v = Vec::with_capacity(total_length);
         trace!(
             "buffer.len():{}, iso_spec.specs[index].length:{}",
             buffer.len(),
             self.iso_spec.specs[index].length
         );
-        if len_prefix > 0 {
-            v.extend_from_slice(format!("{:0w$}", buffer.len(), w = len_prefix).as_bytes());
+        let iso_field = &self.iso_spec.specs[index];
+        let length_prefix = || {
+            let prefix_digits = iso_field.size_type.prefix_digits();
+            iso_field.length_encoding.encode(buffer.len(), prefix_digits)
+        };
+        if iso_field.char_type == FieldCharType::Iso8583_n_bcd {
+            // only digits can be packed, whatever the message validator lets through
+            if let Some(position) = buffer.iter().position(|byte| !byte.is_ascii_digit()) {
+                return Err(IsoError::InvalidCharacter { index, position });
+            }
+            if len_prefix > 0 {
+                v.extend_from_slice(&length_prefix()?);
+                v.extend_from_slice(&IsoMsg::ascii_to_bcd(buffer)?);
+            } else {
+                // fixed BCD fields always hold the spec amount of digits
+                let digits = FieldPadding::LeadingZeros.pad(buffer, iso_field.length);
+                v.extend_from_slice(&IsoMsg::ascii_to_bcd(&digits)?);
+            }
+        } else if len_prefix > 0 {
+            v.extend_from_slice(&length_prefix()?);
+            v.extend_from_slice(buffer);
+        } else if self.iso_spec.specs[index].size_type == FieldSizeType::Fixed {
+            let padding = self.field_padding(index);
+            v.extend_from_slice(&padding.pad(buffer, self.iso_spec.specs[index].length));
+        } else {
+            v.extend_from_slice(buffer);
         }
-        v.extend_from_slice(buffer);
 
         trace!(
             "index:{}, set_extend_from_slice : v {}",
             index,
-            str::from_utf8(&v).unwrap()
+            String::from_utf8_lossy(&v)
         );
         trace!("set_field: v.len:{}", v.len());
-        self.fields[index].exist = true;
+        self.store_field(index, v, len_prefix);
         Ok(())
     }
 
-    pub fn get_field_length_prefix(&self, index: usize) -> usize {
-        match self.iso_spec.specs[index].size_type {
-            FieldSizeType::LlVar => 2,
-            FieldSizeType::LllVar => 3,
-            FieldSizeType::LlllVar => 4,
-            _ => 0,
+    /// Stores the bytes of a field as they are, length prefix included, e.g. to patch a
+    /// field captured elsewhere. Only the index and the presence of a whole prefix are
+    /// checked, `validate` reports a prefix disagreeing with the value it announces.
+    pub fn set_field_raw(&mut self, index: usize, raw_value: &[u8]) -> Result<(), IsoError> {
+        if index >= self.fields.len()
+            || index >= self.iso_spec.specs.len()
+            || self.iso_spec.bitmap_index() == Some(index)
+            || IsoMsg::tertiary_bit_index(self.iso_spec) == Some(index)
+        {
+            return Err(IsoError::InvalidField { index });
+        }
+        let len_prefix = self.get_field_length_prefix(index);
+        if raw_value.len() < len_prefix {
+            return Err(IsoError::InvalidLengthPrefix {
+                label_id: self.iso_spec.specs[index].label_id.clone(),
+                prefix: raw_value.to_vec(),
+            });
+        }
+
+        if self.iso_spec.specs[index].char_type == FieldCharType::Iso8583_n_bcd {
+            IsoMsg::check_packed_digits(index, &raw_value[len_prefix..])?;
+        }
+
+        self.store_field(index, raw_value.to_vec(), len_prefix);
+        Ok(())
+    }
+
+    /// Fails on a packed BCD nibble above 9, reported at the position of its byte
+    fn check_packed_digits(index: usize, packed: &[u8]) -> Result<(), IsoError> {
+        match iso_field::bcd_to_ascii(packed) {
+            Err(NumericError::InvalidNibble { position, .. }) => Err(IsoError::InvalidCharacter {
+                index,
+                position: position / 2,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Marks the field present with `raw_value`, its length prefix included
+    fn store_field(&mut self, index: usize, raw_value: Vec<u8>, len_prefix: usize) {
+        let iso_field = &self.iso_spec.specs[index];
+        let field = &mut self.fields[index];
+        field.exist = true;
+        field.len = raw_value.len();
+        field.tag_len = len_prefix;
+        field.iso_field_label = Some(iso_field.label.clone());
+        field.iso_field_label_id = iso_field.label_id.clone();
+        field.char_type = iso_field.char_type.clone();
+        field.new_payload = Some(raw_value);
+    }
+
+    /// Copies field `index` of `source`, e.g. from a request to its response. The value is
+    /// set without its length prefix, so the field is re-encoded and checked against this
+    /// message spec, a value exceeding its length fails with the `set_field` error. A field
+    /// absent from `source` leaves this message unchanged.
+    pub fn copy_field_from(&mut self, source: &IsoMsg, index: usize) -> Result<(), IsoError> {
+        let field = match source.present_field(index) {
+            Ok(field) => field,
+            Err(IsoError::FieldNotSet { .. }) => return Ok(()),
+            Err(error) => return Err(error),
+        };
+        let value = source.field_text(index, field);
+        self.set_field(index, &value)
+    }
+
+    /// Sets a text field, refusing characters outside the field char type like
+    /// `set_field_checked` before the message validator sees the value
+    pub fn set_field_str(&mut self, index: usize, value: &str) -> Result<(), IsoError> {
+        let char_type = &self
+            .iso_spec
+            .specs
+            .get(index)
+            .ok_or(IsoError::InvalidField { index })?
+            .char_type;
+        self.iso_spec.char_types.validate_char_type(index, char_type, value.as_bytes())?;
+
+        self.set_field(index, value.as_bytes())
+    }
+
+    /// Same as `set_field`, also enforcing the character class of the field char type, e.g
+    /// digits only on `n` and `ns` fields or printable ASCII on `ans` fields. The first
+    /// refused byte is reported as `InvalidCharacter`. `set_field_str` checks text values the
+    /// same way, `set_field` leaves values to the message validator which accepts anything
+    /// by default.
+    pub fn set_field_checked(&mut self, index: usize, buffer: &[u8]) -> Result<(), IsoError> {
+        let char_type = &self
+            .iso_spec
+            .specs
+            .get(index)
+            .ok_or(IsoError::InvalidField { index })?
+            .char_type;
+        self.iso_spec.char_types.validate_char_type(index, char_type, buffer)?;
+
+        self.set_field(index, buffer)
+    }
+
+    /// Converts DE 4 with the DE 10 conversion rate and writes the result on DE 6
+    pub fn recompute_billing_amount(&mut self) -> Result<u64, IsoError> {
+        let billing_amount = self.converted_billing_amount()?;
+        let index = self.de_index(6);
+        self.set_field(index, &numeric::format_fixed(billing_amount, 12)?)?;
+
+        Ok(billing_amount)
+    }
+
+    /// Checks the received DE 6 against DE 4 converted with the DE 10 conversion rate
+    pub fn validate_billing_amount(&self, tolerance: u64) -> Result<(), IsoError> {
+        let recomputed = self.converted_billing_amount()?;
+        let received = self.numeric_field(self.de_index(6))?;
+
+        if recomputed.abs_diff(received) > tolerance {
+            return Err(IsoError::Conversion(ConversionError::Mismatch {
+                recomputed,
+                received,
+                tolerance,
+            }));
+        }
+        Ok(())
+    }
+
+    /// Checks the fields repeating another one, when both are present: the DE 35 track
+    /// must carry the DE 2 PAN, the DE 54 additional amounts of an original amount type
+    /// must match DE 4 and the DE 38 approval code must be as long as DE 27 tells
+    pub fn validate_linked_fields(&self) -> Result<(), IsoError> {
+        let is_present = |index| self.present_field(index).is_ok();
+        let (pan_index, track_index) = (self.de_index(2), self.de_index(35));
+        if is_present(pan_index) && is_present(track_index) {
+            let track = self.get_field_slice(track_index)?;
+            let track = Track2::parse(track).map_err(|error| IsoError::Track2 {
+                index: track_index,
+                error,
+            })?;
+            if track.pan != self.get_field_slice(pan_index)? {
+                return Err(IsoError::LinkedFieldMismatch {
+                    index: track_index,
+                    linked: pan_index,
+                });
+            }
+        }
+
+        let (amount_index, additional_index) = (self.de_index(4), self.de_index(54));
+        if is_present(amount_index) && is_present(additional_index) {
+            let amount = self.numeric_field(amount_index)?;
+            let additional = self.get_field_slice(additional_index)?;
+            let blocks = additional.chunks_exact(ADDITIONAL_AMOUNT_LEN);
+            if !blocks.remainder().is_empty() {
+                return Err(IsoError::InvalidLength {
+                    index: additional_index,
+                    expected: additional.len() - blocks.remainder().len() + ADDITIONAL_AMOUNT_LEN,
+                    got: additional.len(),
+                });
+            }
+            for (block_index, block) in blocks.enumerate() {
+                if !TRANSACTION_AMOUNT_TYPES.contains(&&block[2..4]) {
+                    continue;
+                }
+                let block_amount = numeric::parse_fixed(&block[8..]).map_err(|error| match error {
+                    NumericError::InvalidDigit { position } => IsoError::InvalidCharacter {
+                        index: additional_index,
+                        position: block_index * ADDITIONAL_AMOUNT_LEN + 8 + position,
+                    },
+                    error => IsoError::Numeric(error),
+                })?;
+                if block_amount != amount {
+                    return Err(IsoError::LinkedFieldMismatch {
+                        index: additional_index,
+                        linked: amount_index,
+                    });
+                }
+            }
+        }
+
+        let (length_index, code_index) = (self.de_index(27), self.de_index(38));
+        if is_present(length_index) && is_present(code_index) {
+            let length = self.get_field_num::<usize>(length_index)?;
+            if self.get_field_str(code_index)?.trim_end().len() != length {
+                return Err(IsoError::LinkedFieldMismatch {
+                    index: code_index,
+                    linked: length_index,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Stages changes of linked fields so they are applied together or not at all
+    pub fn transaction(&mut self) -> MsgTransaction<'_, 'a, 'b> {
+        MsgTransaction::new(self)
+    }
+
+    /// Parses the PDS sub-elements of a composite field once, e.g. DE 48, an absent field
+    /// gives an empty handle. See `CompositeHandle` for writing the changes back.
+    pub fn composite(&self, index: usize) -> Result<CompositeHandle, IsoError> {
+        match self.get_field_slice(index) {
+            Ok(value) => CompositeHandle::parse(index, Some(value)),
+            Err(IsoError::FieldNotSet { .. }) => CompositeHandle::parse(index, None),
+            Err(error) => Err(error),
+        }
+    }
+
+    pub(crate) fn fields_snapshot(&self) -> Vec<FieldPayload> {
+        self.fields.clone()
+    }
+
+    pub(crate) fn restore_fields(&mut self, fields: Vec<FieldPayload>) {
+        self.fields = fields;
+    }
+
+    fn converted_billing_amount(&self) -> Result<u64, IsoError> {
+        let amount = self.numeric_field(self.de_index(4))?;
+        let rate_index = self.de_index(10);
+        let rate_value = self.field_value(self.present_field(rate_index)?);
+        let rate: &[u8; 8] = rate_value.try_into().map_err(|_| IsoError::InvalidLength {
+            index: rate_index,
+            expected: 8,
+            got: rate_value.len(),
+        })?;
+
+        let billing_amount = ConversionRate::parse(rate)?.apply(amount);
+        if billing_amount > MAX_AMOUNT {
+            return Err(IsoError::Conversion(ConversionError::Overflow {
+                amount: billing_amount,
+            }));
+        }
+        Ok(billing_amount)
+    }
+
+    fn numeric_field(&self, index: usize) -> Result<u64, IsoError> {
+        let value = self.field_text(index, self.present_field(index)?);
+        numeric::parse_fixed(&value).map_err(|error| match error {
+            NumericError::InvalidDigit { position } => {
+                IsoError::InvalidCharacter { index, position }
+            }
+            error => IsoError::Numeric(error),
+        })
+    }
+
+    /// Reads a numeric field such as an amount or a counter, surrounding spaces and
+    /// leading zeros are dropped so an all zeros field reads as 0
+    pub fn get_field_num<T: FromStr>(&self, index: usize) -> Result<T, IsoError> {
+        let value = self.field_text(index, self.present_field(index)?);
+        let start = value.iter().take_while(|&&byte| byte == b' ').count();
+        let end = value.len() - value[start..].iter().rev().take_while(|&&b| b == b' ').count();
+        let digits = &value[start..end];
+        if digits.is_empty() {
+            return Err(IsoError::Numeric(NumericError::Empty));
+        }
+        if let Some(position) = digits.iter().position(|byte| !byte.is_ascii_digit()) {
+            return Err(IsoError::InvalidCharacter {
+                index,
+                position: start + position,
+            });
+        }
+
+        let significant = digits
+            .iter()
+            .position(|&byte| byte != b'0')
+            .unwrap_or(digits.len() - 1);
+        // only digits are left, parsing fails when the number doesn't fit in `T`
+        str::from_utf8(&digits[significant..])
+            .ok()
+            .and_then(|digits| digits.parse().ok())
+            .ok_or(IsoError::Numeric(NumericError::Overflow))
+    }
+
+    /// Writes a number on a numeric field, fixed fields are left padded with zeros to the
+    /// spec length while variable fields get the digits alone
+    pub fn set_field_num(&mut self, index: usize, value: u64) -> Result<(), IsoError> {
+        let iso_field = self.iso_spec.specs.get(index).ok_or(IsoError::InvalidField { index })?;
+        let digits = if iso_field.size_type == FieldSizeType::Fixed {
+            numeric::format_fixed(value, iso_field.length)?
+        } else {
+            value.to_string().into_bytes()
+        };
+
+        self.set_field(index, &digits)
+    }
+
+    /// Reads a signed amount, packed as BCD on fields declaring `IsoField::packed_amount`,
+    /// prefixed by `C` or `D` on `xn` fields, `D` being negative, unsigned digits otherwise
+    pub fn get_signed_amount(&self, index: usize) -> Result<i64, IsoError> {
+        let iso_field = self.iso_spec.specs.get(index).ok_or(IsoError::InvalidField { index })?;
+        if let Some(packed_amount) = &iso_field.packed_amount {
+            let value = self.field_value(self.present_field(index)?);
+            return Ok(packed_amount.decode(value)?);
+        }
+        if iso_field.char_type != FieldCharType::Iso8583_xn {
+            let amount = self.numeric_field(index)?;
+            return i64::try_from(amount).map_err(|_| IsoError::Numeric(NumericError::Overflow));
+        }
+
+        let value = self.field_value(self.present_field(index)?);
+        let negative = match value.first() {
+            Some(b'C') => false,
+            Some(b'D') => true,
+            _ => return Err(IsoError::InvalidCharacter { index, position: 0 }),
+        };
+        let amount = numeric::parse_fixed(&value[1..]).map_err(|error| match error {
+            NumericError::InvalidDigit { position } => IsoError::InvalidCharacter {
+                index,
+                position: position + 1,
+            },
+            error => IsoError::Numeric(error),
+        })?;
+        let amount =
+            i64::try_from(amount).map_err(|_| IsoError::Numeric(NumericError::Overflow))?;
+        Ok(if negative { -amount } else { amount })
+    }
+
+    /// Writes a signed amount the way `get_signed_amount` reads it, a negative amount on a
+    /// field which carries no sign is a `NumericError::Negative`
+    pub fn set_signed_amount(&mut self, index: usize, value: i64) -> Result<(), IsoError> {
+        let iso_field = self.iso_spec.specs.get(index).ok_or(IsoError::InvalidField { index })?;
+        if let Some(packed_amount) = &iso_field.packed_amount {
+            let packed = packed_amount.encode(value, iso_field.length)?;
+            return self.set_field(index, &packed);
+        }
+        if iso_field.char_type != FieldCharType::Iso8583_xn {
+            let amount = u64::try_from(value)
+                .map_err(|_| IsoError::Numeric(NumericError::Negative { value }))?;
+            return self.set_field_num(index, amount);
         }
+
+        let mut signed = vec![if value < 0 { b'D' } else { b'C' }];
+        if iso_field.size_type == FieldSizeType::Fixed {
+            let width = iso_field.length.saturating_sub(1);
+            signed.extend_from_slice(&numeric::format_fixed(value.unsigned_abs(), width)?);
+        } else {
+            signed.extend_from_slice(value.unsigned_abs().to_string().as_bytes());
+        }
+        self.set_field(index, &signed)
+    }
+
+    /// Spec index of a data element, falling back to `de` on specs without DE label ids
+    fn de_index(&self, de: usize) -> usize {
+        self.iso_spec.de_index(de).unwrap_or(de)
+    }
+
+    /// Copies the value of data element `de` (DE 2 for the PAN, DE 39 for the response
+    /// code), without its length prefix, returning how many bytes were written
+    pub fn get_iso_field(&self, de: usize, buffer: &mut [u8]) -> Result<usize, IsoError> {
+        let index = self.iso_spec.de_index(de).ok_or(IsoError::InvalidField { index: de })?;
+        let value = self.get_field_slice(index)?;
+        if value.len() > buffer.len() {
+            return Err(IsoError::BufferTooSmall {
+                needed: value.len(),
+                available: buffer.len(),
+            });
+        }
+
+        buffer[..value.len()].copy_from_slice(value);
+        Ok(value.len())
+    }
+
+    /// Text of a field as exported, binary fields are written as uppercase hexadecimal
+    fn export_value(&self, index: usize, field: &FieldPayload) -> String {
+        let value = self.field_text(index, field);
+        if self.iso_spec.specs[index].char_type.get_str("content_type") == Some("binary") {
+            iso_field::to_hex(&value)
+        } else {
+            String::from_utf8_lossy(&value).to_string()
+        }
+    }
+
+    /// Exports the present data elements keyed by their label, see `LabelKeys` for how
+    /// fields sharing a label are told apart
+    pub fn labeled_values(&self, label_keys: LabelKeys) -> BTreeMap<String, String> {
+        self.fields
+            .iter()
+            .enumerate()
+            .filter(|(index, field)| {
+                field.exist && self.iso_spec.specs[*index].size_type != FieldSizeType::BitMap
+            })
+            .map(|(index, field)| {
+                let key = self.iso_spec.label_key(index, label_keys);
+                (key, self.export_value(index, field))
+            })
+            .collect()
+    }
+
+    /// Structured value of a field, decoded by the codec registered for it on the spec,
+    /// fields without codec give their bytes as `FieldValue::Bytes`
+    pub fn value(&self, index: usize) -> Result<FieldValue, IsoError> {
+        let field = self.present_field(index)?;
+        match self.iso_spec.field_codecs.get(index) {
+            Some(codec) => self.decode_value(index, field, codec),
+            None => self.get_field_owned(index).map(FieldValue::Bytes),
+        }
+    }
+
+    /// Encodes `value` with the codec registered for the field and sets the result,
+    /// fields without codec take `FieldValue::Bytes` or `FieldValue::Text`
+    pub fn set_value(&mut self, index: usize, value: &FieldValue) -> Result<(), IsoError> {
+        let bytes = match (self.iso_spec.field_codecs.get(index), value) {
+            (Some(codec), _) => codec
+                .encode(value)
+                .map_err(|error| IsoError::Codec { index, error })?,
+            (None, FieldValue::Bytes(bytes)) => bytes.clone(),
+            (None, FieldValue::Text(text)) => text.as_bytes().to_vec(),
+            (None, _) => {
+                let error = CodecError::UnexpectedValue;
+                return Err(IsoError::Codec { index, error });
+            }
+        };
+        self.set_field(index, &bytes)
+    }
+
+    /// Copies the value of a field, without its length prefix
+    pub fn get_field_owned(&self, index: usize) -> Result<Vec<u8>, IsoError> {
+        let field = self.present_field(index)?;
+        if let FieldCharType::Custom(id) = self.iso_spec.specs[index].char_type {
+            let custom_type = self.iso_spec.char_types.custom_type(index, id)?;
+            return (custom_type.decode)(self.field_value(field)).ok_or_else(|| {
+                IsoError::Rejected {
+                    index,
+                    reason: format!("not a valid {} value", custom_type.name),
+                }
+            });
+        }
+
+        Ok(self.field_text(index, field).into_owned())
+    }
+
+    /// Borrows the value of a field as found on the payload, or as written by `set_field`,
+    /// without its length prefix. Nothing is decoded, packed BCD stays packed.
+    pub fn get_field_slice(&self, index: usize) -> Result<&[u8], IsoError> {
+        let field = self.present_field(index)?;
+        Ok(self.field_value(field))
+    }
+
+    /// Value of data element `de`, see `IsoSpecs::de_to_index` for the mapping to the spec
+    pub fn get_de(&self, de: u8) -> Result<&[u8], IsoError> {
+        self.get_field_slice(self.de_position(de)?)
+    }
+
+    /// Sets data element `de` through `set_field`
+    pub fn set_de(&mut self, de: u8, buffer: &[u8]) -> Result<(), IsoError> {
+        let index = self.de_position(de)?;
+        self.set_field(index, buffer)
+    }
+
+    /// Value of the field labelled `name`, see `IsoSpecs::field_index_by_name`
+    pub fn get_field_by_name(&self, name: &str) -> Result<&[u8], IsoError> {
+        self.get_field_slice(self.iso_spec.field_index_by_name(name)?)
+    }
+
+    /// Sets the field labelled `name` through `set_field`
+    pub fn set_field_by_name(&mut self, name: &str, value: &[u8]) -> Result<(), IsoError> {
+        let index = self.iso_spec.field_index_by_name(name)?;
+        self.set_field(index, value)
+    }
+
+    fn de_position(&self, de: u8) -> Result<usize, IsoError> {
+        if de < 2 {
+            return Err(IsoError::ReservedDataElement { de });
+        }
+
+        self.iso_spec
+            .de_to_index(de)
+            .ok_or(IsoError::UnknownDataElement { de })
+    }
+
+    /// Sets a field from its decoded value, custom char types encode it before it is set
+    pub fn set_field_decoded(&mut self, index: usize, value: &[u8]) -> Result<(), IsoError> {
+        match self.iso_spec.specs.get(index).map(|field| &field.char_type) {
+            Some(FieldCharType::Custom(id)) => {
+                let custom_type = self.iso_spec.char_types.custom_type(index, *id)?;
+                self.set_field(index, &(custom_type.encode)(value))
+            }
+            Some(_) => self.set_field(index, value),
+            None => Err(IsoError::InvalidField { index }),
+        }
+    }
+
+    /// Value of a text field, without its length prefix, borrowed from the payload except
+    /// for packed BCD fields which are expanded into their digits
+    pub fn get_field_str(&self, index: usize) -> Result<Cow<'_, str>, IsoError> {
+        let field = self.present_field(index)?;
+        if self.iso_spec.specs[index].char_type.get_str("content_type") == Some("binary") {
+            return Err(IsoError::NotTextField { index });
+        }
+
+        match self.field_text(index, field) {
+            Cow::Borrowed(value) => str::from_utf8(value)
+                .map(Cow::Borrowed)
+                .map_err(|error| IsoError::Utf8 { index, error }),
+            Cow::Owned(value) => String::from_utf8(value)
+                .map(Cow::Owned)
+                .map_err(|error| IsoError::Utf8 {
+                    index,
+                    error: error.utf8_error(),
+                }),
+        }
+    }
+
+    /// Owned counterpart of `get_field_str`
+    pub fn get_field_string(&self, index: usize) -> Result<String, IsoError> {
+        self.get_field_str(index).map(Cow::into_owned)
+    }
+
+    /// DE 2 for logs and audit trails, only its first 6 and last 4 digits left visible
+    pub fn masked_pan(&self) -> Result<String, IsoError> {
+        self.mask_field(self.de_index(2), 6, 4, '*')
+    }
+
+    /// Text of a field with every character but the first `keep_prefix` and the last
+    /// `keep_suffix` replaced by `mask_char`. A value too short to hide anything between
+    /// them is masked whole rather than shown.
+    pub fn mask_field(
+        &self,
+        index: usize,
+        keep_prefix: usize,
+        keep_suffix: usize,
+        mask_char: char,
+    ) -> Result<String, IsoError> {
+        let value = self.get_field_string(index)?;
+        let len = value.chars().count();
+        if len <= keep_prefix + keep_suffix {
+            return Ok(std::iter::repeat(mask_char).take(len).collect());
+        }
+
+        Ok(value
+            .chars()
+            .enumerate()
+            .map(|(position, c)| {
+                if position < keep_prefix || position >= len - keep_suffix {
+                    c
+                } else {
+                    mask_char
+                }
+            })
+            .collect())
+    }
+
+    /// Padding of a fixed field, the one of the spec or else the one seen at parse time
+    fn field_padding(&self, index: usize) -> FieldPadding {
+        match self.iso_spec.specs[index].padding {
+            FieldPadding::None => self.fields[index].detected_pad,
+            padding => padding,
+        }
+    }
+
+    /// Same as `get_field_str` with the field padding stripped, see `IsoField::padding`
+    pub fn get_field_str_trimmed(&self, index: usize) -> Result<Cow<'_, str>, IsoError> {
+        // padding bytes are ascii, so trimming them keeps the value valid utf-8
+        fn trim(padding: FieldPadding, value: &str) -> &str {
+            str::from_utf8(padding.trim(value.as_bytes())).unwrap_or(value)
+        }
+
+        let padding = self.field_padding(index);
+        Ok(match self.get_field_str(index)? {
+            Cow::Borrowed(value) => Cow::Borrowed(trim(padding, value)),
+            Cow::Owned(value) => Cow::Owned(trim(padding, &value).to_string()),
+        })
+    }
+
+    /// Bytes taken by the length prefix of the field, see `IsoField::length_encoding`
+    pub fn get_field_length_prefix(&self, index: usize) -> usize {
+        let iso_field = &self.iso_spec.specs[index];
+        iso_field
+            .length_encoding
+            .prefix_len(iso_field.size_type.prefix_digits())
     }
 
     pub fn present_fields(&self) -> Vec<&FieldPayload> {
         self.fields.iter().filter(|f| f.exist).collect()
     }
 
+    /// Spec indexes of the present fields in ascending order, the mti included. The bitmap
+    /// is left out as it is generated from the other fields, see `bitmap_bits`.
+    pub fn present_field_indices(&self) -> Vec<usize> {
+        let bitmap_index = self.iso_spec.bitmap_index();
+        (0..self.fields.len())
+            .filter(|&index| Some(index) != bitmap_index && self.has_field(index))
+            .collect()
+    }
+
+    /// Whether the field at spec `index` holds a value, the bitmap included, without
+    /// reading it. Indexes past the spec are not present.
+    pub fn has_field(&self, index: usize) -> bool {
+        self.fields.get(index).is_some_and(|field| field.exist)
+    }
+
+    /// Bits of the bitmap as written for the present fields, the parsed bitmap as long as
+    /// no field was set or removed. Bit 0 announces the secondary bitmap, bit `n - 1` DE `n`.
+    pub fn bitmap_bits(&self) -> BitArray<u64, U192> {
+        if self.iso_spec.bitmap_index().is_none() {
+            return BitArray::<u64, U192>::from_elem(false);
+        }
+        // the rebuilt bitmaps always hold at least the primary one
+        IsoMsg::process_bitmaps(&self.build_bitmap())
+            .unwrap_or_else(|_| BitArray::<u64, U192>::from_elem(false))
+    }
+
+    /// Data element numbers set on the bitmap rebuilt from the present fields, in ascending
+    /// order. DE 1 is listed when a secondary bitmap is needed, DE 65 for a tertiary one.
+    pub fn present_de_numbers(&self) -> Vec<usize> {
+        if self.iso_spec.bitmap_index().is_none() {
+            return vec![];
+        }
+
+        let bitmap = self.build_bitmap();
+        (0..bitmap.len() * 8)
+            .filter(|bit| bitmap[bit / 8] & (0x80 >> (bit % 8)) != 0)
+            .map(|bit| bit + 1)
+            .collect()
+    }
+
+    /// One line per present field, see the alternate `Display` form
+    fn write_field_lines(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (index, field) in self.fields.iter().enumerate() {
+            if !field.exist {
+                continue;
+            }
+            let iso_field = &self.iso_spec.specs[index];
+            let value = self.field_text(index, field);
+            writeln!(
+                f,
+                "DE{:>3} ({})  [{}]: {}",
+                index,
+                iso_field.label,
+                self.field_value(field).len(),
+                display_value(iso_field, &value)
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Walks the present fields in index order, values set by `set_field` included,
+    /// without allocating
+    pub fn iter(&self) -> FieldViews<'_, 'a, 'b> {
+        FieldViews {
+            iso_msg: self,
+            fields: self.fields.iter().enumerate(),
+        }
+    }
+
+    /// Same walk as `iter`, yielding the spec definition of each field instead of its label
+    pub fn iter_fields(&self) -> impl Iterator<Item = (usize, &IsoField, &[u8])> {
+        self.fields
+            .iter()
+            .enumerate()
+            .filter(|(_, field)| field.exist)
+            .map(move |(index, field)| {
+                (index, &self.iso_spec.specs[index], self.field_value(field))
+            })
+    }
+
+    /// Gathers the spec metadata, raw bytes and decoded value of a field
+    pub fn describe_field(&self, index: usize) -> Option<FieldDescription> {
+        let field = self.fields.get(index)?;
+        let iso_field = self.iso_spec.specs.get(index)?;
+
+        let (raw_hex, decoded) = if field.exist {
+            let raw_hex = iso_field::to_hex(field.raw_value(self.payload.deref()));
+            (raw_hex, field.get_ipm_value(self.payload.deref()).ok())
+        } else {
+            ("".to_string(), None)
+        };
+
+        Some(FieldDescription {
+            index,
+            label: iso_field.label.clone(),
+            char_type: iso_field.char_type.clone(),
+            size_type: iso_field.size_type.clone(),
+            max_len: iso_field.length,
+            present: field.exist,
+            raw_hex,
+            decoded,
+            byte_offset: field.index,
+            byte_len: field.len,
+        })
+    }
+
+    fn present_field(&self, index: usize) -> Result<&FieldPayload, IsoError> {
+        match self.fields.get(index) {
+            Some(field) if field.exist => Ok(field),
+            Some(_) => Err(IsoError::FieldNotSet { index }),
+            None => Err(IsoError::InvalidField { index }),
+        }
+    }
+
+    fn field_value<'s>(&'s self, field: &'s FieldPayload) -> &'s [u8] {
+        &field.raw_value(self.payload.deref())[field.tag_len..]
+    }
+
+    fn decode_value(
+        &self,
+        index: usize,
+        field: &FieldPayload,
+        codec: &dyn FieldCodec,
+    ) -> Result<FieldValue, IsoError> {
+        codec
+            .decode(self.field_value(field))
+            .map_err(|error| IsoError::Codec { index, error })
+    }
+
+    /// Value of a present field with packed BCD expanded into its ascii digits
+    fn field_text<'s>(&'s self, index: usize, field: &'s FieldPayload) -> Cow<'s, [u8]> {
+        let value = self.field_value(field);
+        if self.iso_spec.specs[index].char_type != FieldCharType::Iso8583_n_bcd {
+            return Cow::Borrowed(value);
+        }
+
+        let digit_count = if field.tag_len > 0 {
+            let tag = &field.raw_value(self.payload.deref())[..field.tag_len];
+            let length_encoding = self.iso_spec.specs[index].length_encoding;
+            length_encoding.decode(tag).unwrap_or(0)
+        } else {
+            self.iso_spec.specs[index].length
+        };
+        // parsing and the setters refuse nibbles above 9, the packed bytes are only left
+        // as they are should one get through
+        let digits = match IsoMsg::bcd_to_ascii(value) {
+            Ok(digits) => digits,
+            Err(_) => return Cow::Borrowed(value),
+        };
+        let padding = digits.len().saturating_sub(digit_count);
+        Cow::Owned(digits[padding..].to_vec())
+    }
+
     #[deprecated(
         since = "0.1.1",
         note = "please use `FieldPayload#iso_field_value` instead"
     )]
-    pub fn get_field(&self, index: usize, buffer: &mut [u8]) -> Result<usize, &str> {
-        let res = self.get_field_raw(index, buffer);
-        if res.is_err() {
-            return Err(res.err().unwrap());
+    /// Copies the value of a field, without its length prefix, to the start of `buffer` and
+    /// returns its length. Whatever the size type, and whether the field was parsed or set,
+    /// the buffer must hold at least the value length, `BufferTooSmall::needed` otherwise.
+    pub fn get_field(&self, index: usize, buffer: &mut [u8]) -> Result<usize, IsoError> {
+        let field = self.readable_field(index)?;
+        let value = self.field_value(field);
+        if buffer.len() < value.len() {
+            return Err(IsoError::BufferTooSmall {
+                needed: value.len(),
+                available: buffer.len(),
+            });
         }
 
-        let (len, field_len_prefix) = res.unwrap();
-        if field_len_prefix > 0 {
-            let temp_buff = buffer[field_len_prefix..len].to_vec();
-            buffer[0..len - field_len_prefix].copy_from_slice(&temp_buff[..]);
-        }
-        Ok(len - field_len_prefix)
+        buffer[..value.len()].copy_from_slice(value);
+        Ok(value.len())
     }
 
-    fn get_field_raw(&self, index: usize, buffer: &mut [u8]) -> Result<(usize, usize), &str> {
-        assert!(index < self.fields.len());
-        let field = &self.fields[index];
-        if !field.exist {
-            return Err("Field not set");
+    fn get_field_raw(
+        &self,
+        index: usize,
+        buffer: &mut [u8],
+    ) -> Result<(usize, usize), IsoError> {
+        let field = self.readable_field(index)?;
+        if buffer.len() < field.len {
+            return Err(IsoError::BufferTooSmall {
+                needed: field.len,
+                available: buffer.len(),
+            });
         }
 
+        let len_prefix = self.get_field_length_prefix(index);
+        buffer[0..field.len].copy_from_slice(field.raw_value(self.payload.deref()));
+        Ok((field.len, len_prefix))
+    }
+
+    /// Present field whose value lies within the payload
+    fn readable_field(&self, index: usize) -> Result<&FieldPayload, IsoError> {
+        let field = self.present_field(index)?;
         if field.len == 0 {
-            return Err("Field not set");
+            return Err(IsoError::FieldNotSet { index });
         }
-        if buffer.len() >= field.len && self.payload.len() >= (field.len + field.index) {
-            let len_prefix = self.get_field_length_prefix(index);
-            buffer[0..field.len]
-                .copy_from_slice(&self.payload[field.index..field.index + field.len]);
-            Ok((field.len, len_prefix))
-        } else {
-            Err("Input buffer is smaller than field value")
+        if field.new_payload.is_none() && self.payload.len() < field.len + field.index {
+            return Err(IsoError::UnexpectedEof {
+                field_index: index,
+                needed: field.len + field.index,
+                available: self.payload.len(),
+            });
         }
+
+        Ok(field)
     }
 
     pub fn is_bit_set(input: u32, n: u8) -> bool {
@@ -156,10 +1622,43 @@ impl<'a, 'b> IsoMsg<'a, 'b> {
         }
     }
 
-    pub fn process_bitmap(bitmap_bytes: &[u8]) -> BitArray<u64, U128> {
-        let bitmap = &bitmap_bytes[0..16]; //this is taking into account that there will always be a secundary bitmap
+    /// Bits of the primary bitmap and of the secondary one when announced and given,
+    /// `TruncatedBitmap` for less than the 8 bytes of a primary bitmap
+    pub fn process_bitmap(bitmap_bytes: &[u8]) -> Result<BitArray<u64, U128>, IsoError> {
+        IsoMsg::check_primary_bitmap(bitmap_bytes)?;
+        // the first bit tells if the secondary bitmap follows the primary one
+        let bitmap = if bitmap_bytes[0] & 0x80 != 0 && bitmap_bytes.len() >= 16 {
+            &bitmap_bytes[0..16]
+        } else {
+            &bitmap_bytes[0..8]
+        };
+
+        Ok(BitArray::<u64, U128>::from_bytes(bitmap))
+    }
+
+    /// Same as `process_bitmap` following a tertiary bitmap as well, announced by the
+    /// first bit of the secondary bitmap
+    pub fn process_bitmaps(bitmap_bytes: &[u8]) -> Result<BitArray<u64, U192>, IsoError> {
+        IsoMsg::check_primary_bitmap(bitmap_bytes)?;
+        let mut bitmap_len = 8;
+        while bitmap_len < 24
+            && bitmap_bytes.len() >= bitmap_len + 8
+            && bitmap_bytes[bitmap_len - 8] & 0x80 != 0
+        {
+            bitmap_len += 8;
+        }
+
+        Ok(BitArray::<u64, U192>::from_bytes(&bitmap_bytes[..bitmap_len]))
+    }
 
-        BitArray::<u64, U128>::from_bytes(bitmap)
+    fn check_primary_bitmap(bitmap_bytes: &[u8]) -> Result<(), IsoError> {
+        if bitmap_bytes.len() < 8 {
+            return Err(IsoError::TruncatedBitmap {
+                needed: 8,
+                available: bitmap_bytes.len(),
+            });
+        }
+        Ok(())
     }
 
     pub fn convert_u32_be(array: &[u8]) -> u32 {
@@ -178,107 +1677,466 @@ impl<'a, 'b> IsoMsg<'a, 'b> {
             + (u32::from(array[3]) << 24)
     }
 
-    pub fn to_byte_array(&self, buffer: &mut [u8]) -> usize {
+    /// Serializes the message into `buffer`, returning the amount of bytes written.
+    /// `serialized_len` tells the size needed, a shorter buffer gives `BufferTooSmall`
+    /// and is left untouched.
+    pub fn to_byte_array(&self, buffer: &mut [u8]) -> Result<usize, IsoError> {
+        let bitmap_field_index = self.iso_spec.bitmap_index();
+        let bitmap = bitmap_field_index.map(|_| self.bitmap_encoding.encode(&self.build_bitmap()));
+        let needed = self.fields_len() + bitmap.as_ref().map_or(0, Vec::len);
+        if needed > buffer.len() {
+            return Err(IsoError::BufferTooSmall {
+                needed,
+                available: buffer.len(),
+            });
+        }
+
         let mut buffer_index = 0usize;
-        let num_iteration: usize = (self.iso_spec.specs.len() - 1 + 63) / 128;
-        let mut bit_arrays = Vec::<BitArray<u64, U128>>::with_capacity(num_iteration);
-        for _ in 0..num_iteration {
-            bit_arrays.push(BitArray::<u64, U128>::from_elem(false));
+        // TODO maybe implement a pointer solution, since we will get a lot of messages one after another
+        for (index, field) in self.fields.iter().enumerate() {
+            if Some(index) == bitmap_field_index {
+                let bitmap = bitmap.as_deref().unwrap_or_default();
+                buffer[buffer_index..buffer_index + bitmap.len()].copy_from_slice(bitmap);
+                trace!(
+                    "serialize: index:{}, bitmap, offset:{}, len:{}",
+                    index,
+                    buffer_index,
+                    bitmap.len()
+                );
+                buffer_index += bitmap.len();
+            } else if field.exist && field.len > 0 {
+                let (field_total_len, _) = self.get_field_raw(index, &mut buffer[buffer_index..])?;
+                trace!(
+                    "serialize: index:{}, label:{}, offset:{}, len:{}",
+                    index,
+                    self.iso_spec.specs[index].label,
+                    buffer_index,
+                    field_total_len
+                );
+                buffer_index += field_total_len;
+            }
         }
-        let mut bit_array_index = 0;
-        let mut bit_index = 0;
-        let mut bitmap_field_index = 0;
+        Ok(buffer_index)
+    }
 
-        let mut bitmap_found = false;
+    /// Exact size of the serialized message, bitmap included as rebuilt from the present
+    /// fields
+    pub fn serialized_len(&self) -> usize {
+        let bitmap_len = match self.iso_spec.bitmap_index() {
+            Some(_) => self.bitmap_encoding.encode(&self.build_bitmap()).len(),
+            None => 0,
+        };
+        self.fields_len() + bitmap_len
+    }
 
-        //XXX seems here that the lib is expecting to parse the mti; the bitmap; the fields itself lol
-        // TODO maybe implement a pointer solution, since we will get a lot of messages one after another
-        for index in 0..self.fields.len() {
-            bit_array_index = index / 128;
-
-            let is_a_bitmap = !bitmap_found
-                && (self.iso_spec.specs[index].char_type == FieldCharType::Iso8583_bmps);
-            if is_a_bitmap {
-                bitmap_field_index = index;
-                bitmap_found = true;
-                bit_index = buffer_index;
-                let res = self.get_field_raw(index, &mut buffer[buffer_index..]);
-                if res.is_ok() {
-                    let (field_total_len, _) = res.unwrap();
-                    buffer_index += field_total_len;
+    /// Length of the present fields, the bitmap left out
+    fn fields_len(&self) -> usize {
+        let bitmap_field_index = self.iso_spec.bitmap_index();
+        self.fields
+            .iter()
+            .enumerate()
+            .filter(|&(index, field)| field.exist && Some(index) != bitmap_field_index)
+            .map(|(_, field)| field.len)
+            .sum()
+    }
+
+    /// Encodes every message behind its length header, back to back, returning the amount
+    /// of bytes written
+    pub fn encode_batch(
+        messages: &[IsoMsg],
+        framing: LengthHeader,
+        buffer: &mut [u8],
+    ) -> Result<usize, IsoError> {
+        let mut buffer_index = 0usize;
+
+        for message in messages {
+            let encoded = message.to_vec()?;
+            let header = framing.encode(encoded.len())?;
+            let needed = buffer_index + header.len() + encoded.len();
+            if needed > buffer.len() {
+                return Err(IsoError::BufferTooSmall {
+                    needed,
+                    available: buffer.len(),
+                });
+            }
+
+            buffer[buffer_index..buffer_index + header.len()].copy_from_slice(&header);
+            buffer_index += header.len();
+            buffer[buffer_index..needed].copy_from_slice(&encoded);
+            buffer_index = needed;
+        }
+
+        Ok(buffer_index)
+    }
+
+    /// Parses every message of a buffer written by `encode_batch`
+    pub fn parse_all(
+        iso_spec: &'b IsoSpecs,
+        payload: &'a [u8],
+        framing: LengthHeader,
+    ) -> Result<Vec<IsoMsg<'a, 'b>>, IsoError> {
+        let mut messages = vec![];
+        let mut payload_index = 0usize;
+
+        while payload_index < payload.len() {
+            let available = payload.len() - payload_index;
+            let len = framing
+                .decode(&payload[payload_index..])
+                .ok_or(IsoError::TruncatedFrame {
+                    needed: framing.header_len(),
+                    available,
+                })?;
+            let start = payload_index + framing.header_len();
+            if start + len > payload.len() {
+                return Err(IsoError::TruncatedFrame {
+                    needed: framing.header_len() + len,
+                    available,
+                });
+            }
+
+            messages.push(IsoMsg::try_new(iso_spec, &payload[start..start + len])?);
+            payload_index = start + len;
+        }
+
+        Ok(messages)
+    }
+
+    /// Serializes the message into a vector of exactly `serialized_len` bytes
+    pub fn to_vec(&self) -> Result<Vec<u8>, IsoError> {
+        let mut buffer = vec![0u8; self.serialized_len()];
+
+        let written = self.to_byte_array(&mut buffer)?;
+        buffer.truncate(written);
+        Ok(buffer)
+    }
+
+    /// Serializes the message only when it passes `validate`, otherwise every
+    /// validation error is returned
+    pub fn to_vec_validated(&self) -> Result<Vec<u8>, Vec<IsoError>> {
+        self.validate()?;
+        self.to_vec().map_err(|error| vec![error])
+    }
+
+    /// Checks every present field against the spec lengths and char types, along with the
+    /// track 2 grammar of `z` fields
+    pub fn validate(&self) -> Result<(), Vec<IsoError>> {
+        let report = self.validate_with(ValidationMode::Strict);
+        if report.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(report.errors)
+        }
+    }
+
+    /// Indexes of the fields the spec marks as required but absent from the message, an
+    /// empty list when the message carries every mandatory field
+    pub fn validate_mandatory_fields(&self) -> Result<Vec<usize>, IsoError> {
+        let mut missing = vec![];
+        for (index, iso_field) in self.iso_spec.specs.iter().enumerate() {
+            if !iso_field.required {
+                continue;
+            }
+            // the bitmap is rebuilt on serialization, it is never missing
+            if self.iso_spec.bitmap_index() == Some(index) {
+                continue;
+            }
+            let field = self.fields.get(index).ok_or(IsoError::InvalidField { index })?;
+            if !field.exist {
+                missing.push(index);
+            }
+        }
+
+        Ok(missing)
+    }
+
+    /// Same checks as `validate`, the structural ones being warnings in lenient mode
+    pub fn validate_with(&self, mode: ValidationMode) -> ValidationReport {
+        let mut errors: Vec<IsoError> = vec![];
+        let mut warnings: Vec<IsoError> = vec![];
+
+        for (index, field) in self.fields.iter().enumerate() {
+            let iso_field = &self.iso_spec.specs[index];
+            if !field.exist || iso_field.size_type == FieldSizeType::BitMap {
+                continue;
+            }
+
+            let value = self.field_text(index, field);
+            match iso_field.size_type {
+                FieldSizeType::Fixed if value.len() != iso_field.length => {
+                    errors.push(IsoError::InvalidLength {
+                        index,
+                        expected: iso_field.length,
+                        got: value.len(),
+                    })
                 }
-            } else {
-                let res = self.get_field_raw(index, &mut buffer[buffer_index..]);
-                if res.is_ok() {
-                    if bitmap_found {
-                        bit_arrays[bit_array_index].set(index - bitmap_field_index, true);
-                        trace!(
-                            "index:{}, buffer[buffer_index..]:{}",
+                FieldSizeType::LlVar
+                | FieldSizeType::LllVar
+                | FieldSizeType::LlllVar
+                | FieldSizeType::LlllllVar
+                    if value.len() > iso_field.length =>
+                {
+                    errors.push(IsoError::ValueTooLong {
+                        index,
+                        max: iso_field.length,
+                        got: value.len(),
+                    })
+                }
+                _ => {}
+            }
+
+            if iso_field.char_type.get_str("content_type") == Some("number") {
+                if let Some(position) = value.iter().position(|byte| !byte.is_ascii_digit()) {
+                    errors.push(IsoError::InvalidCharacter { index, position });
+                }
+            }
+            if field.tag_len > 0 {
+                let raw_value = field.raw_value(self.payload.deref());
+                match IsoMsg::get_field_length(iso_field, raw_value) {
+                    Ok((len, _)) if len != raw_value.len() => {
+                        errors.push(IsoError::PrefixMismatch {
                             index,
-                            str::from_utf8(&buffer[buffer_index..]).unwrap()
-                        );
+                            announced: len - field.tag_len,
+                            got: raw_value.len() - field.tag_len,
+                        })
                     }
-                    let (field_total_len, _) = res.unwrap();
-                    buffer_index += field_total_len;
+                    Ok(_) => {}
+                    Err(error) => errors.push(error),
                 }
             }
-        }
-        //override bitmap XXX but why??????
-        let mut bitmap = String::with_capacity(bit_array_index * 16);
-        for (i, bit_array_item) in bit_arrays.iter_mut().enumerate().take(bit_array_index) {
-            if i == 0 && bit_array_item.len() > 64 {
-                bit_array_item.set(0, true);
+            if let Some(codec) = self.iso_spec.field_codecs.get(index) {
+                if let Err(error) = self.decode_value(index, field, codec) {
+                    errors.push(error);
+                }
             }
-            let bytes = bit_array_item.to_bytes();
-            let mut byte_index = 0;
-
-            while byte_index < bytes.len() {
-                let ms_str = IsoMsg::convert_u32_be(&bytes[byte_index..byte_index + 4]);
-                byte_index += 4;
-                bitmap.push_str(&format!("{:08X}", ms_str));
+            if let FieldCharType::Custom(_) = iso_field.char_type {
+                let char_types = &self.iso_spec.char_types;
+                let char_type = &iso_field.char_type;
+                if let Err(error) = char_types.validate_char_type(index, char_type, &value) {
+                    errors.push(error);
+                }
+            }
+            if iso_field.char_type == FieldCharType::Iso8583_z {
+                if let Err(error) = Track2::parse(&value) {
+                    let error = IsoError::Track2 { index, error };
+                    match mode {
+                        ValidationMode::Strict => errors.push(error),
+                        ValidationMode::Lenient => warnings.push(error),
+                    }
+                }
             }
         }
-        buffer[bit_index..bitmap.len() + bit_index]
-            .copy_from_slice(&bitmap.as_bytes()[0..bitmap.len()]);
-        buffer_index
+
+        ValidationReport { errors, warnings }
     }
 
-    pub fn get_field_length(iso_field: &IsoField, input_buffer: &[u8]) -> (usize, usize) {
-        match iso_field.size_type {
-            FieldSizeType::Fixed | FieldSizeType::BitMap => (iso_field.length, 0),
-            FieldSizeType::LlVar => {
-                let str_digits = str::from_utf8(&input_buffer[0..2]).unwrap();
-                (usize::from_str_radix(str_digits, 10).unwrap() + 2, 2)
+    /// Rebuilds the raw bitmap bytes from the present fields, the secondary bitmap
+    /// is only emitted when a field above 64 is present and the tertiary one, on specs
+    /// allowing it, when a field above 128 is present
+    fn build_bitmap(&self) -> Vec<u8> {
+        let mut bit_array = BitArray::<u64, U192>::from_elem(false);
+        let mut has_tertiary = false;
+
+        if let Some(bitmap_field_index) = self.iso_spec.bitmap_index() {
+            let bitmap_field = &self.iso_spec.specs[bitmap_field_index];
+            let max_bitmaps = IsoMsg::max_bitmaps(self.iso_spec, bitmap_field);
+            let data_elements = self.fields.iter().enumerate().skip(bitmap_field_index + 1);
+            for (index, field) in data_elements {
+                let bit = index - bitmap_field_index;
+                if field.exist && bit < 64 * max_bitmaps {
+                    bit_array.set(bit, true);
+                }
             }
-            FieldSizeType::LllVar => {
-                let str_digits = str::from_utf8(&input_buffer[0..3]).unwrap();
-                (usize::from_str_radix(str_digits, 10).unwrap() + 3, 3)
+
+            if max_bitmaps == 3 {
+                has_tertiary = (128..bit_array.len()).any(|bit| bit_array[bit]);
+                bit_array.set(TERTIARY_BIT, has_tertiary);
             }
-            FieldSizeType::LlllVar => {
-                let str_digits = str::from_utf8(&input_buffer[0..4]).unwrap();
-                (usize::from_str_radix(str_digits, 10).unwrap() + 4, 4)
+        }
+
+        let has_secondary_bitmap = (64..bit_array.len()).any(|bit| bit_array[bit]);
+        bit_array.set(0, has_secondary_bitmap);
+
+        let mut bitmap = bit_array.to_bytes();
+        bitmap.truncate(match (has_secondary_bitmap, has_tertiary) {
+            (_, true) => 24,
+            (true, false) => 16,
+            (false, false) => 8,
+        });
+        bitmap
+    }
+
+    /// Payload length of a field along with the length of its prefix, variable length
+    /// prefixes must be digits in the field `LengthEncoding` and can't announce more than
+    /// the spec maximum
+    pub fn get_field_length(
+        iso_field: &IsoField,
+        input_buffer: &[u8],
+    ) -> Result<(usize, usize), IsoError> {
+        // packed BCD lengths count digits, two of them per byte
+        let byte_len = |len: usize| match iso_field.char_type {
+            FieldCharType::Iso8583_n_bcd => (len + 1) / 2,
+            _ => len,
+        };
+
+        let prefix_digits = iso_field.size_type.prefix_digits();
+        if prefix_digits == 0 {
+            return Ok((byte_len(iso_field.length), 0));
+        }
+        let tag_len = iso_field.length_encoding.prefix_len(prefix_digits);
+
+        let prefix = &input_buffer[..tag_len.min(input_buffer.len())];
+        let invalid_prefix = || IsoError::InvalidLengthPrefix {
+            label_id: iso_field.label_id.clone(),
+            prefix: prefix.to_vec(),
+        };
+        if prefix.len() < tag_len {
+            return Err(invalid_prefix());
+        }
+
+        let len = iso_field
+            .length_encoding
+            .decode(prefix)
+            .ok_or_else(invalid_prefix)?;
+        if len > iso_field.length {
+            return Err(invalid_prefix());
+        }
+        Ok((byte_len(len) + tag_len, tag_len))
+    }
+
+    /// Expands packed BCD into ascii digits, two digits per byte, failing on a nibble above 9
+    pub fn bcd_to_ascii(input: &[u8]) -> Result<Vec<u8>, NumericError> {
+        iso_field::bcd_to_ascii(input)
+    }
+
+    /// Packs ascii digits as BCD, odd amounts of digits are padded with a leading zero,
+    /// failing on anything but a digit
+    pub fn ascii_to_bcd(input: &[u8]) -> Result<Vec<u8>, NumericError> {
+        iso_field::ascii_to_bcd(input)
+    }
+
+    /// Number of payload bytes taken by the bitmaps, the secondary bitmap is only read
+    /// when flagged by the first bit and when the bitmap field length allows it
+    fn bitmap_length(
+        iso_spec: &IsoSpecs,
+        bitmap_encoding: BitmapEncoding,
+        iso_field: &IsoField,
+        input_buffer: &[u8],
+    ) -> Result<usize, IsoError> {
+        let max_bitmaps = IsoMsg::max_bitmaps(iso_spec, iso_field);
+        let bytes_per_bitmap = bitmap_encoding.bytes_per_bitmap();
+
+        let mut bitmaps = 1;
+        // a bitmap which can't be read yet is reported by the caller bounds check
+        while bitmaps < max_bitmaps && input_buffer.len() >= bitmaps * bytes_per_bitmap {
+            let start = (bitmaps - 1) * bytes_per_bitmap;
+            let bitmap = bitmap_encoding
+                .decode(&input_buffer[start..start + bytes_per_bitmap])
+                .map_err(|error| match error {
+                    IsoError::InvalidBitmap { position } => IsoError::InvalidBitmap {
+                        position: start + position,
+                    },
+                    error => error,
+                })?;
+            if bitmap[0] & 0x80 == 0 {
+                break;
             }
+            bitmaps += 1;
         }
+        Ok(bitmaps * bytes_per_bitmap)
     }
 
-    //return a Result, create a debug param?
-    pub fn from_byte_array(iso_spec: &IsoSpecs, input_buffer: &[u8]) -> Vec<FieldPayload> {
-        let mut payload_index = 0usize;
+    /// Spec index matching the bit which announces the tertiary bitmap, on specs allowing
+    /// one, that index can't hold a field
+    fn tertiary_bit_index(iso_spec: &IsoSpecs) -> Option<usize> {
+        let bitmap_index = iso_spec.bitmap_index()?;
+        let max_bitmaps = IsoMsg::max_bitmaps(iso_spec, &iso_spec.specs[bitmap_index]);
+        (max_bitmaps == 3).then_some(bitmap_index + TERTIARY_BIT)
+    }
 
-        let bit_array = &IsoMsg::process_bitmap(&input_buffer[4..4 + 16]);
+    /// How many bitmaps the bitmap field holds at most, the spec length counts bitmaps
+    /// written with the spec encoding
+    fn max_bitmaps(iso_spec: &IsoSpecs, iso_field: &IsoField) -> usize {
+        (iso_field.length / iso_spec.bitmap_encoding.bytes_per_bitmap()).min(3)
+    }
 
-        let mut fields = Vec::with_capacity(iso_spec.specs.len());
+    /// Locates every field of the payload, variable lengths are checked against the spec
+    /// maximum and every field against the end of the payload
+    pub fn from_byte_array(
+        iso_spec: &IsoSpecs,
+        input_buffer: &[u8],
+    ) -> Result<Vec<FieldPayload>, IsoError> {
+        IsoMsg::parse_fields(iso_spec, input_buffer).map(|(fields, _)| fields)
+    }
 
-        for iso_spec_index in 0..iso_spec.specs.len() {
-            let iso_field: &IsoField = &iso_spec.specs[iso_spec_index];
-            let is_a_mti_or_bitmap = iso_spec_index == 0 || iso_spec_index == 1;
+    /// Locates every field of the payload, along with the bitmap encoding it was read with
+    fn parse_fields(
+        iso_spec: &IsoSpecs,
+        input_buffer: &[u8],
+    ) -> Result<(Vec<FieldPayload>, BitmapEncoding), IsoError> {
+        let mut payload_index = 0usize;
+        let mut bitmap_encoding = iso_spec.bitmap_encoding;
+        // the bitmap position on the spec along with its parsed bits
+        let mut bitmap: Option<(usize, BitArray<u64, U192>)> = None;
+        let tertiary_bit_index = IsoMsg::tertiary_bit_index(iso_spec);
 
-            // i0 and i1 are bitmap and mti
-            let field_exist = is_a_mti_or_bitmap || bit_array.get(iso_spec_index - 1).unwrap();
+        let mut fields = Vec::with_capacity(iso_spec.specs.len());
+        let unexpected_eof = |field_index, needed| IsoError::UnexpectedEof {
+            field_index,
+            needed,
+            available: input_buffer.len(),
+        };
+
+        for (iso_spec_index, iso_field) in iso_spec.specs.iter().enumerate() {
+            // fields placed before the bitmap (mti, headers) are always present
+            let field_exist = match &bitmap {
+                // with a tertiary bitmap, bit 65 announces it instead of a field
+                Some(_) if Some(iso_spec_index) == tertiary_bit_index => false,
+                Some((bitmap_index, bit_array)) => bit_array
+                    .get(iso_spec_index - bitmap_index)
+                    .unwrap_or(false),
+                None => true,
+            };
 
             let field = if field_exist {
-                let (len, tag_len) =
-                    IsoMsg::get_field_length(iso_field, &input_buffer[payload_index..]);
+                let (len, tag_len) = if bitmap.is_none() && iso_field.size_type == FieldSizeType::BitMap {
+                    if iso_spec.detect_bitmap_encoding {
+                        bitmap_encoding = BitmapEncoding::detect(&input_buffer[payload_index..]);
+                    }
+                    let primary_bitmap_end = payload_index + bitmap_encoding.bytes_per_bitmap();
+                    if primary_bitmap_end > input_buffer.len() {
+                        return Err(unexpected_eof(iso_spec_index, primary_bitmap_end));
+                    }
+                    let bitmap_len = IsoMsg::bitmap_length(
+                        iso_spec,
+                        bitmap_encoding,
+                        iso_field,
+                        &input_buffer[payload_index..],
+                    )?;
+                    if payload_index + bitmap_len > input_buffer.len() {
+                        return Err(unexpected_eof(iso_spec_index, payload_index + bitmap_len));
+                    }
+                    let bitmap_bytes = bitmap_encoding
+                        .decode(&input_buffer[payload_index..payload_index + bitmap_len])?;
+                    bitmap = Some((iso_spec_index, IsoMsg::process_bitmaps(&bitmap_bytes)?));
+                    (bitmap_len, 0)
+                } else {
+                    IsoMsg::get_field_length(iso_field, &input_buffer[payload_index..])?
+                };
+                if payload_index + len > input_buffer.len() {
+                    return Err(unexpected_eof(iso_spec_index, payload_index + len));
+                }
+                if iso_field.char_type == FieldCharType::Iso8583_n_bcd {
+                    let packed = &input_buffer[payload_index + tag_len..payload_index + len];
+                    IsoMsg::check_packed_digits(iso_spec_index, packed)?;
+                }
+                let content_type = iso_field.char_type.get_str("content_type");
+                let is_text = matches!(content_type, Some("string") | Some("number"))
+                    && iso_field.char_type != FieldCharType::Iso8583_n_bcd;
+                let detected_pad = if iso_field.size_type == FieldSizeType::Fixed && is_text {
+                    FieldPadding::detect(&input_buffer[payload_index..payload_index + len])
+                } else {
+                    FieldPadding::None
+                };
                 FieldPayload {
                     index: payload_index,
                     len,
@@ -287,27 +2145,26 @@ impl<'a, 'b> IsoMsg<'a, 'b> {
                     iso_field_label: Some(iso_field.label.clone()), //TODO use the reference instead of cloning everytime
                     iso_field_label_id: iso_field.label_id.clone(),
                     char_type: iso_field.char_type.clone(),
+                    new_payload: None,
+                    detected_pad,
                 }
             } else {
                 FieldPayload::default()
             };
 
             if field.exist {
-                //TODO fill a buffer with array of errors
-                // dbg!((
-                //     IsoMsg::get_field_length(iso_field, &input_buffer[payload_index..]),
-                //     iso_field.label.clone(),
-                //     String::from_utf8_lossy(&input_buffer[0..4]),
-                //     String::from_utf8_lossy(
-                //         &input_buffer[payload_index..payload_index + field.len]
-                //     ),
-                //     format!("{:?}", bit_array)
-                // ));
+                trace!(
+                    "parse: index:{}, label:{}, offset:{}, len:{}",
+                    iso_spec_index,
+                    iso_field.label,
+                    payload_index,
+                    field.len
+                );
             }
 
             payload_index += field.len;
             fields.push(field)
         }
-        fields
+        Ok((fields, bitmap_encoding))
     }
 }
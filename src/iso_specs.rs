@@ -1,4 +1,9 @@
 use super::*;
+use crate::char_types::CharTypeRegistry;
+use crate::field_codec::FieldCodecs;
+use crate::iso_error::IsoError;
+use crate::numeric;
+use crate::spec_cell::SpecCell;
 use iso_field::FieldCharType;
 use iso_field::FieldSizeType;
 use iso_field::IsoField;
@@ -180,15 +185,325 @@ pub enum Category {
     Unknown,
 }
 
+/// How the bitmaps are represented inside the payload
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitmapEncoding {
+    /// 8 raw bytes per bitmap, as found on IPM files
+    Binary,
+    /// 16 ASCII hexadecimal characters per bitmap
+    Hex,
+}
+
+impl BitmapEncoding {
+    /// `Iso8583_bmp` bitmaps are raw bytes while `Iso8583_bmps` are hexadecimal strings
+    pub fn from_char_type(char_type: &FieldCharType) -> Option<BitmapEncoding> {
+        match char_type {
+            FieldCharType::Iso8583_bmp => Some(BitmapEncoding::Binary),
+            FieldCharType::Iso8583_bmps => Some(BitmapEncoding::Hex),
+            _ => None,
+        }
+    }
+
+    /// Guesses the encoding of the bitmap starting `payload`: a hex bitmap is at least
+    /// 16 hexadecimal ascii characters, anything else is read as binary
+    pub fn detect(payload: &[u8]) -> BitmapEncoding {
+        let hex_len = BitmapEncoding::Hex.bytes_per_bitmap();
+        if payload.len() >= hex_len && payload[..hex_len].iter().all(u8::is_ascii_hexdigit) {
+            BitmapEncoding::Hex
+        } else {
+            BitmapEncoding::Binary
+        }
+    }
+
+    /// How many payload bytes a single 64 bits bitmap takes
+    pub fn bytes_per_bitmap(&self) -> usize {
+        match self {
+            BitmapEncoding::Binary => 8,
+            BitmapEncoding::Hex => 16,
+        }
+    }
+
+    /// Translates the bitmap as found on the payload into raw bitmap bytes, a hex bitmap
+    /// holding anything but pairs of hexadecimal digits is an `InvalidBitmap`
+    pub fn decode(&self, bitmap: &[u8]) -> Result<Vec<u8>, IsoError> {
+        match self {
+            BitmapEncoding::Binary => Ok(bitmap.to_vec()),
+            BitmapEncoding::Hex => {
+                if let Some(position) = bitmap.iter().position(|byte| !byte.is_ascii_hexdigit()) {
+                    return Err(IsoError::InvalidBitmap { position });
+                }
+                iso_field::from_hex(bitmap).ok_or(IsoError::InvalidBitmap {
+                    position: bitmap.len(),
+                })
+            }
+        }
+    }
+
+    /// Translates raw bitmap bytes into the representation used on the payload
+    pub fn encode(&self, bitmap: &[u8]) -> Vec<u8> {
+        match self {
+            BitmapEncoding::Binary => bitmap.to_vec(),
+            BitmapEncoding::Hex => iso_field::to_hex(bitmap).into_bytes(),
+        }
+    }
+}
+
+/// A difference between two specs, fields are matched by their label id
+#[derive(Debug, PartialEq)]
+pub enum SpecDelta {
+    Added(IsoField),
+    Removed(IsoField),
+    Changed { before: IsoField, after: IsoField },
+}
+
+impl fmt::Display for SpecDelta {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn describe(field: &IsoField) -> String {
+            format!(
+                "{} ({} {} {}{})",
+                field.label,
+                field.char_type.as_str(),
+                field.size_type.as_str(),
+                field.length,
+                if field.required { " required" } else { "" }
+            )
+        }
+
+        match self {
+            SpecDelta::Added(field) => write!(f, "+ {}: {}", field.label_id, describe(field)),
+            SpecDelta::Removed(field) => write!(f, "- {}: {}", field.label_id, describe(field)),
+            SpecDelta::Changed { before, after } => write!(
+                f,
+                "~ {}: {} => {}",
+                after.label_id,
+                describe(before),
+                describe(after)
+            ),
+        }
+    }
+}
+
+/// Tells the trailer record of a file apart from the records using the same layout
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TrailerMarker {
+    /// the record mti equals these bytes
+    Mti(Vec<u8>),
+    /// the first field of the record starts with these bytes, for trailers without mti
+    Sentinel(Vec<u8>),
+}
+
+/// How label keyed outputs name their fields
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LabelKeys {
+    /// labels shared by several fields are suffixed with their DE number,
+    /// e.g. "Reserved for National use (DE60)"
+    #[default]
+    Disambiguated,
+    /// bare labels, values of fields sharing a label overwrite each other
+    Plain,
+}
+
+static BUILTIN: SpecCell<IsoSpecs> = SpecCell::new(IsoSpecs::new);
+
+/// Misspelled labels of earlier built-in specs and their corrected form, still accepted by
+/// the label lookups until the next major release
+pub const LEGACY_LABELS: &[(&str, &str)] = &[
+    ("Authorizing Agent Inst Id Cod", "Authorizing Agent Inst Id Code"),
+    ("Additional Data 4)", "Additional Data 4"),
+];
+
+/// Corrected form of a legacy label, compared ignoring case, other labels are returned as is
+pub fn current_label(label: &str) -> &str {
+    LEGACY_LABELS
+        .iter()
+        .find(|(legacy, _)| legacy.eq_ignore_ascii_case(label))
+        .map_or(label, |(_, current)| current)
+}
+
+/// Fields of the 2003 revision differing from the built-in 1993 spec, as
+/// (data element, char type, length, size type). The dates carry a four digits year and
+/// DE 22 becomes a variable point of service dataset.
+const ISO2003_DELTAS: [(usize, FieldCharType, usize, FieldSizeType); 4] = [
+    (12, FieldCharType::Iso8583_ns, 14, FieldSizeType::Fixed),
+    (15, FieldCharType::Iso8583_ns, 8, FieldSizeType::Fixed),
+    (22, FieldCharType::Iso8583_ans, 255, FieldSizeType::LllVar),
+    (28, FieldCharType::Iso8583_ns, 8, FieldSizeType::Fixed),
+];
+
+/// ISO 8583:2003 spec, for messages whose mti starts with `2`, built from the 1993
+/// fields of `IsoSpecs::new` with `ISO2003_DELTAS` applied
+pub fn iso2003() -> IsoSpecs {
+    let mut iso_spec = IsoSpecs::new();
+    for (de, char_type, length, size_type) in ISO2003_DELTAS {
+        let index = iso_spec.de_index(de).expect("built-in data element");
+        let field = &mut iso_spec.specs[index];
+        field.char_type = char_type;
+        field.length = length;
+        field.size_type = size_type;
+    }
+
+    iso_spec
+}
+
 /// Auth spec defines the format of Iso8583 message
+#[derive(Clone)]
 pub struct IsoSpecs {
     pub specs: Vec<IsoField>,
+    pub bitmap_encoding: BitmapEncoding,
+    /// detect the bitmap encoding of each parsed message instead of using `bitmap_encoding`,
+    /// messages are then written back with the encoding they were read with
+    pub detect_bitmap_encoding: bool,
+    /// behaviour of the `FieldCharType::Custom` types used by the fields
+    pub char_types: CharTypeRegistry,
+    /// codecs of the composite fields, see `IsoMsg::value`
+    pub field_codecs: FieldCodecs,
+    /// marks the spec as a trailer layout, see `IsoMsg::is_trailer`
+    pub trailer: Option<TrailerMarker>,
+    /// length every record is padded to on files with fixed length records
+    pub record_len: Option<usize>,
+}
+
+impl Default for IsoSpecs {
+    fn default() -> Self {
+        IsoSpecs::new()
+    }
 }
 
 impl IsoSpecs {
     pub fn new() -> IsoSpecs {
+        IsoSpecs::from_specs(IsoSpecs::define_specs())
+    }
+
+    /// Built-in spec shared by every caller, built once on first access
+    pub fn builtin() -> &'static IsoSpecs {
+        BUILTIN.get()
+    }
+
+    /// Builds a spec from a custom set of fields, the bitmap encoding follows the
+    /// bitmap field char type and falls back to binary
+    pub fn from_specs(specs: Vec<IsoField>) -> IsoSpecs {
+        let bitmap_encoding = specs
+            .iter()
+            .find(|spec| spec.size_type == FieldSizeType::BitMap)
+            .and_then(|spec| BitmapEncoding::from_char_type(&spec.char_type))
+            .unwrap_or(BitmapEncoding::Binary);
+
         IsoSpecs {
-            specs: IsoSpecs::define_specs(),
+            specs,
+            bitmap_encoding,
+            detect_bitmap_encoding: false,
+            char_types: CharTypeRegistry::default(),
+            field_codecs: FieldCodecs::default(),
+            trailer: None,
+            record_len: None,
+        }
+    }
+
+    /// Length of the records written by `frame_records_for`, `None` when the records keep
+    /// their own length
+    pub fn fixed_record_len(&self) -> Option<usize> {
+        self.record_len
+    }
+
+    /// Lists what changes from this spec to `other`, following the order of the fields
+    pub fn diff(&self, other: &IsoSpecs) -> Vec<SpecDelta> {
+        let find = |specs: &[IsoField], label_id: &str| -> Option<IsoField> {
+            specs.iter().find(|spec| spec.label_id == label_id).cloned()
+        };
+        let mut deltas = vec![];
+
+        for before in self.specs.iter() {
+            match find(&other.specs, &before.label_id) {
+                None => deltas.push(SpecDelta::Removed(before.clone())),
+                Some(after) if &after != before => deltas.push(SpecDelta::Changed {
+                    before: before.clone(),
+                    after,
+                }),
+                Some(_) => {}
+            }
+        }
+
+        for after in other.specs.iter() {
+            if find(&self.specs, &after.label_id).is_none() {
+                deltas.push(SpecDelta::Added(after.clone()));
+            }
+        }
+
+        deltas
+    }
+
+    /// Position of the bitmap field, every field after it is governed by the bitmap
+    pub fn bitmap_index(&self) -> Option<usize> {
+        self.specs
+            .iter()
+            .position(|spec| spec.size_type == FieldSizeType::BitMap)
+    }
+
+    /// Position of data element `de`, looked up by its zero padded label id, e.g. "039"
+    pub fn de_index(&self, de: usize) -> Option<usize> {
+        let label_id = numeric::format_fixed(de as u64, 3).ok()?;
+        self.specs
+            .iter()
+            .position(|spec| spec.label_id.as_bytes() == label_id)
+    }
+
+    /// Position of data element `de` counted from the bitmap, which is DE 1, so header
+    /// fields ahead of the mti don't shift it. DE 0 and DE 1 have no data field.
+    pub fn de_to_index(&self, de: u8) -> Option<usize> {
+        if de < 2 {
+            return None;
+        }
+
+        let index = self.bitmap_index()? + de as usize - 1;
+        if index < self.specs.len() {
+            Some(index)
+        } else {
+            None
+        }
+    }
+
+    /// Positions of every field carrying `label`, labels being compared trimmed and ignoring
+    /// case, legacy labels resolve to their corrected form, see `LEGACY_LABELS`
+    pub fn find_by_label(&self, label: &str) -> Vec<usize> {
+        let label = current_label(label.trim());
+        self.specs
+            .iter()
+            .enumerate()
+            .filter(|(_, spec)| spec.label.trim().eq_ignore_ascii_case(label))
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    /// Position of the only field carrying `label`, matched like on `find_by_label`
+    pub fn find_unique_by_label(&self, label: &str) -> Result<usize, IsoError> {
+        match self.find_by_label(label).as_slice() {
+            [index] => Ok(*index),
+            [] => Err(IsoError::UnknownLabel {
+                label: label.trim().to_string(),
+            }),
+            indexes => Err(IsoError::AmbiguousLabel {
+                label: label.trim().to_string(),
+                indexes: indexes.to_vec(),
+            }),
+        }
+    }
+
+    /// Position of the field named `name`, same lookup as `find_unique_by_label`
+    pub fn field_index_by_name(&self, name: &str) -> Result<usize, IsoError> {
+        self.find_unique_by_label(name)
+    }
+
+    /// Key naming the field on label keyed outputs
+    pub fn label_key(&self, index: usize, label_keys: LabelKeys) -> String {
+        let spec = &self.specs[index];
+        let shared = self.specs.iter().filter(|other| other.label == spec.label).count() > 1;
+        if label_keys == LabelKeys::Plain || !shared {
+            return spec.label.clone();
+        }
+
+        match spec.label_id.parse::<usize>() {
+            Ok(de) => format!("{} (DE{})", spec.label, de),
+            Err(_) => format!("{} ({})", spec.label, spec.label_id),
         }
     }
 
@@ -204,7 +519,7 @@ impl IsoSpecs {
             IsoField::new(
                 "Bitmaps",
                 "bitmaps", // Primary Bitmap (8 bytes) + DE 1 (8 bytes) = 16 bytes
-                FieldCharType::Iso8583_bmps,
+                FieldCharType::Iso8583_bmp,
                 16,
                 FieldSizeType::BitMap,
             ), // Bitmap
@@ -601,7 +916,7 @@ impl IsoSpecs {
                 FieldSizeType::Fixed,
             ), // Authorization Life Cycle Code
             IsoField::new(
-                "Authorizing Agent Inst Id Cod",
+                "Authorizing Agent Inst Id Code",
                 "058", // DE 58
                 FieldCharType::Iso8583_n,
                 11,
@@ -1063,12 +1378,12 @@ impl IsoSpecs {
                 FieldSizeType::LllVar,
             ), // Additional Data 3
             IsoField::new(
-                "Additional Data 4)",
+                "Additional Data 4",
                 "124", // DE 124
                 FieldCharType::Iso8583_ans,
                 999,
                 FieldSizeType::LllVar,
-            ), // Additional Data 4)
+            ), // Additional Data 4
             IsoField::new(
                 "Additional Data 5",
                 "125", // DE 125
@@ -1101,3 +1416,23 @@ impl IsoSpecs {
         h
     }
 }
+
+#[test]
+fn test_iso2003_deltas() {
+    let deltas: Vec<String> = IsoSpecs::new()
+        .diff(&iso2003())
+        .iter()
+        .map(|delta| delta.to_string())
+        .collect();
+    assert_eq!(
+        deltas,
+        vec![
+            "~ 012: Date and Time, Local Txn (ns fixed 12) => \
+             Date and Time, Local Txn (ns fixed 14)",
+            "~ 015: Date, Settlement (ns fixed 6) => Date, Settlement (ns fixed 8)",
+            "~ 022: Point of Service Data Code (an fixed 12) => \
+             Point of Service Data Code (ans lllvar 255)",
+            "~ 028: Date, Reconciliation (ns fixed 6) => Date, Reconciliation (ns fixed 8)",
+        ]
+    );
+}
@@ -0,0 +1,53 @@
+use crate::iso_error::SpecError;
+use crate::iso_specs::IsoSpecs;
+use crate::yaml_specs::SpecDocument;
+use std::path::Path;
+
+/// Spec loaded from a json definition, holding the same fields as a `YamlSpec` document
+///
+/// ```json
+/// {
+///   "bitmap_encoding": "binary",
+///   "fields": [
+///     { "label_id": "mti", "label": "Message Type Indicator", "char_type": "mti",
+///       "size_type": "fixed", "length": 4 },
+///     { "label_id": "bitmaps", "label": "Bitmaps", "char_type": "bmp",
+///       "size_type": "bitmap", "length": 16 },
+///     { "label_id": "002", "label": "Primary Account Number", "char_type": "ns",
+///       "size_type": "llvar", "length": 19 }
+///   ]
+/// }
+/// ```
+pub struct JsonSpec {
+    handle: IsoSpecs,
+}
+
+impl JsonSpec {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(json: &str) -> Result<JsonSpec, SpecError> {
+        let document: SpecDocument = serde_json::from_str(json)?;
+
+        Ok(JsonSpec {
+            handle: document.into_handle()?,
+        })
+    }
+
+    pub fn from_path(path: &Path) -> Result<JsonSpec, SpecError> {
+        JsonSpec::from_str(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn spec(&self) -> &IsoSpecs {
+        &self.handle
+    }
+
+    #[deprecated(since = "1.1.0", note = "please use `JsonSpec::spec` instead")]
+    pub fn get_handle(&self) -> &IsoSpecs {
+        self.spec()
+    }
+}
+
+impl From<JsonSpec> for IsoSpecs {
+    fn from(json_spec: JsonSpec) -> Self {
+        json_spec.handle
+    }
+}
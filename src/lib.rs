@@ -6,11 +6,36 @@ extern crate serde_derive;
 #[macro_use]
 extern crate log;
 
+pub mod alloc_budget;
+pub mod builder;
+pub mod capture;
+pub mod char_types;
+pub mod context;
+pub mod conversion_rate;
+pub mod field_codec;
 pub mod file_utils;
+pub mod framing;
+pub mod iso_error;
 pub mod iso_field;
 pub mod iso_msg;
 pub mod iso_specs;
+#[cfg(feature = "json-spec")]
+pub mod json_specs;
+pub mod message_codes;
+pub mod mti;
+pub mod numeric;
 pub mod pds;
+pub mod pipeline;
+pub mod presets;
+pub mod record_cache;
+pub mod spec_cell;
+pub mod spec_registry;
+#[cfg(feature = "toml-spec")]
+pub mod toml_specs;
+pub mod track2;
+pub mod transaction;
+pub mod validator;
+pub mod yaml_specs;
 
 use crate::iso_specs::Category;
 use eyre::{eyre, Result};
@@ -57,10 +82,14 @@ pub struct Message {
 }
 
 impl Message {
-    fn get_category(mti: &str, ipm_function_code: &iso_field::IPMValue) -> Category {
+    /// A message without DE 24 carries no function code, so its category stays unknown
+    fn get_category(mti: &str, ipm_function_code: Option<&iso_field::IPMValue>) -> Category {
         let mut category = Category::Unknown;
 
-        let function_code = ipm_function_code.get_string();
+        let function_code = match ipm_function_code {
+            Some(ipm_function_code) => ipm_function_code.get_string(),
+            None => return category,
+        };
 
         for spec_category in Category::iter() {
             if spec_category.get_str("mti") == Some(mti)
@@ -77,6 +106,37 @@ impl Message {
 pub struct Iso8583File {
     pub messages: Vec<Message>,
     pub categories_indexes: HashMap<String, Vec<usize>>,
+    pub summary: ReadSummary,
+}
+
+/// Outcome of the checks run once every record of a file has been read
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ReadSummary {
+    /// index of the trailer closing the file, only looked for when the trailer is required
+    pub trailer: Option<usize>,
+    pub reconciliation: Option<Reconciliation>,
+}
+
+/// Message count announced by the trailer PDS 0306 against the amount of messages read,
+/// header and trailer included
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Reconciliation {
+    /// `None` when the trailer has no readable PDS 0306
+    pub announced_messages: Option<usize>,
+    pub read_messages: usize,
+}
+
+impl Reconciliation {
+    fn of(trailer: &Message, read_messages: usize) -> Reconciliation {
+        Reconciliation {
+            announced_messages: trailer.pds.get("0306").and_then(|count| count.parse().ok()),
+            read_messages,
+        }
+    }
+
+    pub fn is_balanced(&self) -> bool {
+        self.announced_messages == Some(self.read_messages)
+    }
 }
 
 impl fmt::Debug for Iso8583File {
@@ -101,6 +161,7 @@ impl Iso8583File {
         let mut parsed_file = Iso8583File {
             messages,
             categories_indexes: HashMap::new(),
+            summary: ReadSummary::default(),
         };
 
         parsed_file.assign_messages_categories()?;
@@ -139,6 +200,7 @@ impl Iso8583File {
         let mut new_iso8583_files = Iso8583File {
             messages: search_messages_result,
             categories_indexes: HashMap::new(),
+            summary: self.summary,
         };
 
         new_iso8583_files.assign_messages_categories().expect("Unable to assign categories messages");
@@ -160,74 +222,229 @@ impl Iso8583File {
     }
 }
 
-pub fn read_and_deblock_file<'a>(file_name: &str) -> Result<Vec<u8>> {
+pub fn read_and_deblock_file(file_name: &str) -> Result<Vec<u8>> {
     let file = file_utils::read_file(file_name);
     let file_contents_base64 = file_utils::deblock_and_remove_rdw_from(file)?;
     Ok(file_contents_base64)
 }
 
-pub fn parse_file(payload: Vec<u8>) -> Result<Iso8583File> {
-    //checks if file has rdw at head and blocks at tail
+/// Tells whether a message is the trailer closing a file
+pub type TrailerClassifier = fn(&Message) -> bool;
+
+/// The IPM file trailer, MTI 1644 with function code 695
+pub fn is_ipm_trailer(message: &Message) -> bool {
+    matches!(message.category, Category::Trailer)
+}
+
+/// Whether a file must end with a trailer record
+#[derive(Debug, Clone, Copy, Default)]
+pub enum TrailerPolicy {
+    /// The last record must match the classifier, a file without it was truncated. The
+    /// trailer message count is then reconciled on the read summary.
+    Required(TrailerClassifier),
+    /// The channel has no trailer, an IPM trailer record is rejected
+    Forbidden,
+    #[default]
+    NotChecked,
+}
+
+/// Options used by `parse_file_with` to read a file
+pub struct ReaderOptions {
+    pub framing: file_utils::Framing,
+    pub spec: iso_specs::IsoSpecs,
+    /// picks the spec of each record from its mti version instead of using `spec`
+    pub registry: Option<spec_registry::SpecRegistry>,
+    pub trailer_policy: TrailerPolicy,
+    /// bounds the records copied out of the file
+    pub alloc_budget: alloc_budget::AllocBudget,
+}
+
+impl ReaderOptions {
+    fn spec_for(&self, record: &[u8]) -> Result<&iso_specs::IsoSpecs, iso_error::IsoError> {
+        match &self.registry {
+            Some(registry) => registry.spec_for(record),
+            None => Ok(&self.spec),
+        }
+    }
+}
+
+impl Default for ReaderOptions {
+    fn default() -> Self {
+        ReaderOptions {
+            framing: file_utils::Framing::Rdw,
+            spec: iso_specs::IsoSpecs::new(),
+            registry: None,
+            trailer_policy: TrailerPolicy::default(),
+            alloc_budget: alloc_budget::AllocBudget::default(),
+        }
+    }
+}
 
-    let handle = iso_specs::IsoSpecs::new();
+pub fn parse_file(payload: Vec<u8>) -> Result<Iso8583File> {
+    parse_file_with(payload, &ReaderOptions::default())
+}
 
+pub fn parse_file_with(payload: Vec<u8>, options: &ReaderOptions) -> Result<Iso8583File> {
     let mut current_vec_index: usize = 0;
     let mut messages: Vec<Message> = vec![];
-
-    let clean_payload = file_utils::deblock_and_remove_rdw_from(payload)?;
-
-    while clean_payload.len() > (current_vec_index + 2) {
-        let mut mti = "".to_owned();
-        let mut primary_bitmap: [u8; 8] = Default::default();
-        let mut data_elements: HashMap<String, iso_field::IPMValue> = HashMap::new();
-        let mut pds: HashMap<String, String> = HashMap::new();
-        let iso_msg = iso_msg::IsoMsg::new(&handle, &clean_payload[current_vec_index..]);
-        for field in iso_msg.present_fields() {
-            let value = field.iso_field_value(&clean_payload[current_vec_index..]);
-            let field_id = field.iso_field_label_id.clone();
-            let ipm_value = field.get_ipm_value(&clean_payload[current_vec_index..])?;
-
-            // this is an additional security to avoid a stack level too deep or endless-loops
-            if data_elements.contains_key(&field_id) {
-                return Err(eyre!("duplicated field should not exist on iso8583 message",));
+    let mut alloc_tracker = options.alloc_budget.tracker();
+
+    match &options.framing {
+        file_utils::Framing::Rdw => {
+            //checks if file has rdw at head and blocks at tail
+            let clean_payload = file_utils::deblock_and_remove_rdw_from(payload)?;
+
+            while clean_payload.len() > (current_vec_index + 2) {
+                let record = &clean_payload[current_vec_index..];
+                let iso_msg = options
+                    .spec_for(record)
+                    .and_then(|handle| iso_msg::IsoMsg::try_new(handle, record))
+                    .and_then(|iso_msg| {
+                        alloc_tracker.charge(iso_msg.length(), "record")?;
+                        Ok(iso_msg)
+                    })
+                    .map_err(|error| eyre!("record {}: {}", messages.len(), error))?;
+                messages.push(build_message(&iso_msg, &clean_payload[current_vec_index..])?);
+
+                current_vec_index += iso_msg.length();
             }
-
-            // Parse PDSs
-            // FIXME: for now pds are only implemented for de48
-            if field_id == "048" {
-                pds = match pds::get_pds_values(&value)? {
-                    Some(v) => v,
-                    None => pds,
+        }
+        file_utils::Framing::LineDelimited { terminators } => {
+            let clean_payload = file_utils::strip_bom(&payload);
+
+            while clean_payload.len() > current_vec_index {
+                let record = &clean_payload[current_vec_index..];
+                let handle = options
+                    .spec_for(record)
+                    .map_err(|error| eyre!("record {}: {}", messages.len(), error))?;
+                let iso_msg = iso_msg::IsoMsg::try_new(handle, record)
+                    .and_then(|iso_msg| {
+                        alloc_tracker.charge(iso_msg.length(), "record")?;
+                        Ok(iso_msg)
+                    })
+                    .map_err(|error| eyre!("record {}: {}", messages.len(), error))?;
+                check_no_terminator_in_binary_fields(handle, &iso_msg, terminators, messages.len())?;
+                messages.push(build_message(&iso_msg, record)?);
+
+                current_vec_index += iso_msg.length();
+                // the final line may come without its terminator
+                if current_vec_index < clean_payload.len() {
+                    let terminator_length = file_utils::terminator_length(
+                        &clean_payload[current_vec_index..],
+                        terminators,
+                    )
+                    .ok_or_else(|| {
+                        eyre!(
+                            "record {} is not followed by a line terminator at position {}",
+                            messages.len() - 1,
+                            current_vec_index
+                        )
+                    })?;
+                    current_vec_index += terminator_length;
                 }
             }
+        }
+    }
+    let summary = check_trailer(&messages, options.trailer_policy)?;
+    let mut iso8583_file = Iso8583File::new(messages)?;
+    iso8583_file.summary = summary;
 
-            // Parse MTI, bitmaps and DEs
-            if field_id == "mti" {
-                mti = ipm_value.get_string();
-            } else if field_id == "bitmaps" {
-                let (primary_bitmap_slice, secondary_bitmap_slice) = value.split_at(8);
+    Ok(iso8583_file)
+}
 
-                primary_bitmap = primary_bitmap_slice.try_into()?;
+fn check_trailer(messages: &[Message], trailer_policy: TrailerPolicy) -> Result<ReadSummary> {
+    match trailer_policy {
+        TrailerPolicy::Required(is_trailer) => match messages.last() {
+            Some(trailer) if is_trailer(trailer) => Ok(ReadSummary {
+                trailer: Some(messages.len() - 1),
+                reconciliation: Some(Reconciliation::of(trailer, messages.len())),
+            }),
+            _ => Err(iso_error::IsoError::MissingTrailer {
+                records: messages.len(),
+            }
+            .into()),
+        },
+        TrailerPolicy::Forbidden => match messages.iter().position(is_ipm_trailer) {
+            Some(record) => Err(iso_error::IsoError::UnexpectedTrailer { record }.into()),
+            None => Ok(ReadSummary::default()),
+        },
+        TrailerPolicy::NotChecked => Ok(ReadSummary::default()),
+    }
+}
 
-                data_elements.insert("001".to_owned(), iso_field::IPMValue::Binary(secondary_bitmap_slice.to_vec()));
-            } else {
-                data_elements.insert(field_id, ipm_value);
+/// Binary fields may hold the terminator bytes, which line based tools would split on
+fn check_no_terminator_in_binary_fields(
+    iso_spec: &iso_specs::IsoSpecs,
+    iso_msg: &iso_msg::IsoMsg,
+    terminators: &[Vec<u8>],
+    record_index: usize,
+) -> Result<()> {
+    for field in iso_msg {
+        let index = field.index();
+        if iso_spec.specs[index].char_type.get_str("content_type") != Some("binary") {
+            continue;
+        }
+        let holds_terminator = field
+            .value()
+            .iter()
+            .any(|byte| terminators.iter().any(|terminator| terminator.contains(byte)));
+
+        if holds_terminator {
+            return Err(eyre!(
+                "binary field {} of record {} holds a line terminator byte, use a length prefixed framing such as Framing::Rdw instead",
+                iso_spec.specs[index].label_id,
+                record_index
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+fn build_message(iso_msg: &iso_msg::IsoMsg, payload: &[u8]) -> Result<Message> {
+    let mut mti = "".to_owned();
+    let mut primary_bitmap: [u8; 8] = Default::default();
+    let mut data_elements: HashMap<String, iso_field::IPMValue> = HashMap::new();
+    let mut pds: HashMap<String, String> = HashMap::new();
+    for field in iso_msg.present_fields() {
+        let value = field.iso_field_value(payload);
+        let field_id = field.iso_field_label_id.clone();
+        let ipm_value = field.get_ipm_value(payload)?;
+
+        // this is an additional security to avoid a stack level too deep or endless-loops
+        if data_elements.contains_key(&field_id) {
+            return Err(eyre!("duplicated field should not exist on iso8583 message",));
+        }
+
+        // Parse PDSs
+        // FIXME: for now pds are only implemented for de48
+        if field_id == "048" {
+            pds = match pds::get_pds_values(&value)? {
+                Some(v) => v,
+                None => pds,
             }
         }
 
-        let message = Message {
-            category: Message::get_category(&mti, &data_elements["024"]),
-            mti,
-            primary_bitmap,
-            data_elements,
-            pds,
-        };
+        // Parse MTI, bitmaps and DEs
+        if field_id == "mti" {
+            mti = ipm_value.get_string();
+        } else if field_id == "bitmaps" {
+            let bitmaps = iso_msg.bitmap_encoding().decode(&value)?;
+            let (primary_bitmap_slice, secondary_bitmap_slice) = bitmaps.split_at(8);
 
-        messages.append(&mut vec![message]);
+            primary_bitmap = primary_bitmap_slice.try_into()?;
 
-        current_vec_index += iso_msg.length();
+            data_elements.insert("001".to_owned(), iso_field::IPMValue::Binary(secondary_bitmap_slice.to_vec()));
+        } else {
+            data_elements.insert(field_id, ipm_value);
+        }
     }
-    let iso8583_file = Iso8583File::new(messages)?;
 
-    Ok(iso8583_file)
+    Ok(Message {
+        category: Message::get_category(&mti, data_elements.get("024")),
+        mti,
+        primary_bitmap,
+        data_elements,
+        pds,
+    })
 }
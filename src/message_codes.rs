@@ -0,0 +1,106 @@
+use std::fmt;
+
+/// Declares a code enum along with its numeric conversions, codes missing from the list
+/// are kept as `Other`
+macro_rules! message_codes {
+    ($(#[$meta:meta])* $name:ident { $($(#[$variant_meta:meta])* $variant:ident = $code:literal,)+ }) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum $name {
+            $($(#[$variant_meta])* $variant,)+
+            Other(u16),
+        }
+
+        impl $name {
+            pub fn from_code(code: u16) -> $name {
+                match code {
+                    $($code => $name::$variant,)+
+                    code => $name::Other(code),
+                }
+            }
+
+            pub fn code(&self) -> u16 {
+                match self {
+                    $($name::$variant => $code,)+
+                    $name::Other(code) => *code,
+                }
+            }
+        }
+
+        impl From<u16> for $name {
+            fn from(code: u16) -> Self {
+                $name::from_code(code)
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                match self {
+                    $name::Other(code) => write!(f, "{}", code),
+                    known => write!(f, "{:?} ({})", known, known.code()),
+                }
+            }
+        }
+    };
+}
+
+message_codes! {
+    /// Function code found on DE 24, telling what the message asks for
+    FunctionCode {
+        /// amount accurate
+        OriginalAuthorization = 100,
+        OriginalAuthorizationEstimated = 101,
+        SupplementaryAuthorization = 106,
+        /// also the IPM first presentment
+        OriginalFinancialRequest = 200,
+        PreviouslyApprovedSameAmount = 201,
+        PreviouslyApprovedDifferentAmount = 202,
+        /// also the IPM full second presentment
+        Resubmission = 205,
+        SecondPresentmentPartial = 282,
+        /// the transaction did not occur
+        FullReversal = 400,
+        PartialReversal = 401,
+        FirstChargebackFull = 450,
+        ArbitrationChargebackFull = 451,
+        FirstChargebackPartial = 453,
+        ArbitrationChargebackPartial = 454,
+        RetrievalRequest = 603,
+        RetrievalRequestAcknowledgement = 605,
+        FileCurrency = 680,
+        FinancialPosition = 685,
+        Settlement = 688,
+        MessageException = 691,
+        TextMessage = 693,
+        FileTrailer = 695,
+        FinancialDetailAddendum = 696,
+        FileHeader = 697,
+        FileReject = 699,
+    }
+}
+
+message_codes! {
+    /// Message reason code found on DE 25, telling why an advice or reversal was sent
+    MessageReasonCode {
+        CustomerCancellation = 4000,
+        /// no action taken
+        Unspecified = 4001,
+        SuspectedMalfunction = 4002,
+        FormatError = 4003,
+        CompletedPartially = 4004,
+        OriginalAmountIncorrect = 4005,
+        ResponseReceivedTooLate = 4006,
+        CardAcceptorDeviceUnableToComplete = 4007,
+        TimeoutWaitingForResponse = 4021,
+    }
+}
+
+#[test]
+fn test_message_codes_round_trip() {
+    assert_eq!(FunctionCode::from_code(400), FunctionCode::FullReversal);
+    assert_eq!(FunctionCode::FileTrailer.code(), 695);
+    assert_eq!(FunctionCode::from(123), FunctionCode::Other(123));
+    assert_eq!(FunctionCode::Other(123).code(), 123);
+    assert_eq!(FunctionCode::FullReversal.to_string(), "FullReversal (400)");
+    assert_eq!(MessageReasonCode::from_code(4021).code(), 4021);
+}
@@ -0,0 +1,163 @@
+use crate::iso_error::IsoError;
+use std::fmt;
+
+/// Declares the enum of an mti digit, digits without a meaning on the standard are kept
+/// as `Reserved`
+macro_rules! mti_digit {
+    ($(#[$meta:meta])* $name:ident { $($(#[$variant_meta:meta])* $variant:ident = $digit:literal,)+ }) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum $name {
+            $($(#[$variant_meta])* $variant,)+
+            Reserved(u8),
+        }
+
+        impl $name {
+            pub fn from_digit(digit: u8) -> $name {
+                match digit {
+                    $($digit => $name::$variant,)+
+                    digit => $name::Reserved(digit),
+                }
+            }
+
+            pub fn digit(&self) -> u8 {
+                match self {
+                    $($name::$variant => $digit,)+
+                    $name::Reserved(digit) => *digit,
+                }
+            }
+        }
+    };
+}
+
+mti_digit! {
+    /// First mti digit, the ISO 8583 version the message follows
+    MtiVersion {
+        Iso1987 = 0,
+        Iso1993 = 1,
+        Iso2003 = 2,
+        National = 8,
+        Private = 9,
+    }
+}
+
+mti_digit! {
+    /// Second mti digit, the overall purpose of the message
+    MessageClass {
+        Authorization = 1,
+        Financial = 2,
+        FileAction = 3,
+        /// reversals and chargebacks
+        Reversal = 4,
+        Reconciliation = 5,
+        Administrative = 6,
+        FeeCollection = 7,
+        NetworkManagement = 8,
+    }
+}
+
+mti_digit! {
+    /// Third mti digit, how the message takes part in its exchange
+    MessageFunction {
+        Request = 0,
+        RequestResponse = 1,
+        Advice = 2,
+        AdviceResponse = 3,
+        Notification = 4,
+        NotificationAcknowledgement = 5,
+        Instruction = 6,
+        InstructionAcknowledgement = 7,
+    }
+}
+
+mti_digit! {
+    /// Fourth mti digit, who sent the message
+    Originator {
+        Acquirer = 0,
+        AcquirerRepeat = 1,
+        Issuer = 2,
+        IssuerRepeat = 3,
+        Other = 4,
+        OtherRepeat = 5,
+    }
+}
+
+/// Message Type Indicator, four digits giving the version, class, function and origin
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Mti {
+    digits: [u8; 4],
+}
+
+impl Mti {
+    /// Parses the four ascii digits of an mti, errors refer to the mti as field 0
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(mti: &str) -> Result<Mti, IsoError> {
+        Mti::from_bytes(mti.as_bytes())
+    }
+
+    /// Same as `from_bytes` on exactly four bytes
+    pub fn parse(mti: &[u8; 4]) -> Result<Mti, IsoError> {
+        Mti::from_bytes(mti)
+    }
+
+    pub fn from_bytes(mti: &[u8]) -> Result<Mti, IsoError> {
+        if mti.len() != 4 {
+            return Err(IsoError::InvalidLength {
+                index: 0,
+                expected: 4,
+                got: mti.len(),
+            });
+        }
+        if let Some(position) = mti.iter().position(|byte| !byte.is_ascii_digit()) {
+            return Err(IsoError::InvalidCharacter { index: 0, position });
+        }
+
+        let mut digits = [0u8; 4];
+        for (digit, byte) in digits.iter_mut().zip(mti) {
+            *digit = byte - b'0';
+        }
+        Ok(Mti { digits })
+    }
+
+    /// ISO 8583 version, e.g. `Iso1993` for 1
+    pub fn version(&self) -> MtiVersion {
+        MtiVersion::from_digit(self.digits[0])
+    }
+
+    /// Message class, e.g. `Authorization` for 1 or `Reversal` for 4
+    pub fn message_class(&self) -> MessageClass {
+        MessageClass::from_digit(self.digits[1])
+    }
+
+    #[deprecated(since = "1.1.0", note = "please use `Mti::message_class` instead")]
+    pub fn class(&self) -> u8 {
+        self.digits[1]
+    }
+
+    /// Message function, e.g. `Request` for 0 or `RequestResponse` for 1
+    pub fn function(&self) -> MessageFunction {
+        MessageFunction::from_digit(self.digits[2])
+    }
+
+    /// Message origin, e.g. `Acquirer` for 0 or `Issuer` for 2
+    pub fn originator(&self) -> Originator {
+        Originator::from_digit(self.digits[3])
+    }
+
+    #[deprecated(since = "1.1.0", note = "please use `Mti::originator` instead")]
+    pub fn origin(&self) -> u8 {
+        self.digits[3]
+    }
+
+    /// The mti as written on the payload
+    pub fn to_bytes(&self) -> [u8; 4] {
+        self.digits.map(|digit| digit + b'0')
+    }
+}
+
+impl fmt::Display for Mti {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bytes = self.to_bytes();
+        write!(f, "{}", String::from_utf8_lossy(&bytes))
+    }
+}
@@ -0,0 +1,337 @@
+use crate::iso_error::NumericError;
+
+/// How a division result is rounded to an integer
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RoundingMode {
+    /// ties go to the even neighbour, a.k.a banker's rounding
+    #[default]
+    HalfEven,
+    /// ties go away from zero
+    HalfUp,
+    /// the fraction is dropped
+    Down,
+}
+
+/// Writes `value` as exactly `width` ascii digits, left padded with zeros
+///
+/// Every number written into a fixed amount of digits goes through here and is either
+/// exact or an error, rounding only happens through `divide_rounded`.
+pub fn format_fixed(value: u64, width: usize) -> Result<Vec<u8>, NumericError> {
+    let digits = value.to_string().into_bytes();
+    if digits.len() > width {
+        return Err(NumericError::TooWide { value, width });
+    }
+
+    let mut fixed = vec![b'0'; width - digits.len()];
+    fixed.extend_from_slice(&digits);
+    Ok(fixed)
+}
+
+/// Reads ascii digits written by `format_fixed`
+pub fn parse_fixed(digits: &[u8]) -> Result<u64, NumericError> {
+    if digits.is_empty() {
+        return Err(NumericError::Empty);
+    }
+    if let Some(position) = digits.iter().position(|byte| !byte.is_ascii_digit()) {
+        return Err(NumericError::InvalidDigit { position });
+    }
+
+    digits.iter().try_fold(0u64, |number, byte| {
+        number
+            .checked_mul(10)
+            .and_then(|number| number.checked_add(u64::from(byte - b'0')))
+            .ok_or(NumericError::Overflow)
+    })
+}
+
+/// Where the sign nibble of a `PackedAmount` sits
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignPosition {
+    /// first nibble, before the digits
+    Leading,
+    /// last nibble, after the digits, as in COBOL `COMP-3`
+    #[default]
+    Trailing,
+    /// no sign nibble, the amount can't be negative
+    None,
+}
+
+/// Layout of a signed amount packed as BCD, two digits per byte
+///
+/// Without zero suppression the amount fills the whole field, left padded with zero
+/// digits. With it, leading zero bytes are dropped down to `min_bytes`, so such amounts
+/// belong to variable length fields.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PackedAmount {
+    pub sign_position: SignPosition,
+    pub positive_nibble: u8,
+    pub negative_nibble: u8,
+    /// least number of bytes left once leading zero bytes are suppressed
+    #[serde(default)]
+    pub min_bytes: Option<usize>,
+}
+
+impl Default for PackedAmount {
+    fn default() -> Self {
+        PackedAmount {
+            sign_position: SignPosition::Trailing,
+            positive_nibble: 0xC,
+            negative_nibble: 0xD,
+            min_bytes: None,
+        }
+    }
+}
+
+impl PackedAmount {
+    /// Trailing sign nibble, `0xC` positive and `0xD` negative, no zero suppression
+    pub fn new() -> PackedAmount {
+        PackedAmount::default()
+    }
+
+    pub fn with_sign(self, sign_position: SignPosition, positive: u8, negative: u8) -> Self {
+        PackedAmount {
+            sign_position,
+            positive_nibble: positive,
+            negative_nibble: negative,
+            ..self
+        }
+    }
+
+    /// Same layout dropping leading zero bytes on encode, keeping at least `min_bytes`
+    pub fn with_zero_suppression(self, min_bytes: usize) -> Self {
+        PackedAmount {
+            min_bytes: Some(min_bytes),
+            ..self
+        }
+    }
+
+    fn sign_nibbles(&self) -> usize {
+        match self.sign_position {
+            SignPosition::None => 0,
+            _ => 1,
+        }
+    }
+
+    /// Packs `value` into at most `width` bytes, exactly `width` without zero suppression
+    pub fn encode(&self, value: i64, width: usize) -> Result<Vec<u8>, NumericError> {
+        if value < 0 && self.sign_position == SignPosition::None {
+            return Err(NumericError::Negative { value });
+        }
+        let magnitude = value.unsigned_abs();
+        let digits = magnitude.to_string().into_bytes();
+        let sign_nibbles = self.sign_nibbles();
+
+        let bytes = match self.min_bytes {
+            Some(min_bytes) => min_bytes
+                .max((digits.len() + sign_nibbles + 1) / 2)
+                .min(width),
+            None => width,
+        };
+        let digit_slots = (bytes * 2).saturating_sub(sign_nibbles);
+        if digits.len() > digit_slots {
+            return Err(NumericError::TooWide {
+                value: magnitude,
+                width: digit_slots,
+            });
+        }
+
+        let mut nibbles = vec![0u8; digit_slots - digits.len()];
+        nibbles.extend(digits.iter().map(|digit| digit - b'0'));
+        let sign = if value < 0 {
+            self.negative_nibble
+        } else {
+            self.positive_nibble
+        };
+        match self.sign_position {
+            SignPosition::Leading => nibbles.insert(0, sign),
+            SignPosition::Trailing => nibbles.push(sign),
+            SignPosition::None => {}
+        }
+
+        Ok(nibbles
+            .chunks(2)
+            .map(|pair| (pair[0] << 4) | pair[1])
+            .collect())
+    }
+
+    /// Unpacks an amount, positions of `InvalidNibble` and `InvalidSign` count nibbles
+    pub fn decode(&self, packed: &[u8]) -> Result<i64, NumericError> {
+        if packed.is_empty() {
+            return Err(NumericError::Empty);
+        }
+        let nibbles: Vec<u8> = packed
+            .iter()
+            .flat_map(|byte| [byte >> 4, byte & 0x0F])
+            .collect();
+        let (sign, digits, first_digit) = match self.sign_position {
+            SignPosition::Leading => (Some((0, nibbles[0])), &nibbles[1..], 1),
+            SignPosition::Trailing => {
+                let last = nibbles.len() - 1;
+                (Some((last, nibbles[last])), &nibbles[..last], 0)
+            }
+            SignPosition::None => (None, &nibbles[..], 0),
+        };
+
+        let negative = match sign {
+            Some((_, nibble)) if nibble == self.positive_nibble => false,
+            Some((_, nibble)) if nibble == self.negative_nibble => true,
+            Some((position, nibble)) => return Err(NumericError::InvalidSign { position, nibble }),
+            None => false,
+        };
+        let mut magnitude = 0i64;
+        for (offset, &nibble) in digits.iter().enumerate() {
+            if nibble > 9 {
+                return Err(NumericError::InvalidNibble {
+                    position: first_digit + offset,
+                    nibble,
+                });
+            }
+            magnitude = magnitude
+                .checked_mul(10)
+                .and_then(|magnitude| magnitude.checked_add(i64::from(nibble)))
+                .ok_or(NumericError::Overflow)?;
+        }
+
+        Ok(if negative { -magnitude } else { magnitude })
+    }
+}
+
+/// Divides `numerator` by `divisor`, rounding the quotient with `rounding`
+pub fn divide_rounded(numerator: u128, divisor: u128, rounding: RoundingMode) -> u128 {
+    let (quotient, remainder) = (numerator / divisor, numerator % divisor);
+
+    let round_up = match rounding {
+        RoundingMode::Down => false,
+        RoundingMode::HalfUp => remainder * 2 >= divisor,
+        RoundingMode::HalfEven => {
+            remainder * 2 > divisor || (remainder * 2 == divisor && quotient % 2 == 1)
+        }
+    };
+
+    quotient + u128::from(round_up)
+}
+
+#[test]
+fn test_format_and_parse_fixed() {
+    assert_eq!(format_fixed(42, 4).unwrap(), b"0042".to_vec());
+    assert_eq!(format_fixed(0, 0), Err(NumericError::TooWide { value: 0, width: 0 }));
+    assert_eq!(
+        format_fixed(10000, 4),
+        Err(NumericError::TooWide {
+            value: 10000,
+            width: 4
+        })
+    );
+    assert_eq!(parse_fixed(b"0042"), Ok(42));
+    assert_eq!(parse_fixed(b"0A42"), Err(NumericError::InvalidDigit { position: 1 }));
+    assert_eq!(parse_fixed(b"99999999999999999999"), Err(NumericError::Overflow));
+    assert_eq!(parse_fixed(b""), Err(NumericError::Empty));
+
+    // parse(format(x)) == x or a typed error for every width
+    let mut seed = 0x9e37_79b9_7f4a_7c15u64;
+    for _ in 0..10_000 {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        let value = seed >> (seed % 64);
+        let width = (seed % 21) as usize;
+
+        match format_fixed(value, width) {
+            Ok(digits) => {
+                assert_eq!(digits.len(), width);
+                assert_eq!(parse_fixed(&digits), Ok(value));
+            }
+            Err(error) => assert_eq!(error, NumericError::TooWide { value, width }),
+        }
+    }
+}
+
+#[test]
+fn test_packed_amounts_round_trip() {
+    let trailing = PackedAmount::new();
+    assert_eq!(trailing.encode(0, 3), Ok(vec![0x00, 0x00, 0x0C]));
+    assert_eq!(trailing.encode(-1250, 3), Ok(vec![0x01, 0x25, 0x0D]));
+    assert_eq!(trailing.decode(&[0x01, 0x25, 0x0D]), Ok(-1250));
+    assert_eq!(trailing.decode(&[0x00, 0x00, 0x0C]), Ok(0));
+
+    // the 19 digits of the largest magnitude and the sign fill 10 bytes
+    for max in [i64::MAX, -i64::MAX] {
+        let packed = trailing.encode(max, 10).unwrap();
+        assert_eq!(packed.len(), 10);
+        assert_eq!(trailing.decode(&packed), Ok(max));
+    }
+    assert_eq!(
+        trailing.encode(i64::MAX, 9),
+        Err(NumericError::TooWide {
+            value: i64::MAX as u64,
+            width: 17
+        })
+    );
+    assert_eq!(
+        trailing.decode(&[0x99; 10]),
+        Err(NumericError::InvalidSign {
+            position: 19,
+            nibble: 9
+        })
+    );
+    let mut above_max = [0x99; 10];
+    above_max[9] = 0x9C;
+    assert_eq!(trailing.decode(&above_max), Err(NumericError::Overflow));
+
+    let leading = PackedAmount::new().with_sign(SignPosition::Leading, 0xA, 0xB);
+    assert_eq!(leading.encode(-42, 2), Ok(vec![0xB0, 0x42]));
+    assert_eq!(leading.decode(&[0xB0, 0x42]), Ok(-42));
+
+    let unsigned = PackedAmount::new().with_sign(SignPosition::None, 0, 0);
+    assert_eq!(unsigned.encode(1234, 3), Ok(vec![0x00, 0x12, 0x34]));
+    assert_eq!(unsigned.encode(-1, 3), Err(NumericError::Negative { value: -1 }));
+
+    // leading zero bytes are dropped down to the minimum byte count
+    let suppressed = PackedAmount::new().with_zero_suppression(2);
+    assert_eq!(suppressed.encode(-1250, 6), Ok(vec![0x01, 0x25, 0x0D]));
+    assert_eq!(suppressed.encode(7, 6), Ok(vec![0x00, 0x7C]));
+    assert_eq!(suppressed.encode(0, 6), Ok(vec![0x00, 0x0C]));
+    assert_eq!(suppressed.decode(&[0x01, 0x25, 0x0D]), Ok(-1250));
+
+    assert_eq!(
+        trailing.decode(&[0x01, 0x25, 0x0E]),
+        Err(NumericError::InvalidSign {
+            position: 5,
+            nibble: 0xE
+        })
+    );
+    assert_eq!(
+        trailing.decode(&[0x01, 0xA5, 0x0D]),
+        Err(NumericError::InvalidNibble {
+            position: 2,
+            nibble: 0xA
+        })
+    );
+    assert_eq!(trailing.decode(&[]), Err(NumericError::Empty));
+}
+
+#[test]
+fn test_zero_padded_formats_stay_in_this_module() {
+    let src = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("src");
+    let pattern = ["format!(\"{", ":0"].concat();
+
+    for entry in std::fs::read_dir(src).unwrap() {
+        let path = entry.unwrap().path();
+        if path.file_name() == Some(std::ffi::OsStr::new("numeric.rs")) {
+            continue;
+        }
+        let source = std::fs::read_to_string(&path).unwrap();
+
+        for (offset, _) in source.match_indices(&pattern) {
+            let spec = &source[offset + pattern.len()..];
+            let spec = &spec[..spec.find('}').unwrap_or(spec.len())];
+            // zero padded hexadecimal is an encoding, not a number emission
+            assert!(
+                spec.ends_with('X') || spec.ends_with('x'),
+                "{} formats a fixed width number, use numeric::format_fixed",
+                path.display()
+            );
+        }
+    }
+}
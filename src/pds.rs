@@ -1,8 +1,12 @@
+use crate::iso_error::{CodecError, IsoError};
+use crate::iso_msg::IsoMsg;
+use crate::numeric;
 use eyre::{eyre, Result};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 const PDS_LEN_SIZE: usize = 3;
 const PDS_ID_SIZE: usize = 4;
+const PDS_MAX_LEN: usize = 999;
 
 /// Each pds comes with a fixed id, length and value, each are concatenated to each other
 /// so a typical pds is (IIIILLLV(V+)+) where Id is always length 4, and the Length is always 3 characters
@@ -42,6 +46,172 @@ fn pds_details(pds_buffer: &str, position: usize) -> Option<(usize, &str, &str)>
     }
 }
 
+/// Sub-elements of a composite field parsed once, returned by `IsoMsg::composite`
+///
+/// Reads are served from a sorted map instead of rescanning the field, and any number
+/// of changes are written back with a single `IsoMsg::set_field` on `flush`. The handle
+/// doesn't borrow the message, it keeps the field value it was parsed from and `flush`
+/// refuses to overwrite the field with `IsoError::StaleComposite` once the field was set
+/// by other means. Sub-elements are written back sorted by tag.
+#[derive(Debug, Clone)]
+pub struct CompositeHandle {
+    index: usize,
+    parsed_from: Option<Vec<u8>>,
+    elements: BTreeMap<String, String>,
+    dirty: bool,
+}
+
+impl CompositeHandle {
+    pub(crate) fn parse(index: usize, value: Option<&[u8]>) -> Result<Self, IsoError> {
+        let elements = match value {
+            Some(value) => parse_elements(value).map_err(|error| IsoError::Codec { index, error })?,
+            None => BTreeMap::new(),
+        };
+
+        Ok(CompositeHandle {
+            index,
+            parsed_from: value.map(|value| value.to_vec()),
+            elements,
+            dirty: false,
+        })
+    }
+
+    /// Index of the composite field
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn get(&self, tag: &str) -> Option<&str> {
+        self.elements.get(tag).map(String::as_str)
+    }
+
+    /// Sets a sub-element, its tag is 4 digits and its value at most 999 bytes long
+    pub fn set(&mut self, tag: &str, value: &str) -> Result<(), IsoError> {
+        if tag.len() != PDS_ID_SIZE || !tag.bytes().all(|byte| byte.is_ascii_digit()) {
+            return Err(self.codec_error(format!("sub-element tag {:?} is not 4 digits", tag)));
+        }
+        if value.len() > PDS_MAX_LEN {
+            return Err(self.codec_error(format!(
+                "sub-element {} has {} bytes, at most {} are allowed",
+                tag,
+                value.len(),
+                PDS_MAX_LEN
+            )));
+        }
+
+        self.elements.insert(tag.to_string(), value.to_string());
+        self.dirty = true;
+        Ok(())
+    }
+
+    pub fn remove(&mut self, tag: &str) -> Option<String> {
+        let removed = self.elements.remove(tag);
+        self.dirty |= removed.is_some();
+        removed
+    }
+
+    /// Tags of the sub-elements, in ascending order
+    pub fn tags(&self) -> impl Iterator<Item = &str> {
+        self.elements.keys().map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.elements.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+
+    /// Whether changes were made since the field was parsed or last flushed
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Recomposes the field value from the sub-elements
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let capacity = self
+            .elements
+            .values()
+            .map(|value| PDS_ID_SIZE + PDS_LEN_SIZE + value.len())
+            .sum();
+        let mut bytes = Vec::with_capacity(capacity);
+        for (tag, value) in &self.elements {
+            bytes.extend_from_slice(tag.as_bytes());
+            let length = numeric::format_fixed(value.len() as u64, PDS_LEN_SIZE)
+                .expect("sub-element values are at most 999 bytes long");
+            bytes.extend_from_slice(&length);
+            bytes.extend_from_slice(value.as_bytes());
+        }
+
+        bytes
+    }
+
+    /// Writes the changes back to the field with a single `set_field`, or removes the field
+    /// once every sub-element is removed. Nothing is written when nothing changed.
+    pub fn flush(&mut self, iso_msg: &mut IsoMsg) -> Result<(), IsoError> {
+        let current = match iso_msg.get_field_slice(self.index) {
+            Ok(value) => Some(value),
+            Err(IsoError::FieldNotSet { .. }) => None,
+            Err(error) => return Err(error),
+        };
+        if current != self.parsed_from.as_deref() {
+            return Err(IsoError::StaleComposite { index: self.index });
+        }
+        if !self.dirty {
+            return Ok(());
+        }
+
+        if self.elements.is_empty() {
+            if self.parsed_from.is_some() {
+                iso_msg.remove_field(self.index)?;
+            }
+            self.parsed_from = None;
+        } else {
+            let bytes = self.to_bytes();
+            iso_msg.set_field(self.index, &bytes)?;
+            self.parsed_from = Some(bytes);
+        }
+        self.dirty = false;
+        Ok(())
+    }
+
+    fn codec_error(&self, reason: String) -> IsoError {
+        IsoError::Codec {
+            index: self.index,
+            error: CodecError::Invalid { reason },
+        }
+    }
+}
+
+/// Strict counterpart of `get_pds_values`, a truncated sub-element is an error
+fn parse_elements(value: &[u8]) -> Result<BTreeMap<String, String>, CodecError> {
+    let text = std::str::from_utf8(value).map_err(|error| CodecError::Utf8 {
+        position: error.valid_up_to(),
+    })?;
+    let mut elements = BTreeMap::new();
+    let mut position = 0usize;
+    while position < text.len() {
+        let value_position = position + PDS_ID_SIZE + PDS_LEN_SIZE;
+        let header = text.get(position..value_position).ok_or_else(|| CodecError::Invalid {
+            reason: format!("sub-element header truncated at position {}", position),
+        })?;
+        let (tag, length) = header.split_at(PDS_ID_SIZE);
+        let length = length.parse::<usize>().map_err(|_| CodecError::Invalid {
+            reason: format!("sub-element {} has the invalid length {:?}", tag, length),
+        })?;
+        let element = text
+            .get(value_position..value_position + length)
+            .ok_or_else(|| CodecError::Invalid {
+                reason: format!("sub-element {} is truncated", tag),
+            })?;
+        elements.insert(tag.to_string(), element.to_string());
+        position = value_position + length;
+    }
+
+    Ok(elements)
+}
+
 #[test]
 #[ignore]
 fn test_pds_reading() {
@@ -70,3 +240,32 @@ fn test_pds_reading_from_t121() {
     let pds_values = get_pds_values(&additional_data).unwrap().unwrap();
     assert_eq!(pds_values.get("0300").unwrap(), "0012303040000002337904401");
 }
+
+#[test]
+fn test_composite_round_trip() {
+    let additional_data = b"0002003MCG0023003NA 0158012          IV";
+
+    let mut handle = CompositeHandle::parse(48, Some(additional_data)).unwrap();
+    assert_eq!(handle.get("0158"), Some("          IV"));
+    assert_eq!(handle.to_bytes(), additional_data.to_vec());
+    assert!(!handle.is_dirty());
+
+    handle.set("0003", "MCG").unwrap();
+    assert_eq!(handle.remove("0023").as_deref(), Some("NA "));
+    assert_eq!(handle.to_bytes(), b"0002003MCG0003003MCG0158012          IV".to_vec());
+    assert!(handle.set("3", "MCG").is_err());
+}
+
+#[test]
+fn test_composite_truncated_element() {
+    let error = CompositeHandle::parse(48, Some(b"0002003MCG0003010MCG")).unwrap_err();
+    assert_eq!(
+        error,
+        IsoError::Codec {
+            index: 48,
+            error: CodecError::Invalid {
+                reason: "sub-element 0003 is truncated".to_string()
+            }
+        }
+    );
+}
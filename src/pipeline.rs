@@ -0,0 +1,159 @@
+use crate::capture::RecordReader;
+use crate::iso_error::IsoError;
+use crate::iso_msg::{FieldView, IsoMsg};
+use crate::iso_specs::IsoSpecs;
+use crate::validator::FieldValidator;
+
+/// Sees every present field of each record during the single walk of a `Pipeline`
+///
+/// Implement it to add a per-record computation, such as statistics or fingerprints,
+/// without walking the fields again. State kept across records is read back once the
+/// pipeline is dropped.
+pub trait FieldConsumer {
+    fn begin(&mut self, _iso_msg: &IsoMsg) {}
+    fn field(&mut self, field: FieldView);
+    fn end(&mut self, _iso_msg: &IsoMsg) {}
+}
+
+/// What a `Pipeline` gathered on one record
+#[derive(Debug, Clone, PartialEq)]
+pub struct PipelineOutput<D> {
+    /// values of the projected fields in `project` order, `None` for absent fields
+    pub projection: Vec<Option<Vec<u8>>>,
+    /// errors of the `validate` rules, in field order
+    pub validation_issues: Vec<IsoError>,
+    /// result of the `decorate` function, `None` without one
+    pub decoration: Option<D>,
+}
+
+type Decorator<'s, D> = Box<dyn Fn(&IsoMsg) -> D + 's>;
+
+/// Validates, projects and decorates each record in a single walk of its fields
+///
+/// ```ignore
+/// let mut pipeline = Pipeline::new(&spec)
+///     .validate(&DefaultValidator)
+///     .project(&[2, 4, 24, 49])
+///     .decorate(|iso_msg| iso_msg.length());
+/// for output in pipeline.run(&payload) { ... }
+/// ```
+pub struct Pipeline<'s, D = ()> {
+    iso_spec: &'s IsoSpecs,
+    rules: Option<&'s dyn FieldValidator>,
+    projection: Vec<usize>,
+    decorator: Option<Decorator<'s, D>>,
+    consumers: Vec<&'s mut dyn FieldConsumer>,
+}
+
+impl<'s> Pipeline<'s> {
+    pub fn new(iso_spec: &'s IsoSpecs) -> Pipeline<'s> {
+        Pipeline {
+            iso_spec,
+            rules: None,
+            projection: vec![],
+            decorator: None,
+            consumers: vec![],
+        }
+    }
+}
+
+impl<'s, D> Pipeline<'s, D> {
+    /// Checks every present field but the bitmap with `rules`
+    pub fn validate(self, rules: &'s dyn FieldValidator) -> Pipeline<'s, D> {
+        Pipeline {
+            rules: Some(rules),
+            ..self
+        }
+    }
+
+    /// Copies the values of the fields at these spec indexes
+    pub fn project(self, indexes: &[usize]) -> Pipeline<'s, D> {
+        Pipeline {
+            projection: indexes.to_vec(),
+            ..self
+        }
+    }
+
+    /// Computes a value of each record once its fields are walked
+    pub fn decorate<E, F>(self, decorator: F) -> Pipeline<'s, E>
+    where
+        F: Fn(&IsoMsg) -> E + 's,
+    {
+        Pipeline {
+            iso_spec: self.iso_spec,
+            rules: self.rules,
+            projection: self.projection,
+            decorator: Some(Box::new(decorator)),
+            consumers: self.consumers,
+        }
+    }
+
+    /// Adds a consumer fed by the same walk
+    pub fn consumer(mut self, consumer: &'s mut dyn FieldConsumer) -> Pipeline<'s, D> {
+        self.consumers.push(consumer);
+        self
+    }
+
+    /// Runs the single walk over a parsed message
+    pub fn process(&mut self, iso_msg: &IsoMsg) -> PipelineOutput<D> {
+        let bitmap_index = self.iso_spec.bitmap_index();
+        let mut projection = vec![None; self.projection.len()];
+        let mut validation_issues = vec![];
+        for consumer in self.consumers.iter_mut() {
+            consumer.begin(iso_msg);
+        }
+
+        for field in iso_msg {
+            let index = field.index();
+            if let Some(rules) = self.rules {
+                if Some(index) != bitmap_index {
+                    if let Err(error) = rules.validate(index, field.iso_field(), field.value()) {
+                        validation_issues.push(error);
+                    }
+                }
+            }
+            for (slot, projected) in projection.iter_mut().zip(&self.projection) {
+                if *projected == index {
+                    *slot = Some(field.value().to_vec());
+                }
+            }
+            for consumer in self.consumers.iter_mut() {
+                consumer.field(field);
+            }
+        }
+
+        for consumer in self.consumers.iter_mut() {
+            consumer.end(iso_msg);
+        }
+        PipelineOutput {
+            projection,
+            validation_issues,
+            decoration: self.decorator.as_ref().map(|decorator| decorator(iso_msg)),
+        }
+    }
+
+    /// Processes the records held back to back in `payload` as read by a `RecordReader`,
+    /// each one parsed once. A malformed record ends the run as nothing after it can be
+    /// located.
+    pub fn run<'p, 'a>(&'p mut self, payload: &'a [u8]) -> PipelineRun<'p, 'a, 's, D> {
+        PipelineRun {
+            records: RecordReader::new(self.iso_spec, payload),
+            pipeline: self,
+        }
+    }
+}
+
+/// Outputs of the records of a payload, see `Pipeline::run`
+pub struct PipelineRun<'p, 'a, 's, D> {
+    pipeline: &'p mut Pipeline<'s, D>,
+    records: RecordReader<'a, 's, 's>,
+}
+
+impl<D> Iterator for PipelineRun<'_, '_, '_, D> {
+    type Item = Result<PipelineOutput<D>, IsoError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let read = self.records.next_message()?;
+        Some(read.map(|(_, iso_msg)| self.pipeline.process(&iso_msg)))
+    }
+}
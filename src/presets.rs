@@ -0,0 +1,191 @@
+use crate::field_codec::LlVarSubFields;
+use crate::iso_field::{FieldCharType, FieldSizeType};
+use crate::iso_specs::{self, IsoSpecs};
+use crate::mti::MtiVersion;
+use crate::spec_cell::SpecCell;
+
+/// Spec of a revision of the standard, registered on a `SpecRegistry` with
+/// `registry.register(P::VERSION, P::spec().clone())`
+pub trait SpecPreset {
+    /// Version digit of the mti of the messages following the revision
+    const VERSION: MtiVersion;
+
+    /// Spec shared by every caller, built once on first access
+    fn spec() -> &'static IsoSpecs;
+}
+
+/// ISO 8583:1987, the fields introduced by 1993 keep their 1987 meaning
+pub struct Iso8583_1987Spec;
+
+/// ISO 8583:1993, the layout of the built-in spec
+pub struct Iso8583_1993Spec;
+
+/// ISO 8583:2003, `iso_specs::iso2003` with the sub-fields of DE 43 decoded by
+/// `IsoMsg::value`
+///
+/// DE 43 is the only composite field modelled, the other 2003 fields with sub-elements,
+/// like the point of service data code of DE 22, are read as plain values.
+pub struct Iso8583_2003Spec;
+
+static ISO1987: SpecCell<IsoSpecs> = SpecCell::new(iso1987);
+static ISO2003: SpecCell<IsoSpecs> = SpecCell::new(iso2003);
+
+/// Fields of the 1987 revision differing from the built-in 1993 spec, as
+/// (data element, label, char type, length, size type)
+const ISO1987_DELTAS: [(usize, &str, FieldCharType, usize, FieldSizeType); 14] = [
+    (
+        12,
+        "Time, Local Txn",
+        FieldCharType::Iso8583_n,
+        6,
+        FieldSizeType::Fixed,
+    ),
+    (
+        22,
+        "Point of Service Entry Mode",
+        FieldCharType::Iso8583_n,
+        3,
+        FieldSizeType::Fixed,
+    ),
+    (
+        24,
+        "Network International Id",
+        FieldCharType::Iso8583_n,
+        3,
+        FieldSizeType::Fixed,
+    ),
+    (
+        25,
+        "Point of Service Condition Code",
+        FieldCharType::Iso8583_n,
+        2,
+        FieldSizeType::Fixed,
+    ),
+    (
+        26,
+        "Point of Service PIN Capture Code",
+        FieldCharType::Iso8583_n,
+        2,
+        FieldSizeType::Fixed,
+    ),
+    (
+        28,
+        "Amount, Txn Fee",
+        FieldCharType::Iso8583_xn,
+        9,
+        FieldSizeType::Fixed,
+    ),
+    (
+        29,
+        "Amount, Settlement Fee",
+        FieldCharType::Iso8583_xn,
+        9,
+        FieldSizeType::Fixed,
+    ),
+    (
+        30,
+        "Amount, Txn Processing Fee",
+        FieldCharType::Iso8583_xn,
+        9,
+        FieldSizeType::Fixed,
+    ),
+    (
+        31,
+        "Amount, Settlement Processing Fee",
+        FieldCharType::Iso8583_xn,
+        9,
+        FieldSizeType::Fixed,
+    ),
+    (
+        39,
+        "Response Code",
+        FieldCharType::Iso8583_an,
+        2,
+        FieldSizeType::Fixed,
+    ),
+    (
+        40,
+        "Service Restriction Code",
+        FieldCharType::Iso8583_an,
+        3,
+        FieldSizeType::Fixed,
+    ),
+    (
+        43,
+        "Card Acceptor Name/Location",
+        FieldCharType::Iso8583_ans,
+        40,
+        FieldSizeType::Fixed,
+    ),
+    (
+        53,
+        "Security Related Control Info",
+        FieldCharType::Iso8583_n,
+        16,
+        FieldSizeType::Fixed,
+    ),
+    (
+        56,
+        "Reserved ISO",
+        FieldCharType::Iso8583_ans,
+        999,
+        FieldSizeType::LllVar,
+    ),
+];
+
+/// Sub-fields of the 2003 DE 43, each with a two digits length prefix
+const ISO2003_DE43_SUB_FIELDS: [&str; 6] = [
+    "name",
+    "street",
+    "city",
+    "postal_code",
+    "region",
+    "country_code",
+];
+
+fn iso1987() -> IsoSpecs {
+    let mut iso_spec = IsoSpecs::new();
+    for (de, label, char_type, length, size_type) in ISO1987_DELTAS {
+        let index = iso_spec.de_index(de).expect("built-in data element");
+        let field = &mut iso_spec.specs[index];
+        field.label = label.to_string();
+        field.char_type = char_type;
+        field.length = length;
+        field.size_type = size_type;
+    }
+
+    iso_spec
+}
+
+fn iso2003() -> IsoSpecs {
+    let mut iso_spec = iso_specs::iso2003();
+    let index = iso_spec.de_index(43).expect("built-in data element");
+    iso_spec
+        .field_codecs
+        .register(index, LlVarSubFields::new(&ISO2003_DE43_SUB_FIELDS));
+    iso_spec
+}
+
+impl SpecPreset for Iso8583_1987Spec {
+    const VERSION: MtiVersion = MtiVersion::Iso1987;
+
+    fn spec() -> &'static IsoSpecs {
+        ISO1987.get()
+    }
+}
+
+impl SpecPreset for Iso8583_1993Spec {
+    const VERSION: MtiVersion = MtiVersion::Iso1993;
+
+    fn spec() -> &'static IsoSpecs {
+        IsoSpecs::builtin()
+    }
+}
+
+impl SpecPreset for Iso8583_2003Spec {
+    const VERSION: MtiVersion = MtiVersion::Iso2003;
+
+    fn spec() -> &'static IsoSpecs {
+        ISO2003.get()
+    }
+}
@@ -0,0 +1,117 @@
+use crate::alloc_budget::AllocBudget;
+use crate::iso_error::IsoError;
+use crate::iso_msg::{IsoMsg, OwnedIsoMsg};
+use crate::iso_specs::IsoSpecs;
+use std::collections::{BTreeMap, HashMap};
+
+/// Hit, miss and eviction counters of a `RecordCache`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    /// payload bytes held by the cached records
+    pub cached_bytes: usize,
+}
+
+struct CacheEntry<'b> {
+    iso_msg: OwnedIsoMsg<'b>,
+    last_used: u64,
+}
+
+/// Parsed records of a payload keyed by their offset, such as the ones yielded by a
+/// `RecordReader`, kept under a budget of payload bytes with the least recently used
+/// records evicted first
+///
+/// Lookups take `&mut self` rather than hiding the bookkeeping behind a `RefCell`, a UI
+/// loop owns its cache and the borrow checker keeps a returned message from outliving
+/// the next lookup, which may evict it. A record larger than the whole budget is still
+/// cached, alone, so it can be returned.
+pub struct RecordCache<'a, 'b> {
+    iso_spec: &'b IsoSpecs,
+    payload: &'a [u8],
+    budget: usize,
+    alloc_budget: AllocBudget,
+    entries: HashMap<usize, CacheEntry<'b>>,
+    /// offsets by last use, the first one is evicted first
+    recency: BTreeMap<u64, usize>,
+    clock: u64,
+    stats: CacheStats,
+}
+
+impl<'a, 'b> RecordCache<'a, 'b> {
+    pub fn new(iso_spec: &'b IsoSpecs, payload: &'a [u8], budget: usize) -> RecordCache<'a, 'b> {
+        RecordCache {
+            iso_spec,
+            payload,
+            budget,
+            alloc_budget: AllocBudget::default(),
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            clock: 0,
+            stats: CacheStats::default(),
+        }
+    }
+
+    /// Bounds the copy of each record parsed into the cache, `AllocBudget::default()`
+    /// otherwise
+    pub fn with_alloc_budget(mut self, alloc_budget: AllocBudget) -> RecordCache<'a, 'b> {
+        self.alloc_budget = alloc_budget;
+        self
+    }
+
+    /// The record starting at `offset`, parsed on the first lookup and whenever it was
+    /// evicted since
+    pub fn get(&mut self, offset: usize) -> Result<&OwnedIsoMsg<'b>, IsoError> {
+        self.clock += 1;
+
+        if let Some(entry) = self.entries.get_mut(&offset) {
+            self.recency.remove(&entry.last_used);
+            self.recency.insert(self.clock, offset);
+            entry.last_used = self.clock;
+            self.stats.hits += 1;
+            return Ok(&self.entries[&offset].iso_msg);
+        }
+
+        self.stats.misses += 1;
+        let record = self.payload.get(offset..).ok_or(IsoError::UnexpectedEof {
+            field_index: 0,
+            needed: offset,
+            available: self.payload.len(),
+        })?;
+        let length = IsoMsg::try_new(self.iso_spec, record)?.length();
+        self.alloc_budget.check(length, "record")?;
+        let iso_msg = IsoMsg::try_from_owned(self.iso_spec, record[..length].to_vec())?;
+
+        while self.stats.cached_bytes + length > self.budget {
+            match self.recency.pop_first() {
+                Some((_, evicted)) => {
+                    let entry = self
+                        .entries
+                        .remove(&evicted)
+                        .expect("evicted an uncached record");
+                    self.stats.cached_bytes -= entry.iso_msg.length();
+                    self.stats.evictions += 1;
+                }
+                None => break,
+            }
+        }
+
+        self.stats.cached_bytes += length;
+        self.recency.insert(self.clock, offset);
+        let entry = CacheEntry {
+            iso_msg,
+            last_used: self.clock,
+        };
+        Ok(&self.entries.entry(offset).or_insert(entry).iso_msg)
+    }
+
+    /// Whether the record at `offset` is cached, without counting a lookup
+    pub fn contains(&self, offset: usize) -> bool {
+        self.entries.contains_key(&offset)
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+}
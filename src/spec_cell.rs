@@ -0,0 +1,166 @@
+//! Lazily built values shared by every thread, like the built-in specs
+//!
+//! With the `std` feature (on by default) a cell wraps `std::sync::OnceLock`. Without it the
+//! cell falls back to an atomic state flag that only needs `core`, so it keeps working where
+//! `OnceLock` isn't available, a first access racing a build spins until the value is ready.
+//! Either way only the first access builds the value, later accesses don't lock.
+//!
+//! The rest of the crate still links std, so leaving the `std` feature off only swaps the cell
+//! backend, it doesn't make the crate `no_std`.
+
+#[cfg(any(not(feature = "std"), test))]
+use core::cell::UnsafeCell;
+#[cfg(any(not(feature = "std"), test))]
+use core::mem::MaybeUninit;
+#[cfg(any(not(feature = "std"), test))]
+use core::sync::atomic::{AtomicU8, Ordering};
+#[cfg(feature = "std")]
+use std::sync::OnceLock;
+
+/// Lazily built value shared by every thread, the builder runs once on first access
+pub struct SpecCell<T> {
+    #[cfg(feature = "std")]
+    value: OnceLock<T>,
+    #[cfg(not(feature = "std"))]
+    value: SpinOnce<T>,
+    build: fn() -> T,
+}
+
+impl<T> SpecCell<T> {
+    pub const fn new(build: fn() -> T) -> SpecCell<T> {
+        SpecCell {
+            #[cfg(feature = "std")]
+            value: OnceLock::new(),
+            #[cfg(not(feature = "std"))]
+            value: SpinOnce::new(),
+            build,
+        }
+    }
+
+    /// Returns the shared value, building it when this is the first access
+    pub fn get(&self) -> &T {
+        // a builder panic leaves the cell empty, the next access simply retries it
+        self.value.get_or_init(self.build)
+    }
+}
+
+#[cfg(any(not(feature = "std"), test))]
+const EMPTY: u8 = 0;
+#[cfg(any(not(feature = "std"), test))]
+const BUILDING: u8 = 1;
+#[cfg(any(not(feature = "std"), test))]
+const READY: u8 = 2;
+
+/// `OnceLock` stand-in built on `core` atomics only, used when the `std` feature is off
+#[cfg(any(not(feature = "std"), test))]
+struct SpinOnce<T> {
+    state: AtomicU8,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+// the value is written once, by the thread that moved the state from EMPTY to BUILDING, and
+// only read after the state is READY
+#[cfg(any(not(feature = "std"), test))]
+unsafe impl<T: Send + Sync> Sync for SpinOnce<T> {}
+#[cfg(any(not(feature = "std"), test))]
+unsafe impl<T: Send> Send for SpinOnce<T> {}
+
+#[cfg(any(not(feature = "std"), test))]
+impl<T> SpinOnce<T> {
+    const fn new() -> SpinOnce<T> {
+        SpinOnce {
+            state: AtomicU8::new(EMPTY),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    fn get_or_init(&self, build: fn() -> T) -> &T {
+        loop {
+            let claimed =
+                self.state.compare_exchange(EMPTY, BUILDING, Ordering::Acquire, Ordering::Acquire);
+            match claimed {
+                Ok(_) => {
+                    // puts the cell back to EMPTY if the builder unwinds
+                    struct Reset<'a>(&'a AtomicU8);
+                    impl Drop for Reset<'_> {
+                        fn drop(&mut self) {
+                            self.0.store(EMPTY, Ordering::Release);
+                        }
+                    }
+
+                    let reset = Reset(&self.state);
+                    let value = build();
+                    core::mem::forget(reset);
+                    unsafe { (*self.value.get()).write(value) };
+                    self.state.store(READY, Ordering::Release);
+                }
+                Err(READY) => return unsafe { (*self.value.get()).assume_init_ref() },
+                Err(_) => core::hint::spin_loop(),
+            }
+        }
+    }
+}
+
+#[cfg(any(not(feature = "std"), test))]
+impl<T> Drop for SpinOnce<T> {
+    fn drop(&mut self) {
+        if *self.state.get_mut() == READY {
+            unsafe { self.value.get_mut().assume_init_drop() };
+        }
+    }
+}
+
+#[cfg(test)]
+fn hammer_first_access(get: fn() -> *const Vec<u8>) -> Vec<usize> {
+    use std::sync::{Arc, Barrier};
+
+    let barrier = Arc::new(Barrier::new(32));
+    let handles: Vec<_> = (0..32)
+        .map(|_| {
+            let barrier = Arc::clone(&barrier);
+            std::thread::spawn(move || {
+                barrier.wait();
+                get() as usize
+            })
+        })
+        .collect();
+    handles.into_iter().map(|h| h.join().unwrap()).collect()
+}
+
+#[test]
+fn first_access_from_many_threads_builds_once() {
+    use std::sync::atomic::AtomicUsize;
+
+    static BUILDS: AtomicUsize = AtomicUsize::new(0);
+    static CELL: SpecCell<Vec<u8>> = SpecCell::new(|| {
+        BUILDS.fetch_add(1, Ordering::SeqCst);
+        vec![1, 2, 3]
+    });
+
+    let addresses = hammer_first_access(|| CELL.get());
+
+    assert_eq!(BUILDS.load(Ordering::SeqCst), 1);
+    assert!(addresses.iter().all(|&address| address == addresses[0]));
+    assert_eq!(CELL.get(), &vec![1, 2, 3]);
+}
+
+#[test]
+fn spin_fallback_builds_once_and_retries_after_a_panic() {
+    use std::sync::atomic::AtomicUsize;
+
+    static BUILDS: AtomicUsize = AtomicUsize::new(0);
+    static CELL: SpinOnce<Vec<u8>> = SpinOnce::new();
+    fn build() -> Vec<u8> {
+        if BUILDS.fetch_add(1, Ordering::SeqCst) == 0 {
+            panic!("first build fails");
+        }
+        vec![1, 2, 3]
+    }
+
+    assert!(std::panic::catch_unwind(|| CELL.get_or_init(build)).is_err());
+    let addresses = hammer_first_access(|| CELL.get_or_init(build));
+
+    assert_eq!(BUILDS.load(Ordering::SeqCst), 2);
+    assert!(addresses.iter().all(|&address| address == addresses[0]));
+    assert_eq!(CELL.get_or_init(build), &vec![1, 2, 3]);
+}
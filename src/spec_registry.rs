@@ -0,0 +1,56 @@
+use crate::iso_error::IsoError;
+use crate::iso_msg::IsoMsg;
+use crate::iso_specs::{self, IsoSpecs};
+use crate::mti::{Mti, MtiVersion};
+use std::collections::BTreeMap;
+
+/// Specs keyed by the mti version digit, for files and channels mixing ISO 8583 revisions
+///
+/// The version is read from the first four bytes of a record, which every spec of the
+/// registry must start with the ascii mti for.
+#[derive(Default)]
+pub struct SpecRegistry {
+    specs: BTreeMap<u8, IsoSpecs>,
+}
+
+impl SpecRegistry {
+    pub fn new() -> SpecRegistry {
+        SpecRegistry::default()
+    }
+
+    /// The 1993 built-in spec for version `1` and the 2003 one for version `2`
+    pub fn standard() -> SpecRegistry {
+        let mut registry = SpecRegistry::new();
+        registry.register(MtiVersion::Iso1993, IsoSpecs::new());
+        registry.register(MtiVersion::Iso2003, iso_specs::iso2003());
+        registry
+    }
+
+    /// Registers the spec of a version, replacing any spec previously registered for it
+    pub fn register(&mut self, version: MtiVersion, iso_spec: IsoSpecs) {
+        self.specs.insert(version.digit(), iso_spec);
+    }
+
+    pub fn get(&self, version: MtiVersion) -> Option<&IsoSpecs> {
+        self.specs.get(&version.digit())
+    }
+
+    /// Spec of the record, chosen by the version digit of its mti
+    pub fn spec_for(&self, record: &[u8]) -> Result<&IsoSpecs, IsoError> {
+        let mti = record.get(..4).ok_or(IsoError::UnexpectedEof {
+            field_index: 0,
+            needed: 4,
+            available: record.len(),
+        })?;
+        let version = Mti::from_bytes(mti)?.version();
+
+        self.get(version).ok_or(IsoError::UnknownMtiVersion {
+            version: version.digit(),
+        })
+    }
+
+    /// Parses the record with the spec of its mti version
+    pub fn parse<'a>(&self, record: &'a [u8]) -> Result<IsoMsg<'a, '_>, IsoError> {
+        IsoMsg::try_new(self.spec_for(record)?, record)
+    }
+}
@@ -0,0 +1,128 @@
+use crate::iso_error::SpecError;
+use crate::iso_specs::IsoSpecs;
+use crate::numeric;
+use crate::yaml_specs::{SpecDocument, YamlField};
+use std::path::Path;
+
+/// A field as written on a toml spec, `index` is its position on the spec
+#[derive(Debug, Deserialize)]
+struct TomlField {
+    index: usize,
+    /// defaults to the index written with three digits, e.g `002`
+    #[serde(default)]
+    label_id: Option<String>,
+    label: String,
+    char_type: String,
+    size_type: String,
+    length: usize,
+    #[serde(default)]
+    required: bool,
+    #[serde(default)]
+    padding: Option<String>,
+    #[serde(default)]
+    length_encoding: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TomlDocument {
+    #[serde(default)]
+    bitmap_encoding: Option<String>,
+    #[serde(default)]
+    field: Vec<TomlField>,
+}
+
+/// Spec loaded from a toml definition, the fields may be listed in any order but their
+/// indexes must run from 0 without holes
+///
+/// ```toml
+/// bitmap_encoding = "binary"
+///
+/// [[field]]
+/// index = 0
+/// label_id = "mti"
+/// label = "Message Type Indicator"
+/// char_type = "mti"
+/// size_type = "fixed"
+/// length = 4
+///
+/// [[field]]
+/// index = 1
+/// label_id = "bitmaps"
+/// label = "Bitmaps"
+/// char_type = "bmp"
+/// size_type = "bitmap"
+/// length = 16
+///
+/// [[field]]
+/// index = 2
+/// label = "Primary Account Number"
+/// char_type = "ns"
+/// size_type = "llvar"
+/// length = 19
+/// ```
+pub struct TomlSpec {
+    handle: IsoSpecs,
+}
+
+impl TomlSpec {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(toml: &str) -> Result<TomlSpec, SpecError> {
+        let mut document: TomlDocument = toml::from_str(toml)?;
+        document.field.sort_by_key(|field| field.index);
+
+        let mut fields = Vec::with_capacity(document.field.len());
+        for (expected, field) in document.field.into_iter().enumerate() {
+            if field.index != expected {
+                return Err(SpecError::UnexpectedIndex {
+                    index: field.index,
+                    expected,
+                });
+            }
+            let label_id = field.label_id.unwrap_or_else(|| {
+                // indexes past 999 keep all their digits
+                let digits = numeric::format_fixed(field.index as u64, 3)
+                    .unwrap_or_else(|_| field.index.to_string().into_bytes());
+                String::from_utf8_lossy(&digits).into_owned()
+            });
+
+            fields.push(YamlField {
+                label_id,
+                label: field.label,
+                char_type: field.char_type,
+                size_type: field.size_type,
+                length: field.length,
+                required: field.required,
+                padding: field.padding,
+                length_encoding: field.length_encoding,
+                after: None,
+            });
+        }
+
+        let document = SpecDocument {
+            bitmap_encoding: document.bitmap_encoding,
+            fields,
+        };
+        Ok(TomlSpec {
+            handle: document.into_handle()?,
+        })
+    }
+
+    pub fn from_path(path: &Path) -> Result<TomlSpec, SpecError> {
+        TomlSpec::from_str(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn spec(&self) -> &IsoSpecs {
+        &self.handle
+    }
+
+    #[deprecated(since = "1.1.0", note = "please use `TomlSpec::spec` instead")]
+    pub fn get_handle(&self) -> &IsoSpecs {
+        self.spec()
+    }
+}
+
+impl From<TomlSpec> for IsoSpecs {
+    fn from(toml_spec: TomlSpec) -> Self {
+        toml_spec.handle
+    }
+}
@@ -0,0 +1,131 @@
+use crate::iso_error::{Track2Error, Track2Violation};
+
+/// Largest track 2 content, sentinels excluded
+pub const MAX_TRACK2_LEN: usize = 37;
+/// Largest primary account number held by a track
+pub const MAX_PAN_LEN: usize = 19;
+
+/// Track 2 equivalent data as found on DE 35, e.g `5555444433332222=2512101123`
+///
+/// The PAN is followed by a single separator, `=` or `D`, then the optional expiry date,
+/// service code and discretionary data, all made of digits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Track2<'a> {
+    pub pan: &'a [u8],
+    pub separator: u8,
+    /// YYMM, `None` on tracks ending before it
+    pub expiry: Option<&'a [u8]>,
+    pub service_code: Option<&'a [u8]>,
+    pub discretionary: &'a [u8],
+}
+
+impl<'a> Track2<'a> {
+    /// Parses a track, the start and end sentinels `;` and `?` are dropped when present and
+    /// error positions are counted on `track` as given
+    pub fn parse(track: &'a [u8]) -> Result<Track2<'a>, Track2Error> {
+        let error = |position, violation| Err(Track2Error::new(position, violation));
+        let start = usize::from(track.first() == Some(&b';'));
+        let end = track.len() - usize::from(track.len() > start && track.last() == Some(&b'?'));
+        let content = &track[start..end];
+
+        if content.len() > MAX_TRACK2_LEN {
+            return error(start + MAX_TRACK2_LEN, Track2Violation::TooLong);
+        }
+        let separator_position = match content.iter().position(|&byte| is_separator(byte)) {
+            Some(position) => position,
+            None => {
+                // a non digit within the PAN is a better hint than the missing separator
+                if let Some(position) = content.iter().position(|byte| !byte.is_ascii_digit()) {
+                    return error(start + position, Track2Violation::NonDigit);
+                }
+                return error(start + content.len(), Track2Violation::MissingSeparator);
+            }
+        };
+
+        let pan = &content[..separator_position];
+        if let Some(position) = pan.iter().position(|byte| !byte.is_ascii_digit()) {
+            return error(start + position, Track2Violation::NonDigit);
+        }
+        if pan.is_empty() {
+            return error(start, Track2Violation::MissingPan);
+        }
+        if pan.len() > MAX_PAN_LEN {
+            return error(start + MAX_PAN_LEN, Track2Violation::PanTooLong);
+        }
+
+        let data_start = separator_position + 1;
+        let data = &content[data_start..];
+        if let Some(position) = data.iter().position(|byte| !byte.is_ascii_digit()) {
+            let violation = if is_separator(data[position]) {
+                Track2Violation::ExtraSeparator
+            } else {
+                Track2Violation::NonDigit
+            };
+            return error(start + data_start + position, violation);
+        }
+
+        let expiry = data.get(..4);
+        let service_code = data.get(4..7);
+        // a partial expiry or service code means the track was cut
+        if (expiry.is_none() && !data.is_empty()) || (service_code.is_none() && data.len() > 4) {
+            return error(start + content.len(), Track2Violation::Truncated);
+        }
+
+        Ok(Track2 {
+            pan,
+            separator: content[separator_position],
+            expiry,
+            service_code,
+            discretionary: data.get(7..).unwrap_or_default(),
+        })
+    }
+}
+
+fn is_separator(byte: u8) -> bool {
+    byte == b'=' || byte == b'D'
+}
+
+#[test]
+fn test_track2_grammar() {
+    let track = Track2::parse(b"5555444433332222=2512101123").unwrap();
+    assert_eq!(track.pan, b"5555444433332222");
+    assert_eq!(track.separator, b'=');
+    assert_eq!(track.expiry, Some(&b"2512"[..]));
+    assert_eq!(track.service_code, Some(&b"101"[..]));
+    assert_eq!(track.discretionary, b"123");
+
+    // the `D` separator variant, wrapped in sentinels
+    let track = Track2::parse(b";5555444433332222D2512101?").unwrap();
+    assert_eq!((track.separator, track.discretionary), (b'D', &b""[..]));
+    assert_eq!(Track2::parse(b"4111111111111111=").unwrap().expiry, None);
+
+    let violation = |track: &[u8]| {
+        let error = Track2::parse(track).unwrap_err();
+        (error.position, error.violation)
+    };
+    assert_eq!(
+        violation(b"55554444333322222512101123"),
+        (26, Track2Violation::MissingSeparator)
+    );
+    assert_eq!(
+        violation(b"5555444433332222=25121011234567890123456"),
+        (37, Track2Violation::TooLong)
+    );
+    assert_eq!(
+        violation(b"55554444A3332222=2512101"),
+        (8, Track2Violation::NonDigit)
+    );
+    assert_eq!(
+        violation(b"5555444433332222=2512=101"),
+        (21, Track2Violation::ExtraSeparator)
+    );
+    assert_eq!(
+        violation(b"55554444333322221111=2512101"),
+        (19, Track2Violation::PanTooLong)
+    );
+    assert_eq!(violation(b"=2512101"), (0, Track2Violation::MissingPan));
+    assert_eq!(
+        violation(b"5555444433332222=25"),
+        (19, Track2Violation::Truncated)
+    );
+}
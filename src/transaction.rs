@@ -0,0 +1,77 @@
+use crate::iso_error::IsoError;
+use crate::iso_msg::IsoMsg;
+
+enum StagedChange {
+    Set { index: usize, value: Vec<u8> },
+    SetNum { index: usize, value: u64 },
+    Remove { index: usize },
+}
+
+/// Changes staged on a message by `IsoMsg::transaction`, applied together by `commit`
+///
+/// Linked fields, such as the DE 2 PAN repeated inside the DE 35 track, must change
+/// together. Nothing reaches the message before `commit`, which applies every staged
+/// change then checks `IsoMsg::validate_linked_fields`, any error leaving the message as
+/// it was. Dropping the transaction discards the staged changes.
+pub struct MsgTransaction<'m, 'a, 'b> {
+    iso_msg: &'m mut IsoMsg<'a, 'b>,
+    staged: Vec<StagedChange>,
+}
+
+impl<'m, 'a, 'b> MsgTransaction<'m, 'a, 'b> {
+    pub(crate) fn new(iso_msg: &'m mut IsoMsg<'a, 'b>) -> MsgTransaction<'m, 'a, 'b> {
+        MsgTransaction {
+            iso_msg,
+            staged: vec![],
+        }
+    }
+
+    /// Stages `IsoMsg::set_field`, the value is checked on commit
+    pub fn set_field(&mut self, index: usize, value: &[u8]) {
+        self.staged.push(StagedChange::Set {
+            index,
+            value: value.to_vec(),
+        });
+    }
+
+    pub fn set_field_str(&mut self, index: usize, value: &str) {
+        self.set_field(index, value.as_bytes());
+    }
+
+    /// Stages `IsoMsg::set_field_num`
+    pub fn set_field_num(&mut self, index: usize, value: u64) {
+        self.staged.push(StagedChange::SetNum { index, value });
+    }
+
+    /// Stages `IsoMsg::remove_field`
+    pub fn remove_field(&mut self, index: usize) {
+        self.staged.push(StagedChange::Remove { index });
+    }
+
+    /// Applies the staged changes in order, the first error rolls every one of them back
+    pub fn commit(self) -> Result<(), IsoError> {
+        let snapshot = self.iso_msg.fields_snapshot();
+        let result =
+            apply(self.iso_msg, &self.staged).and_then(|_| self.iso_msg.validate_linked_fields());
+        if result.is_err() {
+            self.iso_msg.restore_fields(snapshot);
+        }
+
+        result
+    }
+
+    /// Discards the staged changes, same as dropping the transaction
+    pub fn rollback(self) {}
+}
+
+fn apply(iso_msg: &mut IsoMsg, staged: &[StagedChange]) -> Result<(), IsoError> {
+    for change in staged {
+        match change {
+            StagedChange::Set { index, value } => iso_msg.set_field(*index, value)?,
+            StagedChange::SetNum { index, value } => iso_msg.set_field_num(*index, *value)?,
+            StagedChange::Remove { index } => iso_msg.remove_field(*index)?,
+        }
+    }
+
+    Ok(())
+}
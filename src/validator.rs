@@ -0,0 +1,65 @@
+use crate::iso_error::IsoError;
+use crate::iso_field::IsoField;
+
+/// Checks a value before `IsoMsg::set_field` stores it, implement it to add business rules
+pub trait FieldValidator {
+    fn validate(&self, index: usize, field: &IsoField, value: &[u8]) -> Result<(), IsoError>;
+}
+
+/// How `IsoMsg::validate_with` reports the structural checks, such as the track 2 grammar
+/// of `z` fields, the length and char type checks are errors in both modes
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ValidationMode {
+    #[default]
+    Strict,
+    /// structural violations are reported as warnings
+    Lenient,
+}
+
+/// Outcome of `IsoMsg::validate_with`
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ValidationReport {
+    pub errors: Vec<IsoError>,
+    pub warnings: Vec<IsoError>,
+}
+
+impl ValidationReport {
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+/// Validator installed unless another one is given, accepting every value. `set_field`
+/// still refuses values longer than the spec.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoValidator;
+
+pub(crate) static NO_VALIDATOR: NoValidator = NoValidator;
+
+impl FieldValidator for NoValidator {
+    fn validate(&self, _: usize, _: &IsoField, _: &[u8]) -> Result<(), IsoError> {
+        Ok(())
+    }
+}
+
+/// Enforces the char type character set and the spec length, opt in with
+/// `IsoMsg::with_validator` or `IsoMsg::new_with_validator`
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DefaultValidator;
+
+impl FieldValidator for DefaultValidator {
+    fn validate(&self, index: usize, field: &IsoField, value: &[u8]) -> Result<(), IsoError> {
+        if value.len() > field.length {
+            return Err(IsoError::ValueTooLong {
+                index,
+                max: field.length,
+                got: value.len(),
+            });
+        }
+
+        match value.iter().position(|&byte| !field.char_type.accepts(byte)) {
+            Some(position) => Err(IsoError::InvalidCharacter { index, position }),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,419 @@
+use crate::iso_error::SpecError;
+use crate::iso_field::{FieldCharType, FieldPadding, FieldSizeType, IsoField, LengthEncoding};
+use crate::iso_specs::{BitmapEncoding, IsoSpecs};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// A field as written on a yaml spec, e.g
+/// `{ label_id: "002", label: Primary Account Number, char_type: ns, size_type: llvar, length: 19 }`
+#[derive(Debug, Deserialize)]
+pub(crate) struct YamlField {
+    pub(crate) label_id: String,
+    pub(crate) label: String,
+    pub(crate) char_type: String,
+    pub(crate) size_type: String,
+    pub(crate) length: usize,
+    #[serde(default)]
+    pub(crate) required: bool,
+    #[serde(default)]
+    pub(crate) padding: Option<String>,
+    #[serde(default)]
+    pub(crate) length_encoding: Option<String>,
+    /// only used by overlays, places the added field right after this label id
+    #[serde(default)]
+    pub(crate) after: Option<String>,
+}
+
+impl YamlField {
+    fn to_iso_field(&self) -> Result<IsoField, SpecError> {
+        let padding = match &self.padding {
+            Some(padding) => padding_from(&self.label_id, padding)?,
+            None => FieldPadding::None,
+        };
+        let length_encoding = match &self.length_encoding {
+            Some(length_encoding) => length_encoding_from(&self.label_id, length_encoding)?,
+            None => LengthEncoding::Ascii,
+        };
+        Ok(IsoField {
+            required: self.required,
+            padding,
+            length_encoding,
+            ..IsoField::new(
+                &self.label,
+                &self.label_id,
+                char_type_from(&self.label_id, &self.char_type)?,
+                self.length,
+                size_type_from(&self.label_id, &self.size_type)?,
+            )
+        })
+    }
+}
+
+/// Partial field definition used to override a field of the base spec
+#[derive(Debug, Deserialize)]
+struct YamlFieldOverride {
+    label_id: String,
+    label: Option<String>,
+    char_type: Option<String>,
+    size_type: Option<String>,
+    length: Option<usize>,
+    required: Option<bool>,
+    padding: Option<String>,
+    length_encoding: Option<String>,
+}
+
+/// The spec document, shared by the yaml and json loaders
+#[derive(Debug, Deserialize)]
+pub(crate) struct SpecDocument {
+    #[serde(default)]
+    pub(crate) bitmap_encoding: Option<String>,
+    pub(crate) fields: Vec<YamlField>,
+}
+
+impl SpecDocument {
+    pub(crate) fn into_handle(self) -> Result<IsoSpecs, SpecError> {
+        let specs = self
+            .fields
+            .iter()
+            .map(YamlField::to_iso_field)
+            .collect::<Result<Vec<IsoField>, SpecError>>()?;
+        let mut handle = IsoSpecs::from_specs(specs);
+
+        if let Some(bitmap_encoding) = self.bitmap_encoding {
+            match bitmap_encoding.to_lowercase().as_str() {
+                "binary" => handle.bitmap_encoding = BitmapEncoding::Binary,
+                "hex" => handle.bitmap_encoding = BitmapEncoding::Hex,
+                "auto" => handle.detect_bitmap_encoding = true,
+                _ => {
+                    return Err(SpecError::UnknownType {
+                        label_id: "bitmap_encoding".to_string(),
+                        value: bitmap_encoding,
+                    })
+                }
+            };
+        }
+
+        Ok(handle)
+    }
+}
+
+/// Changes applied on top of a base spec by `YamlSpec::compose`
+///
+/// ```yaml
+/// priority: 1
+/// override:
+///   - { label_id: "048", length: 500 }
+/// add:
+///   - { label_id: "129", label: Extra, char_type: ans, size_type: lllvar, length: 999 }
+/// remove: ["127"]
+/// ```
+#[derive(Debug, Deserialize)]
+pub struct YamlOverlay {
+    /// overlays touching the same field are applied from the lowest to the highest priority
+    #[serde(default)]
+    priority: Option<u32>,
+    #[serde(default, rename = "override")]
+    overrides: Vec<YamlFieldOverride>,
+    #[serde(default)]
+    add: Vec<YamlField>,
+    #[serde(default)]
+    remove: Vec<String>,
+}
+
+impl YamlOverlay {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(yaml: &str) -> Result<YamlOverlay, SpecError> {
+        Ok(serde_yaml::from_str(yaml)?)
+    }
+
+    pub fn from_path(path: &Path) -> Result<YamlOverlay, SpecError> {
+        YamlOverlay::from_str(&std::fs::read_to_string(path)?)
+    }
+
+    fn touched_label_ids(&self) -> Vec<&str> {
+        let overridden = self.overrides.iter().map(|field| field.label_id.as_str());
+        let added = self.add.iter().map(|field| field.label_id.as_str());
+        let removed = self.remove.iter().map(|label_id| label_id.as_str());
+
+        overridden.chain(added).chain(removed).collect()
+    }
+
+    fn apply(&self, specs: &mut Vec<IsoField>) -> Result<(), SpecError> {
+        let position = |specs: &[IsoField], label_id: &str| -> Result<usize, SpecError> {
+            specs
+                .iter()
+                .position(|spec| spec.label_id == label_id)
+                .ok_or_else(|| SpecError::UnknownField {
+                    label_id: label_id.to_string(),
+                })
+        };
+
+        for label_id in self.remove.iter() {
+            let index = position(specs, label_id)?;
+            specs.remove(index);
+        }
+
+        for field in self.overrides.iter() {
+            let index = position(specs, &field.label_id)?;
+            let spec = &mut specs[index];
+            if let Some(label) = &field.label {
+                spec.label = label.to_string();
+            }
+            if let Some(char_type) = &field.char_type {
+                spec.char_type = char_type_from(&field.label_id, char_type)?;
+            }
+            if let Some(size_type) = &field.size_type {
+                spec.size_type = size_type_from(&field.label_id, size_type)?;
+            }
+            if let Some(length) = field.length {
+                spec.length = length;
+            }
+            if let Some(required) = field.required {
+                spec.required = required;
+            }
+            if let Some(padding) = &field.padding {
+                spec.padding = padding_from(&field.label_id, padding)?;
+            }
+            if let Some(length_encoding) = &field.length_encoding {
+                spec.length_encoding = length_encoding_from(&field.label_id, length_encoding)?;
+            }
+        }
+
+        for field in self.add.iter() {
+            if position(specs, &field.label_id).is_ok() {
+                return Err(SpecError::DuplicatedField {
+                    label_id: field.label_id.to_string(),
+                });
+            }
+            let index = match &field.after {
+                Some(label_id) => position(specs, label_id)? + 1,
+                None => specs.len(),
+            };
+            specs.insert(index, field.to_iso_field()?);
+        }
+
+        Ok(())
+    }
+}
+
+/// Spec loaded from a yaml definition, allowing dialects to be switched without recompiling
+///
+/// ```yaml
+/// bitmap_encoding: binary # or hex, auto detects it on each message
+/// fields:
+///   - { label_id: mti, label: Message Type Indicator, char_type: mti, size_type: fixed, length: 4 }
+///   - { label_id: bitmaps, label: Bitmaps, char_type: bmp, size_type: bitmap, length: 16 }
+///   - { label_id: "002", label: Primary Account Number, char_type: ns, size_type: llvar, length: 19 }
+/// ```
+pub struct YamlSpec {
+    handle: IsoSpecs,
+}
+
+impl YamlSpec {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(yaml: &str) -> Result<YamlSpec, SpecError> {
+        let document: SpecDocument = serde_yaml::from_str(yaml)?;
+
+        Ok(YamlSpec {
+            handle: document.into_handle()?,
+        })
+    }
+
+    pub fn from_path(path: &Path) -> Result<YamlSpec, SpecError> {
+        YamlSpec::from_str(&std::fs::read_to_string(path)?)
+    }
+
+    /// Builds a new spec by applying each overlay on top of `base`
+    ///
+    /// Overlays may remove, override or add fields. Two overlays changing the same field
+    /// are rejected unless both have distinct explicit priorities, in which case the highest
+    /// priority is applied last.
+    pub fn compose(base: &YamlSpec, overlays: &[YamlOverlay]) -> Result<YamlSpec, SpecError> {
+        let mut touched_by: HashMap<&str, usize> = HashMap::new();
+        for (overlay_index, overlay) in overlays.iter().enumerate() {
+            for label_id in overlay.touched_label_ids() {
+                if let Some(previous_index) = touched_by.insert(label_id, overlay_index) {
+                    let previous_priority = overlays[previous_index].priority;
+                    let is_ambiguous = previous_priority.is_none()
+                        || overlay.priority.is_none()
+                        || previous_priority == overlay.priority;
+
+                    if previous_index != overlay_index && is_ambiguous {
+                        return Err(SpecError::Conflict {
+                            label_id: label_id.to_string(),
+                            overlays: (previous_index, overlay_index),
+                        });
+                    }
+                }
+            }
+        }
+
+        let mut sorted_overlays: Vec<&YamlOverlay> = overlays.iter().collect();
+        sorted_overlays.sort_by_key(|overlay| overlay.priority.unwrap_or(0));
+
+        let mut specs = base.handle.specs.clone();
+        for overlay in sorted_overlays {
+            overlay.apply(&mut specs)?;
+        }
+
+        let mut handle = IsoSpecs::from_specs(specs);
+        handle.bitmap_encoding = base.handle.bitmap_encoding;
+        handle.detect_bitmap_encoding = base.handle.detect_bitmap_encoding;
+        handle.field_codecs = base.handle.field_codecs.clone();
+        handle.trailer = base.handle.trailer.clone();
+
+        Ok(YamlSpec { handle })
+    }
+
+    pub fn spec(&self) -> &IsoSpecs {
+        &self.handle
+    }
+
+    #[deprecated(since = "1.1.0", note = "please use `YamlSpec::spec` instead")]
+    pub fn get_handle(&self) -> &IsoSpecs {
+        self.spec()
+    }
+}
+
+impl From<YamlSpec> for IsoSpecs {
+    fn from(yaml_spec: YamlSpec) -> Self {
+        yaml_spec.handle
+    }
+}
+
+fn char_type_from(label_id: &str, char_type: &str) -> Result<FieldCharType, SpecError> {
+    FieldCharType::from_str(char_type).ok_or_else(|| SpecError::UnknownType {
+        label_id: label_id.to_string(),
+        value: char_type.to_string(),
+    })
+}
+
+fn size_type_from(label_id: &str, size_type: &str) -> Result<FieldSizeType, SpecError> {
+    FieldSizeType::from_str(size_type).ok_or_else(|| SpecError::UnknownType {
+        label_id: label_id.to_string(),
+        value: size_type.to_string(),
+    })
+}
+
+fn padding_from(label_id: &str, padding: &str) -> Result<FieldPadding, SpecError> {
+    FieldPadding::from_str(padding).ok_or_else(|| SpecError::UnknownType {
+        label_id: label_id.to_string(),
+        value: padding.to_string(),
+    })
+}
+
+fn length_encoding_from(label_id: &str, encoding: &str) -> Result<LengthEncoding, SpecError> {
+    LengthEncoding::from_str(encoding).ok_or_else(|| SpecError::UnknownType {
+        label_id: label_id.to_string(),
+        value: encoding.to_string(),
+    })
+}
+
+#[cfg(test)]
+const BASE_SPEC: &str = r#"
+bitmap_encoding: binary
+fields:
+  - { label_id: mti, label: Message Type Indicator, char_type: mti, size_type: fixed, length: 4 }
+  - { label_id: bitmaps, label: Bitmaps, char_type: bmp, size_type: bitmap, length: 16 }
+  - { label_id: "002", label: Primary Account Number, char_type: ns, size_type: llvar, length: 19 }
+  - { label_id: "003", label: Processing Code, char_type: ns, size_type: fixed, length: 6 }
+  - { label_id: "004", label: "Amount, Txn", char_type: n, size_type: fixed, length: 12 }
+"#;
+
+#[test]
+fn test_compose_override_add_and_remove() {
+    let base = YamlSpec::from_str(BASE_SPEC).unwrap();
+    let overlay = YamlOverlay::from_str(
+        r#"
+override:
+  - { label_id: "002", length: 16, size_type: fixed }
+  - { label_id: "004", required: true, padding: leading_zeros }
+add:
+  - { label_id: "005", label: "Amount, Reconciliation", char_type: n, size_type: fixed, length: 12 }
+remove: ["003"]
+"#,
+    )
+    .unwrap();
+
+    let composed = YamlSpec::compose(&base, &[overlay]).unwrap();
+    let label_ids: Vec<&str> = composed
+        .spec()
+        .specs
+        .iter()
+        .map(|spec| spec.label_id.as_str())
+        .collect();
+    assert_eq!(label_ids, vec!["mti", "bitmaps", "002", "004", "005"]);
+    let required = composed.spec().specs.iter().map(|spec| spec.required);
+    assert_eq!(required.collect::<Vec<_>>(), [false, false, false, true, false]);
+    assert_eq!(composed.spec().specs[3].padding, FieldPadding::LeadingZeros);
+
+    let deltas: Vec<String> = base
+        .spec()
+        .diff(composed.spec())
+        .iter()
+        .map(|delta| delta.to_string())
+        .collect();
+    assert_eq!(
+        deltas,
+        vec![
+            "~ 002: Primary Account Number (ns llvar 19) => Primary Account Number (ns fixed 16)",
+            "- 003: Processing Code (ns fixed 6)",
+            "~ 004: Amount, Txn (n fixed 12) => Amount, Txn (n fixed 12 required)",
+            "+ 005: Amount, Reconciliation (n fixed 12)",
+        ]
+    );
+}
+
+#[test]
+fn test_compose_conflicts() {
+    let base = YamlSpec::from_str(BASE_SPEC).unwrap();
+    let shorter_pan = "override: [{ label_id: \"002\", length: 16 }]";
+    let longer_pan = "override: [{ label_id: \"002\", length: 19 }]";
+
+    let conflict = YamlSpec::compose(
+        &base,
+        &[
+            YamlOverlay::from_str(shorter_pan).unwrap(),
+            YamlOverlay::from_str(longer_pan).unwrap(),
+        ],
+    );
+    assert!(matches!(
+        conflict,
+        Err(SpecError::Conflict { overlays: (0, 1), .. })
+    ));
+
+    let prioritized = YamlSpec::compose(
+        &base,
+        &[
+            YamlOverlay::from_str(&format!("priority: 2\n{}", shorter_pan)).unwrap(),
+            YamlOverlay::from_str(&format!("priority: 1\n{}", longer_pan)).unwrap(),
+        ],
+    )
+    .unwrap();
+    assert_eq!(prioritized.spec().specs[2].length, 16);
+}
+
+#[test]
+fn test_parse_with_composed_spec() {
+    let base = YamlSpec::from_str(BASE_SPEC).unwrap();
+    // fields are positional from the bitmap, so DE 5 to 23 must exist to reach DE 24
+    let mut overlay = String::from("add:\n");
+    for de in 5..24 {
+        overlay.push_str(&format!(
+            "  - {{ label_id: \"{:03}\", label: filler, char_type: ans, size_type: fixed, length: 1 }}\n",
+            de
+        ));
+    }
+    overlay.push_str(
+        "  - { label_id: \"024\", label: Function Code, char_type: an, size_type: fixed, length: 3 }\n",
+    );
+    let composed = YamlSpec::compose(&base, &[YamlOverlay::from_str(&overlay).unwrap()]).unwrap();
+
+    let mut payload = b"1644".to_vec();
+    payload.extend_from_slice(&[0x70, 0, 0x01, 0, 0, 0, 0, 0]);
+    payload.extend_from_slice(b"165555444433332222000000000000001000200");
+
+    let iso_msg = crate::iso_msg::IsoMsg::new(composed.spec(), &payload);
+    let function_code = iso_msg.present_fields()[5].iso_field_value(&payload);
+    assert_eq!(function_code, b"200".to_vec());
+}
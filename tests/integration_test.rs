@@ -1,7 +1,44 @@
-use iso8583::iso_msg::IsoMsg;
-use iso8583::iso_specs::IsoSpecs;
+use iso8583::builder::IsoMsgBuilder;
+use iso8583::capture::{Capture, Record, RecordReader, Replayer};
+use iso8583::char_types::CustomCharType;
+use iso8583::context::Context;
+use iso8583::field_codec::{DelimitedList, FieldCodec, FieldValue, SubField, SubFieldTable};
+use iso8583::file_utils::{frame_records, frame_records_for, Framing};
+use iso8583::alloc_budget::AllocBudget;
+use iso8583::framing::{
+    read_framed, read_framed_with, read_message_timeout, read_message_timeout_with, write_framed,
+    LengthHeader,
+};
+use iso8583::iso_error::{
+    CodecError, ConversionError, IsoError, NumericError, SpecError, Track2Error, Track2Violation,
+};
+use iso8583::iso_field::{FieldCharType, FieldPadding, FieldSizeType, IsoField, LengthEncoding};
+use iso8583::iso_msg::{FieldDiff, FieldView, IsoMsg, OwnedIsoMsg};
+#[cfg(feature = "serde")]
+use iso8583::iso_msg::IsoMsgSeed;
+use iso8583::iso_specs::{self, BitmapEncoding, IsoSpecs, LabelKeys, TrailerMarker};
+#[cfg(feature = "json-spec")]
+use iso8583::json_specs::JsonSpec;
+use iso8583::spec_registry::SpecRegistry;
+#[cfg(feature = "toml-spec")]
+use iso8583::toml_specs::TomlSpec;
+use iso8583::message_codes::{FunctionCode, MessageReasonCode};
+use iso8583::numeric::{PackedAmount, SignPosition};
+use iso8583::pipeline::{FieldConsumer, Pipeline, PipelineOutput};
+use iso8583::mti::{MessageClass, MessageFunction, Mti, MtiVersion, Originator};
+use iso8583::presets::{Iso8583_1987Spec, Iso8583_1993Spec, Iso8583_2003Spec, SpecPreset};
+use iso8583::record_cache::{CacheStats, RecordCache};
+use iso8583::validator::{DefaultValidator, FieldValidator, ValidationMode, ValidationReport};
+use iso8583::yaml_specs::YamlSpec;
+use iso8583::{Reconciliation, TrailerPolicy};
+#[cfg(feature = "serde")]
+use serde::de::DeserializeSeed;
+use std::borrow::Cow;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::time::UNIX_EPOCH;
 #[cfg(test)]
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 #[cfg(test)]
 use std::fs::File;
 #[cfg(test)]
@@ -10,7 +47,7 @@ use std::io::Read;
 #[test]
 fn parse_bitmap_binary() {
     let bitmap: &[u8] = &[128, 0, 1, 0, 0, 1, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0];
-    let bit_arrays = IsoMsg::process_bitmap(bitmap);
+    let bit_arrays = IsoMsg::process_bitmap(bitmap).unwrap();
     assert_eq!(format!("{:?}", bit_arrays), "10000000000000000000000100000000000000000000000100000000000000000000001000000000000000000000000000000000000000000000000000000000");
 }
 
@@ -22,7 +59,7 @@ fn parse_r111_binary() {
 
     let mut payload = vec![0; metadata.len() as usize];
 
-    file.read(&mut payload).expect("buffer overflow");
+    file.read_exact(&mut payload).expect("buffer overflow");
 
     let _iso8583_file: iso8583::Iso8583File = iso8583::parse_file(payload).unwrap();
 }
@@ -35,7 +72,7 @@ fn parse_invalid_file() {
 
     let mut payload = vec![0; metadata.len() as usize];
 
-    file.read(&mut payload).expect("buffer overflow");
+    file.read_exact(&mut payload).expect("buffer overflow");
 
     let _iso8583_file: iso8583::Iso8583File = iso8583::parse_file(payload).unwrap();
 }
@@ -48,7 +85,7 @@ fn parse_t113_blocked_with_rdw_binary() {
 
     let mut payload = vec![0; metadata.len() as usize];
 
-    file.read(&mut payload).expect("buffer overflow");
+    file.read_exact(&mut payload).expect("buffer overflow");
 
     let iso8583_file: iso8583::Iso8583File = iso8583::parse_file(payload).unwrap();
 
@@ -74,7 +111,7 @@ fn search_and_filter_t113_deblocked_sample() {
 
     let mut payload = vec![0; metadata.len() as usize];
 
-    file.read(&mut payload).expect("buffer overflow");
+    file.read_exact(&mut payload).expect("buffer overflow");
 
     let iso8583_file: iso8583::Iso8583File = iso8583::parse_file(payload).unwrap();
 
@@ -94,7 +131,7 @@ fn parse_t121_deblocked_sample() {
 
     let mut payload = vec![0; metadata.len() as usize];
 
-    file.read(&mut payload).expect("buffer overflow");
+    file.read_exact(&mut payload).expect("buffer overflow");
 
     let iso8583_file: iso8583::Iso8583File = iso8583::parse_file(payload).unwrap();
 
@@ -115,7 +152,7 @@ fn parse_t121_deblocked_sample() {
             } else {
                k
             };
-            let iso_field = iso_fields_ref.into_iter().find(|field| field.label_id == current_label_id);
+            let iso_field = iso_fields_ref.iter().find(|field| field.label_id == current_label_id);
             println!("orig: {} => {}", iso_field.unwrap().label, v.get_string());
         }
         println!("\n\n{:?}\n\n", &original_group.pds);
@@ -130,7 +167,7 @@ fn parse_t121_deblocked_sample() {
                k
             };
 
-            let iso_field = iso_fields_ref.into_iter().find(|field| field.label_id == current_label_id);
+            let iso_field = iso_fields_ref.iter().find(|field| field.label_id == current_label_id);
             println!("orig: {} => {}", iso_field.unwrap().label, v.get_string());
         }
 
@@ -147,7 +184,7 @@ fn parse_r119_binary() {
 
     let mut payload = vec![0; metadata.len() as usize];
 
-    file.read(&mut payload).expect("buffer overflow");
+    file.read_exact(&mut payload).expect("buffer overflow");
 
     let iso8583_file: iso8583::Iso8583File = iso8583::parse_file(payload).unwrap();
 
@@ -155,3 +192,3908 @@ fn parse_r119_binary() {
         println!("{:?}", message);
     }
 }
+
+#[test]
+fn parse_binary_and_hex_bitmaps() {
+    // DE 1 (secondary bitmap), 2, 3, 4, 24 and 71
+    let bitmap: [u8; 16] = [0xF0, 0, 0x01, 0, 0, 0, 0, 0, 0x02, 0, 0, 0, 0, 0, 0, 0];
+    let fields = b"16555544443333222200000000000000100020000000001";
+
+    let mut binary_payload = b"1644".to_vec();
+    binary_payload.extend_from_slice(&bitmap);
+    binary_payload.extend_from_slice(fields);
+
+    let mut hex_payload = b"1644F0000100000000000200000000000000".to_vec();
+    hex_payload.extend_from_slice(fields);
+
+    let binary_spec = IsoSpecs::new();
+    let mut hex_spec = IsoSpecs::new();
+    hex_spec.bitmap_encoding = BitmapEncoding::Hex;
+    hex_spec.specs[1].length = 32;
+
+    for (spec, payload) in [(&binary_spec, &binary_payload), (&hex_spec, &hex_payload)] {
+        let iso_msg = IsoMsg::new(spec, payload);
+        let values: Vec<Vec<u8>> = iso_msg
+            .present_fields()
+            .iter()
+            .skip(2)
+            .map(|field| field.iso_field_value(payload))
+            .collect();
+
+        assert_eq!(
+            values,
+            vec![
+                b"5555444433332222".to_vec(),
+                b"000000".to_vec(),
+                b"000000001000".to_vec(),
+                b"200".to_vec(),
+                b"00000001".to_vec()
+            ]
+        );
+        assert_eq!(iso_msg.length(), payload.len());
+
+        let mut buffer = [0u8; 1024];
+        let written = iso_msg.to_byte_array(&mut buffer).unwrap();
+        assert_eq!(&buffer[..written], payload.as_slice());
+        assert_eq!(iso_msg.to_vec().as_ref(), Ok(payload));
+    }
+}
+
+#[test]
+fn hex_bitmaps_only_decode_hexadecimal_digits() {
+    assert_eq!(
+        BitmapEncoding::Hex.decode(b"F000010000000000"),
+        Ok(vec![0xF0, 0, 0x01, 0, 0, 0, 0, 0])
+    );
+    assert_eq!(
+        BitmapEncoding::Hex.decode(b"F0000Z0000000000"),
+        Err(IsoError::InvalidBitmap { position: 5 })
+    );
+    // from_str_radix alone would accept the sign
+    assert_eq!(
+        BitmapEncoding::Hex.decode(b"+1"),
+        Err(IsoError::InvalidBitmap { position: 0 })
+    );
+    assert_eq!(
+        BitmapEncoding::Hex.decode(b"F00"),
+        Err(IsoError::InvalidBitmap { position: 3 })
+    );
+}
+
+#[test]
+fn round_trip_primary_bitmap_only() {
+    // DE 2, 3, 4 and 24, no secondary bitmap
+    let fields = b"165555444433332222000000000000001000200";
+
+    let mut binary_payload = b"1644".to_vec();
+    binary_payload.extend_from_slice(&[0x70, 0, 0x01, 0, 0, 0, 0, 0]);
+    binary_payload.extend_from_slice(fields);
+
+    let mut hex_payload = b"16447000010000000000".to_vec();
+    hex_payload.extend_from_slice(fields);
+
+    let binary_spec = IsoSpecs::new();
+    let mut hex_spec = IsoSpecs::new();
+    hex_spec.bitmap_encoding = BitmapEncoding::Hex;
+    hex_spec.specs[1].length = 32;
+
+    for (spec, payload) in [(&binary_spec, &binary_payload), (&hex_spec, &hex_payload)] {
+        let iso_msg = IsoMsg::new(spec, payload);
+
+        let function_code = iso_msg.present_fields()[5].iso_field_value(payload);
+        assert_eq!(function_code, b"200".to_vec());
+        assert_eq!(iso_msg.length(), payload.len());
+
+        let mut buffer = [0u8; 1024];
+        let written = iso_msg.to_byte_array(&mut buffer).unwrap();
+        assert_eq!(&buffer[..written], payload.as_slice());
+        assert_eq!(iso_msg.to_vec().as_ref(), Ok(payload));
+    }
+}
+
+fn binary_payload(mti: &[u8], bitmap: &[u8], fields: &[u8]) -> Vec<u8> {
+    let mut payload = mti.to_vec();
+    payload.extend_from_slice(bitmap);
+    payload.extend_from_slice(fields);
+    payload
+}
+
+#[test]
+fn to_vec_validated_rejects_invalid_messages() {
+    let spec = IsoSpecs::new();
+    let bitmap = [0x70, 0, 0x01, 0, 0, 0, 0, 0];
+
+    let valid_payload = binary_payload(b"1644", &bitmap, b"165555444433332222000000000000001000200");
+    let valid_msg = IsoMsg::new(&spec, &valid_payload);
+    assert_eq!(valid_msg.to_vec_validated(), Ok(valid_payload.clone()));
+
+    // DE 4 is numeric, a DE 2 longer than its maximum is already rejected while parsing
+    let invalid_payload = binary_payload(
+        b"1644",
+        &bitmap,
+        b"16555544443333222200000000000000010A200",
+    );
+    let invalid_msg = IsoMsg::new(&spec, &invalid_payload);
+    assert_eq!(
+        invalid_msg.to_vec_validated(),
+        Err(vec![IsoError::InvalidCharacter { index: 4, position: 11 }])
+    );
+}
+
+#[test]
+fn primary_bitmap_never_marks_secondary_fields() {
+    let bit_array = IsoMsg::process_bitmap(&[0x60, 0, 0, 0, 0, 0, 0, 0]).unwrap();
+    assert!((64..128).all(|bit| !bit_array.get(bit).unwrap()));
+    // shorter input than a primary bitmap is refused rather than indexed
+    assert_eq!(
+        IsoMsg::process_bitmap(&[]).unwrap_err(),
+        IsoError::TruncatedBitmap { needed: 8, available: 0 }
+    );
+    assert_eq!(
+        IsoMsg::process_bitmaps(&[0x80, 0, 0]).unwrap_err(),
+        IsoError::TruncatedBitmap { needed: 8, available: 3 }
+    );
+    assert_eq!(IsoMsg::process_bitmaps(&[0x80, 0, 0, 0, 0, 0, 0, 0]).unwrap().len(), 192);
+
+    // read as a secondary bitmap, the PAN right after the primary bitmap would flag fields above 64
+    let payload = binary_payload(
+        b"1644",
+        &[0x60, 0, 0, 0, 0, 0, 0, 0],
+        b"165555444433332222000000",
+    );
+    let spec = IsoSpecs::new();
+    let iso_msg = IsoMsg::new(&spec, &payload);
+
+    let present_ids: Vec<&str> = iso_msg
+        .present_fields()
+        .iter()
+        .map(|field| field.iso_field_label_id.as_str())
+        .collect();
+    assert_eq!(present_ids, vec!["mti", "bitmaps", "002", "003"]);
+    assert_eq!(iso_msg.present_fields()[1].len, 8);
+    assert_eq!(iso_msg.present_fields()[3].iso_field_value(&payload), b"000000".to_vec());
+}
+
+#[test]
+fn parse_spec_with_header_before_the_mti() {
+    let mut specs = vec![IsoField::new(
+        "Header",
+        "header",
+        FieldCharType::Iso8583_ans,
+        12,
+        FieldSizeType::Fixed,
+    )];
+    specs.extend(IsoSpecs::define_specs());
+    let spec = IsoSpecs::from_specs(specs);
+
+    let mut payload = b"IPMHEADER001".to_vec();
+    payload.extend(binary_payload(
+        b"1644",
+        &[0x70, 0, 0x01, 0, 0, 0, 0, 0],
+        b"165555444433332222000000000000001000200",
+    ));
+    let iso_msg = IsoMsg::new(&spec, &payload);
+
+    let values: Vec<(&str, Vec<u8>)> = iso_msg
+        .present_fields()
+        .iter()
+        .map(|field| (field.iso_field_label_id.as_str(), field.iso_field_value(&payload)))
+        .collect();
+    assert_eq!(values[0], ("header", b"IPMHEADER001".to_vec()));
+    assert_eq!(values[1], ("mti", b"1644".to_vec()));
+    assert_eq!(values[3], ("002", b"5555444433332222".to_vec()));
+    assert_eq!(values[6], ("024", b"200".to_vec()));
+    assert_eq!(iso_msg.to_vec().unwrap(), payload);
+}
+
+#[test]
+fn bitmap_encoding_follows_the_bitmap_char_type() {
+    let binary_spec = IsoSpecs::from_specs(IsoSpecs::define_specs());
+    assert_eq!(binary_spec.bitmap_encoding, BitmapEncoding::Binary);
+
+    let mut specs = IsoSpecs::define_specs();
+    specs[1] = IsoField::new(
+        "Bitmaps",
+        "bitmaps",
+        FieldCharType::Iso8583_bmps,
+        32,
+        FieldSizeType::BitMap,
+    );
+    let spec = IsoSpecs::from_specs(specs);
+    assert_eq!(spec.bitmap_encoding, BitmapEncoding::Hex);
+
+    let mut payload = b"16447000010000000000".to_vec();
+    payload.extend_from_slice(b"165555444433332222000000000000001000200");
+    let iso_msg = IsoMsg::new(&spec, &payload);
+
+    assert_eq!(
+        iso_msg.present_fields()[2].iso_field_value(&payload),
+        b"5555444433332222".to_vec()
+    );
+    assert_eq!(iso_msg.to_vec().unwrap(), payload);
+}
+
+#[test]
+fn iterate_over_present_fields() {
+    let spec = IsoSpecs::new();
+    let payload = binary_payload(
+        b"1644",
+        &[0x70, 0, 0x01, 0, 0, 0, 0, 0],
+        b"165555444433332222000000000000001000200",
+    );
+    let iso_msg = IsoMsg::new(&spec, &payload);
+
+    let fields: Vec<(usize, &str, &[u8])> = iso_msg
+        .iter()
+        .map(|field| (field.index(), field.label(), field.value()))
+        .collect();
+    assert_eq!(fields.len(), 6);
+    assert_eq!(fields[0], (0, "Message Type Indicator", b"1644".as_slice()));
+    assert_eq!(fields[2], (2, "Primary Account Number", b"5555444433332222".as_slice()));
+    assert_eq!(fields[5], (24, "Function Code", b"200".as_slice()));
+
+    let fields: Vec<(usize, &IsoField, &[u8])> = iso_msg.iter_fields().collect();
+    assert_eq!(fields.len(), 6);
+    let (index, pan, value) = fields[2];
+    assert_eq!(index, 2);
+    assert_eq!(pan.size_type, FieldSizeType::LlVar);
+    assert_eq!(pan.label_id, "002");
+    assert_eq!(value, b"5555444433332222");
+}
+
+#[test]
+fn describe_the_pan_field() {
+    let spec = IsoSpecs::new();
+    let payload = binary_payload(
+        b"1644",
+        &[0x70, 0, 0x01, 0, 0, 0, 0, 0],
+        b"165555444433332222000000000000001000200",
+    );
+    let iso_msg = IsoMsg::new(&spec, &payload);
+
+    let pan = iso_msg.describe_field(2).unwrap();
+    assert_eq!(pan.index, 2);
+    assert_eq!(pan.label, "Primary Account Number");
+    assert_eq!(pan.char_type, FieldCharType::Iso8583_ns);
+    assert_eq!(pan.size_type, FieldSizeType::LlVar);
+    assert_eq!(pan.max_len, 19);
+    assert!(pan.present);
+    assert_eq!(pan.raw_hex, "313635353535343434343333333332323232");
+    assert_eq!(pan.decoded.unwrap().get_string(), "5555444433332222");
+    assert_eq!(pan.byte_offset, 12);
+    assert_eq!(pan.byte_len, 18);
+
+    let absent = iso_msg.describe_field(5).unwrap();
+    assert!(!absent.present);
+    assert!(absent.decoded.is_none());
+    assert!(iso_msg.describe_field(500).is_none());
+}
+
+#[test]
+fn bitmap_length_follows_the_spec_and_continuation_bit() {
+    let fields = b"165555444433332222000000000000001000200";
+    let secondary_bitmap = [0xF0, 0, 0x01, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+
+    let spec = IsoSpecs::new();
+    let with_secondary = binary_payload(b"1644", &secondary_bitmap, fields);
+    assert_eq!(IsoMsg::new(&spec, &with_secondary).present_fields()[1].len, 16);
+
+    let mut hex_spec = IsoSpecs::new();
+    hex_spec.bitmap_encoding = BitmapEncoding::Hex;
+    hex_spec.specs[1].length = 32;
+    let hex_with_secondary = binary_payload(b"1644", b"F0000100000000000000000000000000", fields);
+    assert_eq!(IsoMsg::new(&hex_spec, &hex_with_secondary).present_fields()[1].len, 32);
+
+    // a spec only declaring a primary bitmap never reads a secondary one
+    let mut primary_only_spec = IsoSpecs::new();
+    primary_only_spec.specs[1].length = 8;
+    let payload = binary_payload(b"1644", &secondary_bitmap[..8], fields);
+    let iso_msg = IsoMsg::new(&primary_only_spec, &payload);
+    assert_eq!(iso_msg.present_fields()[1].len, 8);
+    assert_eq!(
+        iso_msg.present_fields()[2].iso_field_value(&payload),
+        b"5555444433332222".to_vec()
+    );
+    assert_eq!(iso_msg.length(), payload.len());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serialize_and_deserialize_field_map() {
+    let spec = IsoSpecs::new();
+    // DE 2, 3, 4, 24 and the binary DE 52
+    let mut fields = b"165555444433332222000000000000001000200".to_vec();
+    fields.extend_from_slice(&[0x01, 0x23, 0x45, 0x67, 0x89, 0xAB, 0xCD, 0xEF]);
+    let payload = binary_payload(b"1644", &[0x70, 0, 0x01, 0, 0, 0, 0x10, 0], &fields);
+    let iso_msg = IsoMsg::new(&spec, &payload);
+
+    let json = serde_json::to_string(&iso_msg).unwrap();
+    assert_eq!(
+        json,
+        r#"{"0":"1644","2":"5555444433332222","3":"000000","4":"000000001000","24":"200","52":"0123456789ABCDEF"}"#
+    );
+
+    let deserialized = IsoMsgSeed(&spec)
+        .deserialize(&mut serde_json::Deserializer::from_str(&json))
+        .unwrap();
+    assert_eq!(deserialized.to_vec().unwrap(), payload);
+
+    let invalid_index =
+        IsoMsgSeed(&spec).deserialize(&mut serde_json::Deserializer::from_str(r#"{"500":"1"}"#));
+    assert!(invalid_index.is_err());
+}
+
+#[test]
+fn set_field_around_the_bitmap() {
+    let mut specs = vec![IsoField::new(
+        "Header",
+        "header",
+        FieldCharType::Iso8583_ans,
+        12,
+        FieldSizeType::Fixed,
+    )];
+    specs.extend(IsoSpecs::define_specs());
+    let spec = IsoSpecs::from_specs(specs);
+
+    // positional fields set on an empty message are written in place without any bit
+    let mut iso_msg = IsoMsg::empty(&spec);
+    iso_msg.set_field(0, b"IPMHEADER001").unwrap();
+    iso_msg.set_field(1, b"1644").unwrap();
+    // DE 2 and DE 71 sit after the bitmap and get their presence bits
+    iso_msg.set_field(3, b"5555444433332222").unwrap();
+    iso_msg.set_field(72, b"00000001").unwrap();
+
+    let mut expected = b"IPMHEADER001".to_vec();
+    expected.extend(binary_payload(
+        b"1644",
+        &[0xC0, 0, 0, 0, 0, 0, 0, 0, 0x02, 0, 0, 0, 0, 0, 0, 0],
+        b"16555544443333222200000001",
+    ));
+    let serialized = iso_msg.to_vec().unwrap();
+    assert_eq!(serialized, expected);
+
+    // overwriting a positional field after parsing keeps every following offset right
+    let mut reparsed = IsoMsg::new(&spec, &serialized);
+    reparsed.set_field(0, b"IPMHEADER002").unwrap();
+    let rewritten = reparsed.to_vec().unwrap();
+    assert_eq!(&rewritten[..12], b"IPMHEADER002");
+    assert_eq!(&rewritten[12..], &expected[12..]);
+
+    // the bitmap is only ever regenerated and positional fields can't be dropped
+    assert!(reparsed.set_field(2, &[0xFF; 8]).is_err());
+    assert_eq!(reparsed.remove_field(0), Err(IsoError::PositionalField { index: 0 }));
+    assert_eq!(reparsed.remove_field(2), Err(IsoError::PositionalField { index: 2 }));
+    assert_eq!(reparsed.remove_field(500), Err(IsoError::InvalidField { index: 500 }));
+    assert!(reparsed.remove_field(72).is_ok());
+}
+
+#[test]
+fn get_and_set_text_fields() {
+    let spec = IsoSpecs::new();
+    let payload = binary_payload(
+        b"1644",
+        &[0x70, 0, 0x01, 0, 0, 0, 0, 0],
+        b"165555444433332222000000000000001000200",
+    );
+    let mut iso_msg = IsoMsg::new(&spec, &payload);
+
+    assert_eq!(iso_msg.get_field_str(2), Ok("5555444433332222".into()));
+    assert_eq!(iso_msg.get_field_str(24), Ok("200".into()));
+    assert_eq!(iso_msg.get_field_str(5), Err(IsoError::FieldNotSet { index: 5 }));
+    assert_eq!(iso_msg.get_field_str(500), Err(IsoError::InvalidField { index: 500 }));
+
+    iso_msg.set_field_str(2, "4111111111111111").unwrap();
+    iso_msg.set_field_str(48, "ADDITIONAL1DATA").unwrap();
+    assert_eq!(iso_msg.get_field_str(2), Ok("4111111111111111".into()));
+    assert_eq!(iso_msg.get_field_str(48), Ok("ADDITIONAL1DATA".into()));
+
+    assert_eq!(
+        iso_msg.set_field_str(2, "4111-1111"),
+        Err(IsoError::InvalidCharacter { index: 2, position: 4 })
+    );
+    assert_eq!(
+        iso_msg.set_field_str(24, "2 0"),
+        Err(IsoError::InvalidCharacter { index: 24, position: 1 })
+    );
+    assert_eq!(
+        iso_msg.set_field_str(4, "12AB"),
+        Err(IsoError::InvalidCharacter { index: 4, position: 2 })
+    );
+    assert_eq!(
+        iso_msg.set_field_str(2, "ABCD"),
+        Err(IsoError::InvalidCharacter { index: 2, position: 0 })
+    );
+    assert_eq!(
+        iso_msg.set_field_str(2, "41111111111111111111"),
+        Err(IsoError::ValueTooLong { index: 2, max: 19, got: 20 })
+    );
+    assert_eq!(iso_msg.set_field_str(1, "FF"), Err(IsoError::InvalidField { index: 1 }));
+    assert_eq!(iso_msg.get_field_str(2), Ok("4111111111111111".into()));
+}
+
+#[test]
+fn fixed_field_padding_survives_a_no_op_edit() {
+    let mut specs = IsoSpecs::define_specs();
+    // DE 3 as a text field padded with trailing spaces
+    specs[3] = IsoField::new(
+        "Processing Code",
+        "003",
+        FieldCharType::Iso8583_ans,
+        6,
+        FieldSizeType::Fixed,
+    );
+    let spec = IsoSpecs::from_specs(specs);
+    let payload = binary_payload(
+        b"1644",
+        &[0x70, 0, 0x01, 0, 0, 0, 0, 0],
+        b"165555444433332222AB    000000001000200",
+    );
+    let mut iso_msg = IsoMsg::new(&spec, &payload);
+
+    assert_eq!(iso_msg.present_fields()[3].detected_pad, FieldPadding::TrailingSpaces);
+    assert_eq!(iso_msg.present_fields()[4].detected_pad, FieldPadding::LeadingZeros);
+    assert_eq!(iso_msg.get_field_str_trimmed(3), Ok("AB".into()));
+    assert_eq!(iso_msg.get_field_str_trimmed(4), Ok("1000".into()));
+
+    let processing_code = iso_msg.get_field_str_trimmed(3).unwrap().to_string();
+    let amount = iso_msg.get_field_str_trimmed(4).unwrap().to_string();
+    iso_msg.set_field_str(3, &processing_code).unwrap();
+    iso_msg.set_field_str(4, &amount).unwrap();
+    assert_eq!(iso_msg.get_field_str(3), Ok("AB    ".into()));
+    assert_eq!(iso_msg.to_vec().unwrap(), payload);
+}
+
+#[test]
+fn detect_hex_and_binary_bitmaps() {
+    let mut spec = IsoSpecs::new();
+    spec.detect_bitmap_encoding = true;
+    // DE 2, 3, 4, 24 and DE 71 on the secondary bitmap
+    let fields = b"16555544443333222200000000000000100020000000001";
+    let bitmap = [0xF0, 0, 0x01, 0, 0, 0, 0, 0, 0x02, 0, 0, 0, 0, 0, 0, 0];
+    let binary = binary_payload(b"1644", &bitmap, fields);
+    let hex = binary_payload(b"1644", b"F0000100000000000200000000000000", fields);
+
+    let binary_msg = IsoMsg::new(&spec, &binary);
+    let hex_msg = IsoMsg::new(&spec, &hex);
+    assert_eq!(binary_msg.bitmap_encoding(), BitmapEncoding::Binary);
+    assert_eq!(hex_msg.bitmap_encoding(), BitmapEncoding::Hex);
+
+    let binary_fields: Vec<(usize, Vec<u8>)> = binary_msg
+        .iter()
+        .filter(|field| field.index() != 1)
+        .map(|field| (field.index(), field.value().to_vec()))
+        .collect();
+    let hex_fields: Vec<(usize, Vec<u8>)> = hex_msg
+        .iter()
+        .filter(|field| field.index() != 1)
+        .map(|field| (field.index(), field.value().to_vec()))
+        .collect();
+    assert_eq!(
+        binary_fields.iter().map(|(index, _)| *index).collect::<Vec<usize>>(),
+        vec![0, 2, 3, 4, 24, 71]
+    );
+    assert_eq!(binary_fields, hex_fields);
+
+    // each message is written back with the encoding it was read with, unless told otherwise
+    assert_eq!(binary_msg.to_vec().unwrap(), binary);
+    assert_eq!(hex_msg.to_vec().unwrap(), hex);
+    let mut converted = IsoMsg::new(&spec, &hex);
+    converted.set_bitmap_encoding(BitmapEncoding::Binary);
+    assert_eq!(converted.to_vec().unwrap(), binary);
+}
+
+#[test]
+fn parse_line_delimited_ascii_records() {
+    let mut options = iso8583::ReaderOptions {
+        framing: Framing::lines(),
+        ..Default::default()
+    };
+    options.spec.detect_bitmap_encoding = true;
+
+    let mut file = File::open("tests/ascii_records.txt").unwrap();
+    let mut lf_payload = vec![];
+    file.read_to_end(&mut lf_payload).unwrap();
+
+    let crlf_payload = String::from_utf8(lf_payload.clone())
+        .unwrap()
+        .replace('\n', "\r\n")
+        .into_bytes();
+    let mut bom_payload = b"\xEF\xBB\xBF".to_vec();
+    bom_payload.extend_from_slice(&lf_payload);
+    let without_final_newline = lf_payload[..lf_payload.len() - 1].to_vec();
+
+    for payload in [lf_payload, crlf_payload, bom_payload, without_final_newline] {
+        let iso8583_file = iso8583::parse_file_with(payload, &options).unwrap();
+        let pans: Vec<String> = iso8583_file
+            .messages
+            .iter()
+            .map(|message| message.data_elements["002"].get_string())
+            .collect();
+        assert_eq!(pans, vec!["5555444433332222", "4111111111111111"]);
+        assert_eq!(iso8583_file.messages[1].data_elements["024"].get_string(), "20A");
+        assert_eq!(iso8583_file.messages[0].primary_bitmap, [0x70, 0, 0x01, 0, 0, 0, 0, 0]);
+    }
+}
+
+#[test]
+fn line_delimited_records_reject_terminators_in_binary_fields() {
+    let options = iso8583::ReaderOptions {
+        framing: Framing::lines(),
+        ..Default::default()
+    };
+    // the binary DE 52 holds a 0x0A byte
+    let mut fields = b"165555444433332222000000000000001000200".to_vec();
+    fields.extend_from_slice(&[0x01, 0x23, 0x0A, 0x67, 0x89, 0xAB, 0xCD, 0xEF]);
+    let mut payload = binary_payload(b"1644", &[0x70, 0, 0x01, 0, 0, 0, 0x10, 0], &fields);
+    payload.push(b'\n');
+
+    let error = iso8583::parse_file_with(payload, &options).unwrap_err();
+    assert!(error.to_string().contains("use a length prefixed framing"));
+}
+
+#[test]
+fn records_without_a_function_code_have_an_unknown_category() {
+    let options = iso8583::ReaderOptions {
+        framing: Framing::lines(),
+        ..Default::default()
+    };
+    let mut payload = binary_payload(
+        b"1644",
+        &[0x70, 0, 0, 0, 0, 0, 0, 0],
+        b"165555444433332222000000000000001000",
+    );
+    payload.push(b'\n');
+
+    let iso8583_file = iso8583::parse_file_with(payload, &options).unwrap();
+    assert_eq!(iso8583_file.messages.len(), 1);
+    assert!(matches!(iso8583_file.messages[0].category, iso8583::iso_specs::Category::Unknown));
+    assert!(!iso8583_file.messages[0].data_elements.contains_key("024"));
+}
+
+#[test]
+fn frame_records_with_the_configured_terminator() {
+    let records = vec![b"1644".to_vec(), b"1240".to_vec()];
+    let crlf = Framing::LineDelimited {
+        terminators: vec![b"\r\n".to_vec()],
+    };
+
+    assert_eq!(frame_records(&records, &crlf), b"1644\r\n1240\r\n".to_vec());
+    assert_eq!(frame_records(&records, &Framing::lines()), b"1644\n1240\n".to_vec());
+    assert_eq!(
+        frame_records(&records, &Framing::Rdw),
+        b"\0\0\0\x041644\0\0\0\x041240".to_vec()
+    );
+}
+
+#[test]
+fn get_field_owned_strips_the_length_prefix() {
+    let spec = IsoSpecs::new();
+    let mut fields = b"165555444433332222000000000000001000200".to_vec();
+    fields.extend_from_slice(&[0x01, 0x23, 0x45, 0x67, 0x89, 0xAB, 0xCD, 0xEF]);
+    let payload = binary_payload(b"1644", &[0x70, 0, 0x01, 0, 0, 0, 0x10, 0], &fields);
+    let mut iso_msg = IsoMsg::new(&spec, &payload);
+
+    assert_eq!(iso_msg.get_field_owned(2), Ok(b"5555444433332222".to_vec()));
+    assert_eq!(
+        iso_msg.get_field_owned(52),
+        Ok(vec![0x01, 0x23, 0x45, 0x67, 0x89, 0xAB, 0xCD, 0xEF])
+    );
+    assert_eq!(iso_msg.get_field_owned(5), Err(IsoError::FieldNotSet { index: 5 }));
+    assert_eq!(iso_msg.get_field_owned(129), Err(IsoError::InvalidField { index: 129 }));
+
+    iso_msg.set_field(2, b"4111111111111111111").unwrap();
+    assert_eq!(iso_msg.get_field_owned(2), Ok(b"4111111111111111111".to_vec()));
+}
+
+#[test]
+fn get_field_str_only_reads_text_fields() {
+    let mut specs = IsoSpecs::define_specs();
+    specs[48] = IsoField::new(
+        "Additional Data",
+        "048",
+        FieldCharType::Iso8583_ans,
+        999,
+        FieldSizeType::LllVar,
+    );
+    let spec = IsoSpecs::from_specs(specs);
+    let mut fields = b"165555444433332222000000000000001000200004".to_vec();
+    fields.extend_from_slice(b"ab\xFFc");
+    fields.extend_from_slice(&[0xFF, 0xFF, 0x45, 0x67, 0x89, 0xAB, 0xCD, 0xEF]);
+    let payload = binary_payload(b"1644", &[0x70, 0, 0x01, 0, 0, 0x01, 0x10, 0], &fields);
+    let iso_msg = IsoMsg::new(&spec, &payload);
+
+    assert_eq!(iso_msg.get_field_str(0), Ok("1644".into()));
+    assert_eq!(iso_msg.get_field_str(3), Ok("000000".into()));
+    assert_eq!(iso_msg.get_field_str(52), Err(IsoError::NotTextField { index: 52 }));
+    assert_eq!(iso_msg.get_field_str(1), Err(IsoError::NotTextField { index: 1 }));
+    match iso_msg.get_field_str(48) {
+        Err(IsoError::Utf8 { index: 48, error }) => assert_eq!(error.valid_up_to(), 2),
+        other => panic!("expected an utf-8 error, got {:?}", other),
+    }
+
+    assert_eq!(iso_msg.get_field_string(2), Ok("5555444433332222".to_string()));
+    assert_eq!(iso_msg.get_field_string(52), Err(IsoError::NotTextField { index: 52 }));
+    assert_eq!(
+        IsoError::NotTextField { index: 52 }.to_string(),
+        "field 52 is a binary field, read it with get_field_slice"
+    );
+    assert_eq!(iso_msg.get_field_slice(52).unwrap()[..2], [0xFF, 0xFF]);
+    assert!(matches!(
+        iso_msg.get_field_string(48),
+        Err(IsoError::Utf8 { index: 48, .. })
+    ));
+}
+
+#[test]
+fn encode_batch_and_parse_it_back() {
+    let spec = IsoSpecs::new();
+    let first = binary_payload(
+        b"1644",
+        &[0x70, 0, 0x01, 0, 0, 0, 0, 0],
+        b"165555444433332222000000000000001000200",
+    );
+    let second = binary_payload(
+        b"1240",
+        &[0x70, 0, 0, 0, 0, 0, 0, 0],
+        b"194111111111111111111000000000000002500",
+    );
+    let messages = vec![IsoMsg::new(&spec, &first), IsoMsg::new(&spec, &second)];
+
+    for framing in [LengthHeader::Binary2, LengthHeader::Binary4, LengthHeader::Ascii4] {
+        let mut buffer = [0u8; 256];
+        let written = IsoMsg::encode_batch(&messages, framing, &mut buffer).unwrap();
+        assert_eq!(written, 2 * framing.header_len() + first.len() + second.len());
+
+        let parsed = IsoMsg::parse_all(&spec, &buffer[..written], framing).unwrap();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[0].to_vec().unwrap(), first);
+        assert_eq!(parsed[1].to_vec().unwrap(), second);
+
+        let truncated = IsoMsg::parse_all(&spec, &buffer[..written - 1], framing);
+        assert!(matches!(truncated, Err(IsoError::TruncatedFrame { .. })));
+    }
+
+    let mut small_buffer = [0u8; 32];
+    assert_eq!(
+        IsoMsg::encode_batch(&messages, LengthHeader::Binary2, &mut small_buffer),
+        Err(IsoError::BufferTooSmall {
+            needed: 2 + first.len(),
+            available: 32
+        })
+    );
+}
+
+#[test]
+fn recompute_billing_amount_from_the_conversion_rate() {
+    let spec = IsoSpecs::new();
+    // DE 4 and DE 10, 15.00 converted at 9.972522
+    let payload = binary_payload(
+        b"1240",
+        &[0x10, 0x40, 0, 0, 0, 0, 0, 0],
+        b"00000000150069972522",
+    );
+    let mut iso_msg = IsoMsg::new(&spec, &payload);
+
+    assert_eq!(iso_msg.recompute_billing_amount(), Ok(14959));
+    assert_eq!(iso_msg.get_field_str(6), Ok("000000014959".into()));
+    assert_eq!(iso_msg.validate_billing_amount(0), Ok(()));
+
+    iso_msg.set_field(6, b"000000014961").unwrap();
+    assert_eq!(iso_msg.validate_billing_amount(2), Ok(()));
+    assert_eq!(
+        iso_msg.validate_billing_amount(1),
+        Err(IsoError::Conversion(ConversionError::Mismatch {
+            recomputed: 14959,
+            received: 14961,
+            tolerance: 1
+        }))
+    );
+
+    iso_msg.set_field(4, b"999999999999").unwrap();
+    assert_eq!(
+        iso_msg.recompute_billing_amount(),
+        Err(IsoError::Conversion(ConversionError::Overflow {
+            amount: 9972521999990
+        }))
+    );
+    iso_msg.set_field(10, b"00000000").unwrap();
+    assert_eq!(
+        iso_msg.recompute_billing_amount(),
+        Err(IsoError::Conversion(ConversionError::ZeroRate))
+    );
+}
+
+#[test]
+fn round_trip_packed_bcd_pan() {
+    let mut specs = IsoSpecs::define_specs();
+    specs[2].char_type = FieldCharType::Iso8583_n_bcd;
+    specs[3].char_type = FieldCharType::Iso8583_n_bcd;
+    let spec = IsoSpecs::from_specs(specs);
+
+    // DE 2 holds 15 digits, left padded with a zero nibble, DE 3 holds 6 digits
+    let mut fields = b"15".to_vec();
+    fields.extend_from_slice(&[0x03, 0x78, 0x28, 0x22, 0x46, 0x31, 0x00, 0x05]);
+    fields.extend_from_slice(&[0x00, 0x20, 0x00]);
+    let payload = binary_payload(b"1100", &[0x60, 0, 0, 0, 0, 0, 0, 0], &fields);
+    let iso_msg = IsoMsg::new(&spec, &payload);
+
+    assert_eq!(iso_msg.get_field_owned(2), Ok(b"378282246310005".to_vec()));
+    assert_eq!(iso_msg.get_field_owned(3), Ok(b"002000".to_vec()));
+    // text accessors expand the nibbles, the value is owned rather than borrowed
+    assert_eq!(iso_msg.get_field_str(2), Ok("378282246310005".into()));
+    assert_eq!(iso_msg.get_field_str(3), Ok("002000".into()));
+    assert!(matches!(iso_msg.get_field_str(2), Ok(Cow::Owned(_))));
+    assert_eq!(iso_msg.get_field_string(2), Ok("378282246310005".to_string()));
+    assert_eq!(iso_msg.get_field_string(3), Ok("002000".to_string()));
+    assert_eq!(iso_msg.get_field_slice(3), Ok(&[0x00, 0x20, 0x00][..]));
+    assert_eq!(iso_msg.length(), payload.len());
+    assert_eq!(iso_msg.validate(), Ok(()));
+    assert_eq!(iso_msg.to_vec().unwrap(), payload);
+
+    let mut rebuilt = IsoMsg::empty(&spec);
+    rebuilt.set_field_str(0, "1100").unwrap();
+    rebuilt.set_field_str(2, "378282246310005").unwrap();
+    rebuilt.set_field_str(3, "2000").unwrap();
+    assert_eq!(rebuilt.to_vec().unwrap(), payload);
+
+    assert_eq!(IsoMsg::bcd_to_ascii(&[0x12, 0x34]), Ok(b"1234".to_vec()));
+    assert_eq!(IsoMsg::ascii_to_bcd(b"123"), Ok(vec![0x01, 0x23]));
+}
+
+#[test]
+fn packed_bcd_fields_only_hold_digits() {
+    let mut specs = IsoSpecs::define_specs();
+    specs[3].char_type = FieldCharType::Iso8583_n_bcd;
+    let spec = IsoSpecs::from_specs(specs);
+
+    // whatever the validator, a non digit can't be packed
+    let mut iso_msg = IsoMsg::empty(&spec).with_validator(&PermissiveValidator);
+    iso_msg.set_field(0, b"1100").unwrap();
+    assert_eq!(
+        iso_msg.set_field(3, b"AB12CD"),
+        Err(IsoError::InvalidCharacter { index: 3, position: 0 })
+    );
+    assert_eq!(
+        iso_msg.set_field(3, b"12 4"),
+        Err(IsoError::InvalidCharacter { index: 3, position: 2 })
+    );
+    assert_eq!(
+        iso_msg.set_field_raw(3, &[0x00, 0x2F, 0x00]),
+        Err(IsoError::InvalidCharacter { index: 3, position: 1 })
+    );
+    assert_eq!(iso_msg.get_field_str(3), Err(IsoError::FieldNotSet { index: 3 }));
+    iso_msg.set_field(3, b"1234").unwrap();
+    assert_eq!(iso_msg.get_field_str(3), Ok("001234".into()));
+
+    // nibbles A to F can't be expanded into digits either
+    let payload = binary_payload(b"1100", &[0x20, 0, 0, 0, 0, 0, 0, 0], &[0x00, 0x12, 0xCD]);
+    assert_eq!(
+        IsoMsg::try_new(&spec, &payload).unwrap_err(),
+        IsoError::InvalidCharacter { index: 3, position: 2 }
+    );
+
+    assert_eq!(
+        IsoMsg::bcd_to_ascii(&[0x12, 0x3A]),
+        Err(NumericError::InvalidNibble {
+            position: 3,
+            nibble: 0x0A
+        })
+    );
+    assert_eq!(
+        IsoMsg::ascii_to_bcd(b"12:4"),
+        Err(NumericError::InvalidDigit { position: 2 })
+    );
+}
+
+#[test]
+fn length_prefixes_must_be_digits_within_the_spec_maximum() {
+    let track_2 = IsoField::new(
+        "Track 2 Data",
+        "035",
+        FieldCharType::Iso8583_z,
+        37,
+        FieldSizeType::LlVar,
+    );
+    let additional_data = IsoField::new(
+        "Additional Data",
+        "048",
+        FieldCharType::Iso8583_an,
+        999,
+        FieldSizeType::LllVar,
+    );
+
+    assert_eq!(IsoMsg::get_field_length(&track_2, b"37"), Ok((39, 2)));
+    assert_eq!(
+        IsoMsg::get_field_length(&track_2, b"99"),
+        Err(IsoError::InvalidLengthPrefix {
+            label_id: "035".to_string(),
+            prefix: b"99".to_vec()
+        })
+    );
+    assert_eq!(
+        IsoMsg::get_field_length(&track_2, &[b'1', 0xFF]),
+        Err(IsoError::InvalidLengthPrefix {
+            label_id: "035".to_string(),
+            prefix: vec![b'1', 0xFF]
+        })
+    );
+    assert_eq!(
+        IsoMsg::get_field_length(&additional_data, b"0"),
+        Err(IsoError::InvalidLengthPrefix {
+            label_id: "048".to_string(),
+            prefix: b"0".to_vec()
+        })
+    );
+
+    // random prefixes either decode within the maximum or fail cleanly
+    let mut seed = 0x2545_f491_4f6c_dd1du64;
+    for _ in 0..10_000 {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        let prefix = seed.to_be_bytes();
+        let available = usize::from(prefix[0] % 5);
+
+        for iso_field in [&track_2, &additional_data] {
+            let prefix = &prefix[1..1 + available];
+            if let Ok((len, tag_len)) = IsoMsg::get_field_length(iso_field, prefix) {
+                assert!(len - tag_len <= iso_field.length);
+            }
+        }
+    }
+}
+
+#[test]
+fn validate_reports_desynced_length_prefixes() {
+    let spec = IsoSpecs::new();
+    let payload = binary_payload(
+        b"1644",
+        &[0x70, 0, 0x01, 0, 0, 0, 0, 0],
+        b"165555444433332222000000000000001000200",
+    );
+    let mut iso_msg = IsoMsg::new(&spec, &payload);
+    assert!(iso_msg.validate().is_ok());
+
+    // a captured DE 2 whose value grew behind its prefix back
+    iso_msg.set_field_raw(2, b"165555444433332222333").unwrap();
+    assert_eq!(
+        iso_msg.validate(),
+        Err(vec![IsoError::PrefixMismatch {
+            index: 2,
+            announced: 16,
+            got: 19
+        }])
+    );
+
+    iso_msg.set_field_raw(2, b"194111111111111111111").unwrap();
+    assert!(iso_msg.validate().is_ok());
+    assert_eq!(iso_msg.get_field_str(2), Ok("4111111111111111111".into()));
+
+    assert_eq!(
+        iso_msg.set_field_raw(2, b"1"),
+        Err(IsoError::InvalidLengthPrefix {
+            label_id: "002".to_string(),
+            prefix: b"1".to_vec()
+        })
+    );
+    assert_eq!(iso_msg.set_field_raw(1, b"00"), Err(IsoError::InvalidField { index: 1 }));
+}
+
+/// Only accepts amounts up to 1000.00 on DE 4, on top of the default checks
+struct AmountLimitValidator;
+
+impl FieldValidator for AmountLimitValidator {
+    fn validate(&self, index: usize, field: &IsoField, value: &[u8]) -> Result<(), IsoError> {
+        DefaultValidator.validate(index, field, value)?;
+        if field.label_id == "004" && value > b"000000100000".as_slice() {
+            return Err(IsoError::Rejected {
+                index,
+                reason: "amount above 1000.00".to_string(),
+            });
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn set_field_goes_through_the_validator() {
+    let spec = IsoSpecs::new();
+    let payload = binary_payload(
+        b"1644",
+        &[0x70, 0, 0x01, 0, 0, 0, 0, 0],
+        b"165555444433332222000000000000001000200",
+    );
+
+    // no validator is installed by default, only the spec length is enforced
+    let mut unchecked_msg = IsoMsg::new(&spec, &payload);
+    assert!(unchecked_msg.set_field(3, b"ABCDEF").is_ok());
+    assert_eq!(
+        unchecked_msg.set_field(24, b"2000"),
+        Err(IsoError::ValueTooLong { index: 24, max: 3, got: 4 })
+    );
+
+    let mut iso_msg = IsoMsg::new(&spec, &payload).with_validator(&DefaultValidator);
+    assert_eq!(
+        iso_msg.set_field(3, b"ABCDEF"),
+        Err(IsoError::InvalidCharacter { index: 3, position: 0 })
+    );
+    assert_eq!(
+        iso_msg.set_field(24, b"2000"),
+        Err(IsoError::ValueTooLong { index: 24, max: 3, got: 4 })
+    );
+    assert_eq!(iso_msg.get_field_str(3), Ok("000000".into()));
+    assert!(iso_msg.set_field(4, b"000000200000").is_ok());
+
+    let validator = AmountLimitValidator;
+    let mut limited_msg = IsoMsg::new_with_validator(&spec, &payload, &validator);
+    assert!(limited_msg.set_field(4, b"000000050000").is_ok());
+    assert_eq!(
+        limited_msg.set_field(4, b"000000200000"),
+        Err(IsoError::Rejected {
+            index: 4,
+            reason: "amount above 1000.00".to_string()
+        })
+    );
+    assert_eq!(
+        limited_msg.set_field(3, b"ABCDEF"),
+        Err(IsoError::InvalidCharacter { index: 3, position: 0 })
+    );
+    assert_eq!(limited_msg.get_field_str(4), Ok("000000050000".into()));
+}
+
+const BASE64_ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_validate(value: &[u8]) -> Option<usize> {
+    value
+        .iter()
+        .position(|byte| *byte != b'=' && !BASE64_ALPHABET.contains(byte))
+}
+
+fn base64_encode(value: &[u8]) -> Vec<u8> {
+    let mut encoded = vec![];
+    for chunk in value.chunks(3) {
+        let bytes = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let sextets = [
+            bytes[0] >> 2,
+            ((bytes[0] & 0x03) << 4) | (bytes[1] >> 4),
+            ((bytes[1] & 0x0F) << 2) | (bytes[2] >> 6),
+            bytes[2] & 0x3F,
+        ];
+        for (position, sextet) in sextets.iter().enumerate() {
+            if position <= chunk.len() {
+                encoded.push(BASE64_ALPHABET[usize::from(*sextet)]);
+            } else {
+                encoded.push(b'=');
+            }
+        }
+    }
+    encoded
+}
+
+fn base64_decode(value: &[u8]) -> Option<Vec<u8>> {
+    if value.len() % 4 != 0 {
+        return None;
+    }
+    let mut decoded = vec![];
+    for chunk in value.chunks(4) {
+        let sextets: Vec<u8> = chunk
+            .iter()
+            .take_while(|byte| **byte != b'=')
+            .map(|byte| BASE64_ALPHABET.iter().position(|letter| letter == byte).map(|p| p as u8))
+            .collect::<Option<Vec<u8>>>()?;
+        let bits = sextets
+            .iter()
+            .fold(0u32, |bits, sextet| (bits << 6) | u32::from(*sextet))
+            << (6 * (4 - sextets.len()));
+        decoded.extend_from_slice(&bits.to_be_bytes()[1..sextets.len()]);
+    }
+    Some(decoded)
+}
+
+#[test]
+fn custom_base64_char_type() {
+    let mut specs = IsoSpecs::define_specs();
+    specs[48].char_type = FieldCharType::from_str("custom:64").unwrap();
+    let mut spec = IsoSpecs::from_specs(specs);
+    spec.char_types.register(
+        64,
+        CustomCharType {
+            name: "base64",
+            validate: base64_validate,
+            encode: base64_encode,
+            decode: base64_decode,
+        },
+    );
+
+    let payload = binary_payload(
+        b"1644",
+        &[0x70, 0, 0x01, 0, 0, 0x01, 0, 0],
+        b"165555444433332222000000000000001000200012aXNvODU4Mw==",
+    );
+    let mut iso_msg = IsoMsg::new(&spec, &payload);
+    assert_eq!(iso_msg.get_field_owned(48), Ok(b"iso8583".to_vec()));
+    assert_eq!(iso_msg.validate(), Ok(()));
+
+    iso_msg.set_field_decoded(48, b"files").unwrap();
+    assert_eq!(iso_msg.get_field_str(48), Ok("ZmlsZXM=".into()));
+    assert_eq!(iso_msg.get_field_owned(48), Ok(b"files".to_vec()));
+    assert_eq!(
+        iso_msg.set_field(48, b"not base64!"),
+        Err(IsoError::InvalidCharacter { index: 48, position: 3 })
+    );
+
+    let unregistered = IsoSpecs::from_specs(spec.specs.clone());
+    let iso_msg = IsoMsg::new(&unregistered, &payload);
+    assert_eq!(
+        iso_msg.get_field_owned(48),
+        Err(IsoError::UnknownCharType { index: 48, id: 64 })
+    );
+}
+
+#[test]
+fn truncated_records_report_the_record_and_field() {
+    let options = iso8583::ReaderOptions {
+        framing: Framing::lines(),
+        ..Default::default()
+    };
+    let record = binary_payload(
+        b"1644",
+        &[0x70, 0, 0x01, 0, 0, 0, 0, 0],
+        b"165555444433332222000000000000001000200",
+    );
+    // the second record stops in the middle of DE 3
+    let mut payload = record.clone();
+    payload.push(b'\n');
+    payload.extend_from_slice(&record[..33]);
+
+    let error = iso8583::parse_file_with(payload, &options).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "record 1: field 3 ends at byte 36 but the payload only has 33 bytes"
+    );
+
+    let spec = IsoSpecs::new();
+    let mut framed = vec![0, 10];
+    framed.extend_from_slice(&record[..10]);
+    assert_eq!(
+        IsoMsg::parse_all(&spec, &framed, LengthHeader::Binary2).unwrap_err(),
+        IsoError::UnexpectedEof {
+            field_index: 1,
+            needed: 12,
+            available: 10
+        }
+    );
+}
+
+#[test]
+fn garbage_length_prefixes_fail_the_record_without_panicking() {
+    let spec = IsoSpecs::new();
+    // DE 2 length prefix is not made of digits
+    let record = binary_payload(
+        b"0100",
+        &[0x70, 0, 0x01, 0, 0, 0, 0, 0],
+        b"1A5555444433332222000000000000001000200",
+    );
+    let mut framed = vec![0, record.len() as u8];
+    framed.extend_from_slice(&record);
+
+    assert_eq!(
+        IsoMsg::parse_all(&spec, &framed, LengthHeader::Binary2).unwrap_err(),
+        IsoError::InvalidLengthPrefix {
+            label_id: "002".to_string(),
+            prefix: b"1A".to_vec()
+        }
+    );
+}
+
+#[test]
+fn random_payloads_parse_or_fail_without_panicking() {
+    let spec = IsoSpecs::builtin();
+    let mut seed = 0x9e37_79b9_7f4a_7c15u64;
+    let mut next = || {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        seed
+    };
+
+    let mut parsed = 0;
+    for round in 0..5_000 {
+        // a valid mti and a bitmap of DE 2 to DE 8 to reach the length prefixes
+        let mut payload = b"1644".to_vec();
+        payload.extend_from_slice(&[next() as u8 & 0x7F, 0, 0, 0, 0, 0, 0, 0]);
+        let len = (next() % 96) as usize;
+        payload.extend((0..len).map(|_| {
+            let byte = next() as u8;
+            // mostly digits, with invalid utf-8 sprinkled in
+            if round % 2 == 0 && byte < 200 {
+                b'0' + byte % 10
+            } else {
+                byte
+            }
+        }));
+
+        if let Ok(iso_msg) = IsoMsg::try_new(spec, &payload) {
+            parsed += 1;
+            for index in iso_msg.present_field_indices() {
+                let _ = iso_msg.get_field_slice(index);
+            }
+            let _ = iso_msg.to_vec();
+        }
+    }
+    assert!(parsed > 0);
+}
+
+#[test]
+fn corrupt_hex_bitmaps_fail_the_record_without_panicking() {
+    let mut spec = IsoSpecs::new();
+    spec.bitmap_encoding = BitmapEncoding::Hex;
+    spec.specs[1].length = 32;
+
+    // non hex bytes in the primary bitmap
+    let payload = b"1644ZZZZ000000000000165555444433332222";
+    assert_eq!(
+        IsoMsg::try_new(&spec, payload).unwrap_err(),
+        IsoError::InvalidBitmap { position: 0 }
+    );
+
+    // the primary bitmap announces a secondary bitmap holding non hex bytes
+    let payload = b"1644C0000000000000000000000000G0000001";
+    assert_eq!(
+        IsoMsg::try_new(&spec, payload).unwrap_err(),
+        IsoError::InvalidBitmap { position: 26 }
+    );
+
+    let mut seed = 0x2545_f491_4f6c_dd1du64;
+    let mut next = || {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        seed
+    };
+    for _ in 0..2_000 {
+        let mut payload = b"1644".to_vec();
+        // mostly hex digits with an occasional invalid byte
+        payload.extend((0..40).map(|_| match next() % 20 {
+            0 => next() as u8,
+            digit => b"0123456789ABCDEF"[digit as usize % 16],
+        }));
+        let _ = IsoMsg::try_new(&spec, &payload);
+    }
+}
+
+#[test]
+fn builtin_spec_is_shared() {
+    let spec = IsoSpecs::builtin();
+    assert!(std::ptr::eq(spec, IsoSpecs::builtin()));
+
+    let payload = binary_payload(
+        b"0100",
+        &[0x70, 0, 0x01, 0, 0, 0, 0, 0],
+        b"165555444433332222000000000000001000200",
+    );
+    let msg = IsoMsg::new(spec, &payload);
+    assert_eq!(msg.get_field_str(3).unwrap(), "000000");
+}
+
+#[test]
+fn compact_drops_an_empty_secondary_bitmap() {
+    let spec = IsoSpecs::new();
+    let payload = binary_payload(
+        b"0100",
+        &[0xF0, 0, 0x01, 0, 0, 0, 0, 0, 0x02, 0, 0, 0, 0, 0, 0, 0],
+        b"16555544443333222200000000000000100020000000001",
+    );
+    let mut iso_msg = IsoMsg::new(&spec, &payload);
+    let length = iso_msg.length();
+
+    iso_msg.remove_field(71).unwrap();
+    iso_msg.compact();
+
+    assert_eq!(iso_msg.length(), length - 8 - 8);
+    assert_eq!(iso_msg.present_fields()[1].len, 8);
+    assert_eq!(
+        iso_msg.to_vec().unwrap(),
+        binary_payload(
+            b"0100",
+            &[0x70, 0, 0x01, 0, 0, 0, 0, 0],
+            b"165555444433332222000000000000001000200",
+        )
+    );
+}
+
+#[test]
+fn from_byte_array_rejects_out_of_bounds_variable_fields() {
+    let spec = IsoSpecs::new();
+    // DE 48 announces 999 bytes but only 5 follow
+    let payload = binary_payload(b"0100", &[0, 0, 0, 0, 0, 0x01, 0, 0], b"999SHORT");
+    assert_eq!(
+        IsoMsg::from_byte_array(&spec, &payload).unwrap_err(),
+        IsoError::UnexpectedEof {
+            field_index: 48,
+            needed: 1014,
+            available: 20
+        }
+    );
+
+    // DE 35 can't announce more than its 37 bytes
+    let payload = binary_payload(b"0100", &[0, 0, 0, 0, 0x20, 0, 0, 0], b"99");
+    assert_eq!(
+        IsoMsg::from_byte_array(&spec, &payload).unwrap_err(),
+        IsoError::InvalidLengthPrefix {
+            label_id: "035".to_string(),
+            prefix: b"99".to_vec()
+        }
+    );
+
+    let payload = binary_payload(
+        b"0100",
+        &[0x70, 0, 0x01, 0, 0, 0, 0, 0],
+        b"165555444433332222000000000000001000200",
+    );
+    assert_eq!(IsoMsg::from_byte_array(&spec, &payload).unwrap().len(), spec.specs.len());
+}
+
+#[test]
+fn fields_sharing_a_label_are_exported_apart() {
+    let spec = IsoSpecs::new();
+    let national_use = "Reserved for National use";
+    assert_eq!(spec.find_by_label(national_use)[..2], [60, 61]);
+    assert_eq!(spec.find_unique_by_label("Transport Data").unwrap(), 59);
+    match spec.find_unique_by_label(national_use) {
+        Err(IsoError::AmbiguousLabel { indexes, .. }) => assert_eq!(indexes[..2], [60, 61]),
+        other => panic!("unexpected lookup {:?}", other),
+    }
+    assert!(matches!(
+        spec.find_unique_by_label("Not a label"),
+        Err(IsoError::UnknownLabel { .. })
+    ));
+
+    let mut iso_msg = IsoMsg::empty(&spec);
+    iso_msg.set_field(59, b"TRANSPORT").unwrap();
+    iso_msg.set_field(60, b"FIRST").unwrap();
+    iso_msg.set_field(61, b"SECOND").unwrap();
+
+    let values = iso_msg.labeled_values(LabelKeys::default());
+    assert_eq!(values["Transport Data"], "TRANSPORT");
+    assert_eq!(values["Reserved for National use (DE60)"], "FIRST");
+    assert_eq!(values["Reserved for National use (DE61)"], "SECOND");
+
+    let values = iso_msg.labeled_values(LabelKeys::Plain);
+    assert_eq!(values.len(), 2);
+    assert_eq!(values[national_use], "SECOND");
+}
+
+#[test]
+fn clone_owned_outlives_the_parsed_buffer() {
+    let spec = IsoSpecs::new();
+    let mut template = {
+        let payload = binary_payload(
+            b"0100",
+            &[0x70, 0, 0x01, 0, 0, 0, 0, 0],
+            b"165555444433332222000000000000001000200",
+        );
+        let mut request = IsoMsg::new(&spec, &payload);
+        request.set_field(4, b"000000002500").unwrap();
+        request.clone_owned()
+    };
+
+    let mut response = template.clone_owned();
+    response.set_field(3, b"200000").unwrap();
+    template.remove_field(24).unwrap();
+
+    assert_eq!(response.get_field_str(3).unwrap(), "200000");
+    assert_eq!(response.get_field_str(4).unwrap(), "000000002500");
+    assert_eq!(response.get_field_str(24).unwrap(), "200");
+    assert_eq!(template.get_field_str(3).unwrap(), "000000");
+    assert!(template.get_field_str(24).is_err());
+}
+
+#[test]
+fn trailer_records_are_flagged_by_mti_or_sentinel() {
+    let totals = |first: IsoField| {
+        IsoSpecs::from_specs(vec![
+            first,
+            IsoField::new(
+                "Record Count",
+                "count",
+                FieldCharType::Iso8583_n,
+                8,
+                FieldSizeType::Fixed,
+            ),
+            IsoField::new(
+                "Total Amount",
+                "total",
+                FieldCharType::Iso8583_n,
+                12,
+                FieldSizeType::Fixed,
+            ),
+        ])
+    };
+
+    let mti = IsoField::new(
+        "Message Type Indicator",
+        "mti",
+        FieldCharType::Iso8583_mti,
+        4,
+        FieldSizeType::Fixed,
+    );
+    let mut spec = totals(mti);
+    let payload = b"169500000042000000123456";
+    assert!(!IsoMsg::new(&spec, payload).is_trailer());
+
+    spec.trailer = Some(TrailerMarker::Mti(b"1695".to_vec()));
+    let trailer = IsoMsg::new(&spec, payload);
+    assert!(trailer.is_trailer());
+    assert_eq!(trailer.get_field_str(1).unwrap(), "00000042");
+    assert!(!IsoMsg::new(&spec, b"164400000042000000123456").is_trailer());
+
+    let record_type = IsoField::new(
+        "Record Type",
+        "record_type",
+        FieldCharType::Iso8583_an,
+        3,
+        FieldSizeType::Fixed,
+    );
+    let mut spec = totals(record_type);
+    spec.trailer = Some(TrailerMarker::Sentinel(b"TRL".to_vec()));
+    assert!(IsoMsg::new(&spec, b"TRL00000042000000123456").is_trailer());
+    assert!(!IsoMsg::new(&spec, b"HDR00000042000000123456").is_trailer());
+}
+
+#[test]
+fn try_new_lets_a_stream_skip_malformed_messages() {
+    let spec = IsoSpecs::new();
+    let good = binary_payload(
+        b"1644",
+        &[0x70, 0, 0x01, 0, 0, 0, 0, 0],
+        b"165555444433332222000000000000001000200",
+    );
+    let bad_prefix = binary_payload(b"1644", &[0x40, 0, 0, 0, 0, 0, 0, 0], b"1X5555");
+    let short_bitmap = b"1644\x70\x00".to_vec();
+    let records = [&good, &bad_prefix, &short_bitmap, &good];
+
+    let mut parsed = vec![];
+    let mut errors = vec![];
+    for record in records {
+        match IsoMsg::try_new(&spec, record) {
+            Ok(iso_msg) => parsed.push(iso_msg.get_field_str(2).unwrap().to_string()),
+            Err(error) => errors.push(error),
+        }
+    }
+
+    assert_eq!(parsed, vec!["5555444433332222", "5555444433332222"]);
+    assert_eq!(
+        errors,
+        vec![
+            IsoError::InvalidLengthPrefix {
+                label_id: "002".to_string(),
+                prefix: b"1X".to_vec()
+            },
+            IsoError::UnexpectedEof {
+                field_index: 1,
+                needed: 12,
+                available: 6
+            }
+        ]
+    );
+}
+
+/// Tag, two digit length and value entries, as carried by some private use fields
+struct TagLengthValue;
+
+impl FieldCodec for TagLengthValue {
+    fn decode(&self, value: &[u8]) -> Result<FieldValue, CodecError> {
+        let text = std::str::from_utf8(value).map_err(|error| CodecError::Utf8 {
+            position: error.valid_up_to(),
+        })?;
+        let mut entries = std::collections::BTreeMap::new();
+        let mut rest = text;
+        while !rest.is_empty() {
+            let invalid = || CodecError::Invalid {
+                reason: format!("truncated entry {:?}", rest),
+            };
+            let len: usize = rest.get(2..4).ok_or_else(invalid)?.parse().map_err(|_| invalid())?;
+            let value = rest.get(4..4 + len).ok_or_else(invalid)?;
+            entries.insert(rest[..2].to_string(), value.to_string());
+            rest = &rest[4 + len..];
+        }
+        Ok(FieldValue::SubFields(entries))
+    }
+
+    fn encode(&self, value: &FieldValue) -> Result<Vec<u8>, CodecError> {
+        match value {
+            FieldValue::SubFields(entries) => Ok(entries
+                .iter()
+                .map(|(tag, value)| format!("{}{:02}{}", tag, value.len(), value))
+                .collect::<String>()
+                .into_bytes()),
+            _ => Err(CodecError::UnexpectedValue),
+        }
+    }
+}
+
+fn composite_spec() -> IsoSpecs {
+    let mut spec = IsoSpecs::new();
+    spec.field_codecs.register(
+        62,
+        SubFieldTable::new(vec![SubField::new("network", 3), SubField::new("reference", 6)]),
+    );
+    spec.field_codecs.register(61, DelimitedList::new(b'|'));
+    spec.field_codecs.register(120, TagLengthValue);
+    spec
+}
+
+#[test]
+fn composite_fields_round_trip_through_codecs() {
+    let spec = composite_spec();
+
+    let mut iso_msg = IsoMsg::empty(&spec);
+    iso_msg.set_field(0, b"1644").unwrap();
+    let table = FieldValue::SubFields(
+        [("network", "MCC"), ("reference", "000123")]
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect(),
+    );
+    let list = FieldValue::List(vec!["A".to_string(), "BC".to_string(), "".to_string()]);
+    let tlv = FieldValue::SubFields(
+        [("01", "X"), ("02", "HELLO")]
+            .iter()
+            .map(|(tag, value)| (tag.to_string(), value.to_string()))
+            .collect(),
+    );
+    iso_msg.set_value(62, &table).unwrap();
+    iso_msg.set_value(61, &list).unwrap();
+    iso_msg.set_value(120, &tlv).unwrap();
+    assert_eq!(iso_msg.get_field_str(62).unwrap(), "MCC000123");
+    assert_eq!(iso_msg.get_field_str(61).unwrap(), "A|BC|");
+    assert_eq!(iso_msg.get_field_str(120).unwrap(), "0101X0205HELLO");
+
+    let payload = iso_msg.to_vec().unwrap();
+    let parsed = IsoMsg::new(&spec, &payload);
+    assert_eq!(parsed.value(62).unwrap(), table);
+    assert_eq!(parsed.value(61).unwrap(), list);
+    assert_eq!(parsed.value(120).unwrap(), tlv);
+    assert_eq!(parsed.value(0).unwrap(), FieldValue::Bytes(b"1644".to_vec()));
+    assert!(parsed.validate().is_ok());
+
+
+    let short_reference = FieldValue::SubFields(
+        [("network", "MCC"), ("reference", "123")]
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect(),
+    );
+    assert_eq!(
+        iso_msg.set_value(62, &short_reference),
+        Err(IsoError::Codec {
+            index: 62,
+            error: CodecError::SubFieldLength {
+                name: "reference".to_string(),
+                expected: 6,
+                got: 3
+            }
+        })
+    );
+    iso_msg.set_field(62, b"MCC").unwrap();
+    assert_eq!(
+        iso_msg.validate(),
+        Err(vec![IsoError::Codec {
+            index: 62,
+            error: CodecError::InvalidLength {
+                expected: 9,
+                got: 3
+            }
+        }])
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn composite_fields_serialize_as_their_decoded_values() {
+    let spec = composite_spec();
+    let mut iso_msg = IsoMsg::empty(&spec);
+    iso_msg.set_field(0, b"1644").unwrap();
+    let list = FieldValue::List(vec!["A".to_string(), "BC".to_string(), "".to_string()]);
+    iso_msg.set_value(61, &list).unwrap();
+    let payload = iso_msg.to_vec().unwrap();
+    let parsed = IsoMsg::new(&spec, &payload);
+
+    let json = serde_json::to_string(&parsed).unwrap();
+    assert!(json.contains(r#""61":["A","BC",""]"#));
+    let from_json = IsoMsgSeed(&spec)
+        .deserialize(&mut serde_json::Deserializer::from_str(&json))
+        .unwrap();
+    assert_eq!(from_json.to_vec().unwrap(), payload);
+}
+
+#[test]
+fn get_field_slice_borrows_values_without_prefix() {
+    let spec = IsoSpecs::new();
+    let payload = binary_payload(
+        b"1644",
+        &[0x70, 0, 0x01, 0, 0, 0, 0, 0],
+        b"165555444433332222000000000000001000200",
+    );
+    let mut iso_msg = IsoMsg::new(&spec, &payload);
+
+    let pan = iso_msg.get_field_slice(2).unwrap();
+    assert_eq!(pan, b"5555444433332222");
+    assert!(payload.as_ptr_range().contains(&pan.as_ptr()));
+    assert_eq!(iso_msg.get_field_slice(4).unwrap(), b"000000001000");
+
+    iso_msg.set_field(2, b"4111111111111111").unwrap();
+    assert_eq!(iso_msg.get_field_slice(2).unwrap(), b"4111111111111111");
+
+    assert_eq!(iso_msg.get_field_slice(6), Err(IsoError::FieldNotSet { index: 6 }));
+    assert_eq!(iso_msg.get_field_slice(500), Err(IsoError::InvalidField { index: 500 }));
+}
+
+#[test]
+fn read_and_write_the_mti() {
+    let spec = IsoSpecs::new();
+    let payload = binary_payload(
+        b"1644",
+        &[0x70, 0, 0x01, 0, 0, 0, 0, 0],
+        b"165555444433332222000000000000001000200",
+    );
+    let mut iso_msg = IsoMsg::new(&spec, &payload);
+
+    let mti = iso_msg.mti().unwrap();
+    assert_eq!(
+        (
+            mti.version(),
+            mti.message_class(),
+            mti.function(),
+            mti.originator()
+        ),
+        (
+            MtiVersion::Iso1993,
+            MessageClass::Administrative,
+            MessageFunction::Notification,
+            Originator::Other
+        )
+    );
+
+    let request = Mti::parse(b"0100").unwrap();
+    assert_eq!(request.version(), MtiVersion::Iso1987);
+    assert_eq!(request.message_class(), MessageClass::Authorization);
+    assert_eq!(request.function(), MessageFunction::Request);
+    assert_eq!(request.originator(), Originator::Acquirer);
+    assert_eq!(request.to_bytes(), *b"0100");
+
+    iso_msg.set_mti(Mti::from_str("0110").unwrap()).unwrap();
+    let response = iso_msg.mti().unwrap();
+    assert_eq!(response.function(), MessageFunction::RequestResponse);
+    assert_eq!(response.message_class().digit(), 1);
+    assert_eq!(response.to_string(), "0110");
+    assert_eq!(Mti::parse(b"0190").unwrap().function(), MessageFunction::Reserved(9));
+    assert_eq!(&iso_msg.to_vec().unwrap()[..4], b"0110");
+
+    assert_eq!(
+        Mti::from_str("01A0"),
+        Err(IsoError::InvalidCharacter {
+            index: 0,
+            position: 2
+        })
+    );
+    assert_eq!(
+        Mti::from_str("010"),
+        Err(IsoError::InvalidLength {
+            index: 0,
+            expected: 4,
+            got: 3
+        })
+    );
+    assert_eq!(
+        IsoMsg::empty(&spec).mti(),
+        Err(IsoError::FieldNotSet { index: 0 })
+    );
+}
+
+#[test]
+fn access_fields_by_data_element_number() {
+    let spec = IsoSpecs::new();
+    let mut iso_msg = IsoMsg::empty(&spec);
+    iso_msg.set_field(0, b"1644").unwrap();
+    iso_msg.set_field(spec.de_index(39).unwrap(), b"000").unwrap();
+    iso_msg.set_field(2, b"5555444433332222").unwrap();
+
+    let mut buffer = [0u8; 32];
+    let len = iso_msg.get_iso_field(39, &mut buffer).unwrap();
+    assert_eq!(&buffer[..len], b"000");
+    let len = iso_msg.get_iso_field(2, &mut buffer).unwrap();
+    assert_eq!(&buffer[..len], b"5555444433332222");
+
+    assert_eq!(
+        iso_msg.get_iso_field(2, &mut [0u8; 4]),
+        Err(IsoError::BufferTooSmall {
+            needed: 16,
+            available: 4
+        })
+    );
+    assert_eq!(iso_msg.get_iso_field(3, &mut buffer), Err(IsoError::FieldNotSet { index: 3 }));
+    assert_eq!(iso_msg.get_iso_field(1, &mut buffer), Err(IsoError::InvalidField { index: 1 }));
+}
+
+#[test]
+fn replay_a_capture_at_twice_the_speed() {
+    let spec = IsoSpecs::new();
+    let mut payload = vec![];
+    for mti in [b"1644", b"1240", b"1442"] {
+        payload.extend(binary_payload(
+            mti,
+            &[0x70, 0, 0x01, 0, 0, 0, 0, 0],
+            b"165555444433332222000000000000001000200",
+        ));
+    }
+
+    let capture_clock = Context::fixed(UNIX_EPOCH);
+    let mut reader = RecordReader::new(&spec, &payload).with_capture(&capture_clock);
+    let mut records: Vec<Record> = vec![];
+    for gap in [100, 300, 0] {
+        records.push(reader.next().unwrap().unwrap());
+        capture_clock.advance(std::time::Duration::from_millis(gap));
+    }
+    assert!(reader.next().is_none());
+
+    let json = serde_json::to_string(reader.capture().unwrap()).unwrap();
+    let capture: Capture = serde_json::from_str(&json).unwrap();
+    assert_eq!(capture.records.len(), 3);
+    assert_eq!(capture.records[1].offset, 51);
+
+    let replay_clock = Context::fixed(UNIX_EPOCH);
+    for speed in [0.0, -1.0, f64::NAN] {
+        assert_eq!(
+            Replayer::new(&capture, &payload, &replay_clock, speed).err(),
+            Some(IsoError::InvalidReplaySpeed)
+        );
+    }
+    let mut replayer = Replayer::new(&capture, &payload, &replay_clock, 2.0).unwrap();
+    let mut paced = vec![];
+    for record in replayer.by_ref() {
+        let at = replay_clock.now().duration_since(UNIX_EPOCH).unwrap();
+        paced.push((record.unwrap(), at.as_millis()));
+    }
+
+    assert_eq!(paced.len(), 3);
+    assert_eq!(
+        paced.iter().map(|(_, at)| *at).collect::<Vec<u128>>(),
+        vec![0, 50, 200]
+    );
+    for ((replayed, _), captured) in paced.iter().zip(records.iter()) {
+        assert_eq!(replayed, captured);
+    }
+    assert_eq!(&paced[2].0.payload[..4], b"1442");
+}
+
+#[test]
+fn cloned_messages_are_edited_independently() {
+    let spec = IsoSpecs::new();
+    let payload = binary_payload(
+        b"1100",
+        &[0x70, 0, 0x01, 0, 0, 0, 0, 0],
+        b"165555444433332222000000000000001000200",
+    );
+    let request = IsoMsg::new(&spec, &payload);
+
+    let mut reversal = request.clone();
+    reversal.set_mti(Mti::from_str("1420").unwrap()).unwrap();
+    reversal.set_field(24, b"400").unwrap();
+
+    assert_eq!(request.get_field_str(0).unwrap(), "1100");
+    assert_eq!(request.get_field_str(24).unwrap(), "200");
+    assert_eq!(request.to_vec().unwrap(), payload);
+    assert_eq!(reversal.get_field_str(0).unwrap(), "1420");
+    assert_eq!(reversal.get_field_str(24).unwrap(), "400");
+    assert_eq!(reversal.get_field_str(2).unwrap(), "5555444433332222");
+}
+
+#[test]
+fn fixed_width_emissions_round_trip_or_fail() {
+    let spec = IsoSpecs::new();
+    let mut seed = 0x2545_f491_4f6c_dd1du64;
+    for _ in 0..2_000 {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+
+        // ascii length headers
+        let len = (seed % 12_000) as usize;
+        match LengthHeader::Ascii4.encode(len) {
+            Ok(header) => assert_eq!(LengthHeader::Ascii4.decode(&header), Some(len)),
+            Err(error) => assert_eq!(error, IsoError::MessageTooLong { max: 9999, got: len }),
+        }
+
+        // variable length prefixes
+        let value = vec![b'A'; (seed % 1_200) as usize];
+        let mut iso_msg = IsoMsg::empty(&spec);
+        match iso_msg.set_field(48, &value) {
+            Ok(()) => assert_eq!(iso_msg.get_field_slice(48).unwrap(), value.as_slice()),
+            Err(error) => assert!(matches!(error, IsoError::ValueTooLong { index: 48, .. })),
+        }
+
+        // recomputed billing amounts
+        let amount = format!("{:012}", seed % 1_000_000_000_000);
+        let mut iso_msg = IsoMsg::empty(&spec);
+        iso_msg.set_field(4, amount.as_bytes()).unwrap();
+        iso_msg.set_field(10, b"69972522").unwrap();
+        match iso_msg.recompute_billing_amount() {
+            Ok(billing_amount) => assert_eq!(
+                iso_msg.get_field_str(6).unwrap().parse::<u64>().unwrap(),
+                billing_amount
+            ),
+            Err(error) => assert!(matches!(
+                error,
+                IsoError::Conversion(ConversionError::Overflow { .. })
+            )),
+        }
+    }
+}
+
+#[test]
+fn tertiary_bitmap_covers_fields_up_to_192() {
+    let mut specs = vec![
+        IsoField::new(
+            "Message Type Indicator",
+            "mti",
+            FieldCharType::Iso8583_mti,
+            4,
+            FieldSizeType::Fixed,
+        ),
+        IsoField::new(
+            "Bitmaps",
+            "bitmaps",
+            FieldCharType::Iso8583_bmp,
+            24,
+            FieldSizeType::BitMap,
+        ),
+    ];
+    for de in 2..=192 {
+        let label_id = format!("{:03}", de);
+        specs.push(IsoField::new(
+            &format!("Field {}", de),
+            &label_id,
+            FieldCharType::Iso8583_n,
+            3,
+            FieldSizeType::Fixed,
+        ));
+    }
+    let spec = IsoSpecs::from_specs(specs);
+
+    let mut iso_msg = IsoMsg::empty(&spec);
+    iso_msg.set_field(0, b"0100").unwrap();
+    iso_msg.set_field(2, b"002").unwrap();
+    iso_msg.set_field(70, b"070").unwrap();
+    iso_msg.set_field(150, b"150").unwrap();
+    // bit 65 announces the tertiary bitmap
+    assert_eq!(iso_msg.set_field(65, b"065"), Err(IsoError::InvalidField { index: 65 }));
+
+    let payload = iso_msg.to_vec().unwrap();
+    let mut bitmap = [0u8; 24];
+    bitmap[0] = 0xC0;
+    bitmap[8] = 0x84;
+    bitmap[18] = 0x04;
+    assert_eq!(payload, binary_payload(b"0100", &bitmap, b"002070150"));
+
+    let parsed = IsoMsg::new(&spec, &payload);
+    let present: Vec<usize> = parsed.iter().map(|field| field.index()).collect();
+    assert_eq!(present, vec![0, 1, 2, 70, 150]);
+    assert_eq!(parsed.get_field_str(150).unwrap(), "150");
+    assert_eq!(parsed.to_vec().unwrap(), payload);
+
+    // without fields above 128 only the secondary bitmap is written
+    let mut iso_msg = IsoMsg::new(&spec, &payload);
+    iso_msg.remove_field(150).unwrap();
+    assert_eq!(iso_msg.to_vec().unwrap().len(), 4 + 16 + 6);
+    iso_msg.remove_field(70).unwrap();
+    assert_eq!(iso_msg.to_vec().unwrap().len(), 4 + 8 + 3);
+}
+
+#[test]
+fn tertiary_bitmap_is_chained_through_an_empty_secondary_group() {
+    let mut specs = vec![
+        IsoField::new(
+            "Message Type Indicator",
+            "mti",
+            FieldCharType::Iso8583_mti,
+            4,
+            FieldSizeType::Fixed,
+        ),
+        IsoField::new(
+            "Bitmaps",
+            "bitmaps",
+            FieldCharType::Iso8583_bmp,
+            24,
+            FieldSizeType::BitMap,
+        ),
+    ];
+    for de in 2..=150 {
+        let label_id = format!("{:03}", de);
+        specs.push(IsoField::new(
+            &format!("Field {}", de),
+            &label_id,
+            FieldCharType::Iso8583_ans,
+            10,
+            FieldSizeType::LlVar,
+        ));
+    }
+    let spec = IsoSpecs::from_specs(specs);
+
+    let mut iso_msg = IsoMsg::empty(&spec);
+    iso_msg.set_field(0, b"0100").unwrap();
+    iso_msg.set_field(140, b"DE140").unwrap();
+    assert_eq!(iso_msg.present_de_numbers(), vec![1, 65, 140]);
+
+    let payload = iso_msg.to_vec().unwrap();
+    let mut bitmap = [0u8; 24];
+    bitmap[0] = 0x80;
+    bitmap[8] = 0x80;
+    bitmap[17] = 0x10;
+    assert_eq!(payload, binary_payload(b"0100", &bitmap, b"05DE140"));
+
+    let parsed = IsoMsg::new(&spec, &payload);
+    assert_eq!(parsed.get_field_str(140), Ok("DE140".into()));
+    assert_eq!(parsed.to_vec().unwrap(), payload);
+}
+
+#[test]
+fn numeric_fields_read_and_write_as_numbers() {
+    let spec = IsoSpecs::new();
+    let payload = binary_payload(
+        b"1644",
+        &[0x70, 0, 0x01, 0, 0, 0, 0, 0],
+        b"165555444433332222000000000000001000200",
+    );
+    let mut iso_msg = IsoMsg::new(&spec, &payload);
+
+    // DE 3 is all zeros, DE 4 holds 10.00
+    assert_eq!(iso_msg.get_field_num::<u32>(3), Ok(0));
+    assert_eq!(iso_msg.get_field_num::<u64>(4), Ok(1000));
+    assert_eq!(iso_msg.get_field_num::<u64>(2), Ok(5555444433332222));
+    assert_eq!(
+        iso_msg.get_field_num::<u8>(4),
+        Err(IsoError::Numeric(NumericError::Overflow))
+    );
+    assert_eq!(iso_msg.get_field_num::<u64>(11), Err(IsoError::FieldNotSet { index: 11 }));
+
+    iso_msg.set_field_num(4, 2550).unwrap();
+    iso_msg.set_field_num(11, 0).unwrap();
+    assert_eq!(iso_msg.get_field_str(4), Ok("000000002550".into()));
+    assert_eq!(iso_msg.get_field_str(11), Ok("000000".into()));
+    assert_eq!(
+        iso_msg.set_field_num(11, 1_000_000),
+        Err(IsoError::Numeric(NumericError::TooWide {
+            value: 1_000_000,
+            width: 6
+        }))
+    );
+
+    // variable fields get no padding
+    iso_msg.set_field_num(2, 42).unwrap();
+    assert_eq!(iso_msg.get_field_str(2), Ok("42".into()));
+    assert_eq!(iso_msg.get_field_num::<u64>(2), Ok(42));
+
+    // DE 37 is free text, spaces around the digits are dropped but not inside them
+    iso_msg.set_field_str(37, "  0042  ").unwrap();
+    assert_eq!(iso_msg.get_field_num::<u64>(37), Ok(42));
+    iso_msg.set_field_str(37, " 4 2").unwrap();
+    assert_eq!(
+        iso_msg.get_field_num::<u64>(37),
+        Err(IsoError::InvalidCharacter { index: 37, position: 2 })
+    );
+    iso_msg.set_field_str(37, "   ").unwrap();
+    assert_eq!(
+        iso_msg.get_field_num::<u64>(37),
+        Err(IsoError::Numeric(NumericError::Empty))
+    );
+}
+
+#[test]
+fn owned_messages_outlive_their_read_buffer() {
+    let spec = IsoSpecs::new();
+    let mut stream = vec![];
+    let payload = binary_payload(
+        b"1644",
+        &[0x70, 0, 0x01, 0, 0, 0, 0, 0],
+        b"165555444433332222000000000000001000200",
+    );
+    write_framed(&mut stream, LengthHeader::Binary2, &payload).unwrap();
+
+    let iso_msg: IsoMsg<'static, '_> = {
+        let mut reader = std::io::Cursor::new(stream);
+        let buffer = read_framed(&mut reader, LengthHeader::Binary2).unwrap();
+        IsoMsg::from_owned(&spec, buffer)
+    };
+
+    assert_eq!(iso_msg.get_field_str(2), Ok("5555444433332222".into()));
+    assert_eq!(iso_msg.get_field_num::<u64>(4), Ok(1000));
+    assert_eq!(iso_msg.to_vec().unwrap(), payload);
+    assert!(matches!(
+        IsoMsg::try_from_owned(&spec, payload[..20].to_vec()),
+        Err(IsoError::UnexpectedEof { .. })
+    ));
+}
+
+#[test]
+fn trailer_policy_rejects_truncated_files() {
+    let record = |function_code: &[u8], pds: &[u8]| {
+        let mut fields = b"165555444433332222000000000000001000".to_vec();
+        fields.extend_from_slice(function_code);
+        fields.extend_from_slice(format!("{:03}", pds.len()).as_bytes());
+        fields.extend_from_slice(pds);
+        binary_payload(b"1644", &[0x70, 0, 0x01, 0, 0, 0x01, 0, 0], &fields)
+    };
+    let lines = |records: &[Vec<u8>]| {
+        records.iter().fold(vec![], |mut payload, record| {
+            payload.extend_from_slice(record);
+            payload.push(b'\n');
+            payload
+        })
+    };
+    let header = record(b"697", b"01050250032105270000002337901101");
+    let trailer = record(b"695", b"030600800000003");
+    let payload = lines(&[header.clone(), header.clone(), trailer]);
+    let truncated = lines(&[header.clone(), header]);
+
+    let mut options = iso8583::ReaderOptions {
+        framing: Framing::lines(),
+        ..Default::default()
+    };
+    let iso8583_file = iso8583::parse_file_with(truncated.clone(), &options).unwrap();
+    assert!(iso8583_file.summary.reconciliation.is_none());
+
+    options.trailer_policy = TrailerPolicy::Required(iso8583::is_ipm_trailer);
+    let error = iso8583::parse_file_with(truncated.clone(), &options).unwrap_err();
+    assert_eq!(
+        error.downcast_ref::<IsoError>(),
+        Some(&IsoError::MissingTrailer { records: 2 })
+    );
+
+    let iso8583_file = iso8583::parse_file_with(payload.clone(), &options).unwrap();
+    assert_eq!(iso8583_file.summary.trailer, Some(2));
+    let reconciliation = iso8583_file.summary.reconciliation.unwrap();
+    assert_eq!(
+        reconciliation,
+        Reconciliation {
+            announced_messages: Some(3),
+            read_messages: 3
+        }
+    );
+    assert!(reconciliation.is_balanced());
+
+    options.trailer_policy = TrailerPolicy::Forbidden;
+    assert!(iso8583::parse_file_with(truncated, &options).is_ok());
+    let error = iso8583::parse_file_with(payload, &options).unwrap_err();
+    assert_eq!(
+        error.downcast_ref::<IsoError>(),
+        Some(&IsoError::UnexpectedTrailer { record: 2 })
+    );
+
+    // the T121 sample ends with a trailer announcing its 6 messages
+    let options = iso8583::ReaderOptions {
+        trailer_policy: TrailerPolicy::Required(iso8583::is_ipm_trailer),
+        ..Default::default()
+    };
+    let sample = std::fs::read("tests/T121_sample.ipm").unwrap();
+    let iso8583_file = iso8583::parse_file_with(sample, &options).unwrap();
+    assert!(iso8583_file.summary.reconciliation.unwrap().is_balanced());
+}
+
+#[test]
+#[allow(deprecated)]
+fn get_field_reports_typed_errors() {
+    let spec = IsoSpecs::new();
+    let payload = binary_payload(
+        b"1644",
+        &[0x70, 0, 0x01, 0, 0, 0, 0, 0],
+        b"165555444433332222000000000000001000200",
+    );
+    let iso_msg = IsoMsg::new(&spec, &payload);
+
+    let mut buffer = [0u8; 32];
+    assert_eq!(iso_msg.get_field(2, &mut buffer), Ok(16));
+    assert_eq!(&buffer[..16], b"5555444433332222");
+    assert_eq!(iso_msg.get_field(5, &mut buffer), Err(IsoError::FieldNotSet { index: 5 }));
+    assert_eq!(iso_msg.get_field(500, &mut buffer), Err(IsoError::InvalidField { index: 500 }));
+    assert_eq!(
+        iso_msg.get_field(2, &mut buffer[..4]),
+        Err(IsoError::BufferTooSmall {
+            needed: 16,
+            available: 4
+        })
+    );
+    assert_eq!(
+        IsoError::FieldNotSet { index: 5 }.to_string(),
+        "field 5 is not set"
+    );
+}
+
+#[test]
+fn patches_carry_only_the_changed_fields() {
+    let spec = IsoSpecs::new();
+    let payload = binary_payload(
+        b"1100",
+        &[0x70, 0, 0x01, 0, 0, 0, 0, 0],
+        b"165555444433332222000000000000001000200",
+    );
+    let base = IsoMsg::new(&spec, &payload);
+
+    let mut response = base.clone();
+    response.set_mti(Mti::from_str("1110").unwrap()).unwrap();
+    response.set_field_str(39, "000").unwrap();
+    // rewriting a field with its own value is not a change
+    response.set_field_str(3, "000000").unwrap();
+
+    let patch = response.to_patch(&base).unwrap();
+    assert_eq!(
+        patch,
+        binary_payload(b"1110", &[0, 0, 0, 0, 0x02, 0, 0, 0], b"000")
+    );
+
+    let mut receiver = IsoMsg::new(&spec, &payload);
+    receiver.apply_patch(&patch).unwrap();
+    assert_eq!(receiver.get_field_str(39), Ok("000".into()));
+    assert_eq!(receiver.to_vec().unwrap(), response.to_vec().unwrap());
+
+    response.remove_field(24).unwrap();
+    assert_eq!(
+        response.to_patch(&base),
+        Err(IsoError::PatchRemovesField { index: 24 })
+    );
+}
+
+/// Accepts every value, leaving the length checks to `set_field`
+struct PermissiveValidator;
+
+impl FieldValidator for PermissiveValidator {
+    fn validate(&self, _: usize, _: &IsoField, _: &[u8]) -> Result<(), IsoError> {
+        Ok(())
+    }
+}
+
+#[test]
+fn set_field_reports_errors_instead_of_panicking() {
+    let spec = IsoSpecs::new();
+    let payload = binary_payload(
+        b"1644",
+        &[0x70, 0, 0x01, 0, 0, 0, 0, 0],
+        b"165555444433332222000000000000001000200",
+    );
+    let mut iso_msg = IsoMsg::new_with_validator(&spec, &payload, &PermissiveValidator);
+
+    assert_eq!(
+        iso_msg.set_field(2, b"41111111111111111111"),
+        Err(IsoError::ValueTooLong {
+            index: 2,
+            max: 19,
+            got: 20
+        })
+    );
+    assert_eq!(
+        iso_msg.set_field(spec.specs.len(), b"1"),
+        Err(IsoError::InvalidField {
+            index: spec.specs.len()
+        })
+    );
+
+    // binary values go through the trace logging without being valid utf-8
+    iso_msg.set_field(52, &[0xFF, 0xFE, 0x00, 0x80, 0xC3, 0x28, 0xA0, 0xA1]).unwrap();
+    assert_eq!(
+        iso_msg.get_field_slice(52),
+        Ok(&[0xFF, 0xFE, 0x00, 0x80, 0xC3, 0x28, 0xA0, 0xA1][..])
+    );
+}
+
+#[test]
+fn function_and_reason_codes_decode_into_enums() {
+    let spec = IsoSpecs::new();
+    // DE 24 is 400 and DE 25 is 4021
+    let payload = binary_payload(
+        b"1420",
+        &[0x70, 0, 0x01, 0x80, 0, 0, 0, 0],
+        b"1655554444333322220000000000000010004004021",
+    );
+    let mut iso_msg = IsoMsg::new(&spec, &payload);
+
+    assert_eq!(iso_msg.function_code(), Ok(FunctionCode::FullReversal));
+    assert_eq!(
+        iso_msg.message_reason_code(),
+        Ok(MessageReasonCode::TimeoutWaitingForResponse)
+    );
+
+    iso_msg.set_field_str(24, "697").unwrap();
+    iso_msg.set_field_num(25, 1234).unwrap();
+    assert_eq!(iso_msg.function_code(), Ok(FunctionCode::FileHeader));
+    assert_eq!(iso_msg.message_reason_code(), Ok(MessageReasonCode::Other(1234)));
+
+    iso_msg.remove_field(25).unwrap();
+    assert_eq!(
+        iso_msg.message_reason_code(),
+        Err(IsoError::FieldNotSet { index: 25 })
+    );
+}
+
+#[test]
+fn track2_grammar_is_checked_by_validation() {
+    let spec = IsoSpecs::new();
+    let payload = binary_payload(
+        b"1100",
+        &[0x70, 0, 0x01, 0, 0, 0, 0, 0],
+        b"165555444433332222000000000000001000200",
+    );
+    let mut iso_msg = IsoMsg::new(&spec, &payload);
+
+    iso_msg.set_field_str(35, "5555444433332222D2512101123").unwrap();
+    assert_eq!(iso_msg.validate(), Ok(()));
+
+    // the separator is missing after the 16 digits PAN
+    iso_msg.set_field_str(35, "55554444333322222512101123").unwrap();
+    let violation = IsoError::Track2 {
+        index: 35,
+        error: Track2Error::new(26, Track2Violation::MissingSeparator),
+    };
+    assert_eq!(iso_msg.validate(), Err(vec![violation.clone()]));
+    assert_eq!(
+        iso_msg.validate_with(ValidationMode::Lenient),
+        ValidationReport {
+            errors: vec![],
+            warnings: vec![violation],
+        }
+    );
+}
+
+#[test]
+fn display_renders_a_field_table() {
+    let spec = IsoSpecs::new();
+    let mut fields = b"165555444433332222000000000000001000200".to_vec();
+    fields.extend_from_slice(&[0x01, 0x23, 0x45, 0x67, 0x89, 0xAB, 0xCD, 0xEF]);
+    let payload = binary_payload(b"1644", &[0x70, 0, 0x01, 0, 0, 0, 0x10, 0], &fields);
+    let iso_msg = IsoMsg::new(&spec, &payload);
+
+    let table = format!("{:12}", iso_msg);
+    let lines: Vec<&str> = table.lines().collect();
+    assert_eq!(
+        lines,
+        vec![
+            "| Field   | Label                         | Value        |",
+            "|---------|-------------------------------|--------------|",
+            "| mti     | Message Type Indicator        | 1644         |",
+            "| bitmaps | Bitmaps                       | 70000100000… |",
+            "| 002     | Primary Account Number        | 55554444333… |",
+            "| 003     | Processing Code               | 000000       |",
+            "| 004     | Amount, Txn                   | 000000001000 |",
+            "| 024     | Function Code                 | 200          |",
+            "| 052     | Personal Id Number (PIN) Data | 0123456789A… |",
+        ]
+    );
+    // the default value column holds 32 characters
+    let pan_row = format!("{}", iso_msg).lines().nth(4).unwrap().to_string();
+    let expected = format!(
+        "| 002     | Primary Account Number        | {:32} |",
+        "5555444433332222"
+    );
+    assert_eq!(pan_row, expected);
+}
+
+#[test]
+fn alternate_display_writes_one_line_per_field() {
+    let spec = IsoSpecs::new();
+    let mut fields = b"165555444433332222000000000000001000200".to_vec();
+    fields.extend_from_slice(&[0x01, 0x23, 0x45, 0x67, 0x89, 0xAB, 0xCD, 0xEF]);
+    let payload = binary_payload(b"1644", &[0x70, 0, 0x01, 0, 0, 0, 0x10, 0], &fields);
+    let iso_msg = IsoMsg::new(&spec, &payload);
+
+    let dump = format!("{:#}", iso_msg);
+    assert_eq!(
+        dump.lines().collect::<Vec<_>>(),
+        vec![
+            "DE  0 (Message Type Indicator)  [4]: 1644",
+            "DE  1 (Bitmaps)  [8]: 7000010000001000",
+            "DE  2 (Primary Account Number)  [16]: 5555444433332222",
+            "DE  3 (Processing Code)  [6]: 000000",
+            "DE  4 (Amount, Txn)  [12]: 000000001000",
+            "DE 24 (Function Code)  [3]: 200",
+            "DE 52 (Personal Id Number (PIN) Data)  [8]: 0123456789ABCDEF",
+        ]
+    );
+    assert_eq!(format!("{:?}", iso_msg), dump);
+}
+
+#[test]
+fn set_field_checked_enforces_the_char_type() {
+    let spec = IsoSpecs::new();
+    let payload = binary_payload(
+        b"1644",
+        &[0x70, 0, 0x01, 0, 0, 0, 0, 0],
+        b"165555444433332222000000000000001000200",
+    );
+    let mut iso_msg = IsoMsg::new(&spec, &payload);
+    let invalid = |index, position| Err(IsoError::InvalidCharacter { index, position });
+
+    // n
+    assert_eq!(iso_msg.set_field_checked(4, b"00000000100A"), invalid(4, 11));
+    assert_eq!(iso_msg.set_field_checked(4, b"000000001000"), Ok(()));
+    // ns
+    assert_eq!(iso_msg.set_field_checked(2, b"4111 1111"), invalid(2, 4));
+    assert_eq!(iso_msg.set_field_checked(2, b"4111111111111111"), Ok(()));
+    // xn, the C and D credit and debit indicators are allowed
+    assert_eq!(iso_msg.set_field_checked(97, b"E0000000000001000"), invalid(97, 0));
+    assert_eq!(iso_msg.set_field_checked(97, b"D0000000000001000"), Ok(()));
+    // an
+    assert_eq!(iso_msg.set_field_checked(24, b"20-"), invalid(24, 2));
+    assert_eq!(iso_msg.set_field_checked(24, b"2A0"), Ok(()));
+    // ans
+    assert_eq!(iso_msg.set_field_checked(37, b"REF 0001\n"), invalid(37, 8));
+    assert_eq!(iso_msg.set_field_checked(37, b"REF-0001 #1"), Ok(()));
+    // b and z take any byte
+    assert_eq!(iso_msg.set_field_checked(52, &[0x00, 0xFF, 0x7F]), Ok(()));
+    assert_eq!(iso_msg.set_field_checked(35, b"5555444433332222=2512"), Ok(()));
+
+    assert_eq!(iso_msg.get_field_str(24), Ok("2A0".into()));
+    assert_eq!(
+        iso_msg.set_field_checked(spec.specs.len(), b"1"),
+        Err(IsoError::InvalidField {
+            index: spec.specs.len()
+        })
+    );
+    // set_field is left to the message validator, which accepts anything by default
+    assert_eq!(iso_msg.set_field(4, b"00000000100A"), Ok(()));
+}
+
+#[test]
+fn to_byte_array_checks_the_buffer_size() {
+    let spec = IsoSpecs::new();
+    let payload = binary_payload(
+        b"1644",
+        &[0x70, 0, 0x01, 0, 0, 0, 0, 0],
+        b"165555444433332222000000000000001000200",
+    );
+    let mut iso_msg = IsoMsg::new(&spec, &payload);
+    // DE 48 at its 999 bytes maximum, behind its LLLVAR prefix
+    iso_msg.set_field(48, &[b'A'; 999]).unwrap();
+    let needed = payload.len() + 3 + 999;
+    assert_eq!(iso_msg.serialized_len(), needed);
+
+    let mut buffer = vec![0u8; needed];
+    assert_eq!(iso_msg.to_byte_array(&mut buffer), Ok(needed));
+    assert_eq!(buffer, iso_msg.to_vec().unwrap());
+
+    let mut buffer = vec![0u8; 512];
+    assert_eq!(
+        iso_msg.to_byte_array(&mut buffer),
+        Err(IsoError::BufferTooSmall {
+            needed,
+            available: 512
+        })
+    );
+    let mut buffer = vec![0u8; needed - 1];
+    assert!(matches!(
+        iso_msg.to_byte_array(&mut buffer),
+        Err(IsoError::BufferTooSmall { .. })
+    ));
+    assert!(buffer.iter().all(|&byte| byte == 0));
+
+    // too short for the mti, or ending within the bitmap
+    for available in [0, 2, 4, 10] {
+        let mut tiny = vec![0u8; available];
+        assert_eq!(
+            iso_msg.to_byte_array(&mut tiny),
+            Err(IsoError::BufferTooSmall { needed, available })
+        );
+    }
+}
+
+#[test]
+fn validate_mandatory_fields_lists_the_absent_ones() {
+    let mut spec = IsoSpecs::new();
+    spec.specs[2] = IsoField::new_required(
+        "Primary Account Number",
+        "002",
+        FieldCharType::Iso8583_ns,
+        19,
+        FieldSizeType::LlVar,
+    );
+    spec.specs[11].required = true;
+    spec.specs[39].required = true;
+    // the bitmap is rebuilt on serialization, marking it changes nothing
+    spec.specs[1].required = true;
+
+    let payload = binary_payload(
+        b"1644",
+        &[0x70, 0, 0x01, 0, 0, 0, 0, 0],
+        b"165555444433332222000000000000001000200",
+    );
+    let mut iso_msg = IsoMsg::new(&spec, &payload);
+    assert_eq!(iso_msg.validate_mandatory_fields(), Ok(vec![11, 39]));
+
+    iso_msg.set_field(11, b"000001").unwrap();
+    iso_msg.set_field(39, b"000").unwrap();
+    assert_eq!(iso_msg.validate_mandatory_fields(), Ok(vec![]));
+
+    iso_msg.remove_field(2).unwrap();
+    assert_eq!(iso_msg.validate_mandatory_fields(), Ok(vec![2]));
+    assert_eq!(
+        IsoMsg::new(&IsoSpecs::new(), &payload).validate_mandatory_fields(),
+        Ok(vec![])
+    );
+}
+
+#[test]
+fn to_vec_round_trips_the_present_fields() {
+    let spec = IsoSpecs::new();
+    let mut iso_msg = IsoMsg::empty(&spec);
+    iso_msg.set_field(0, b"0100").unwrap();
+    iso_msg.set_field(2, b"4111111111111111").unwrap();
+    iso_msg.set_field(4, b"000000001000").unwrap();
+    iso_msg.set_field(11, b"000042").unwrap();
+    iso_msg.set_field(48, b"R1").unwrap();
+    iso_msg.set_field(52, &[0xDE, 0xAD, 0xBE, 0xEF, 0, 1, 2, 3]).unwrap();
+
+    let serialized = iso_msg.to_vec().unwrap();
+    assert_eq!(serialized.len(), iso_msg.serialized_len());
+    assert_eq!(serialized.capacity(), iso_msg.serialized_len());
+    let mut buffer = [0u8; 256];
+    assert_eq!(iso_msg.to_byte_array(&mut buffer), Ok(serialized.len()));
+    let mut short_buffer = vec![0u8; iso_msg.serialized_len() - 1];
+    assert_eq!(
+        iso_msg.to_byte_array(&mut short_buffer),
+        Err(IsoError::BufferTooSmall {
+            needed: serialized.len(),
+            available: serialized.len() - 1
+        })
+    );
+
+    let reparsed = IsoMsg::new(&spec, &serialized);
+    let fields = |iso_msg: &IsoMsg| -> Vec<(usize, Vec<u8>)> {
+        iso_msg
+            .iter()
+            .map(|field| (field.index(), field.value().to_vec()))
+            .collect()
+    };
+    // the bitmap is absent from the built message and rebuilt on the parsed one
+    let mut expected = fields(&iso_msg);
+    expected.insert(1, (1, serialized[4..12].to_vec()));
+    assert_eq!(fields(&reparsed), expected);
+}
+
+#[test]
+fn frame_records_for_pads_to_the_fixed_record_length() {
+    let mut spec = IsoSpecs::new();
+    let payload = binary_payload(
+        b"1644",
+        &[0x70, 0, 0x01, 0, 0, 0, 0, 0],
+        b"165555444433332222000000000000001000200",
+    );
+    let record = IsoMsg::new(&spec, &payload).to_vec().unwrap();
+    let records = vec![record.clone()];
+
+    // without a record length the records keep their own length
+    assert_eq!(spec.fixed_record_len(), None);
+    assert_eq!(
+        frame_records_for(&spec, &records, &Framing::Rdw),
+        Ok(frame_records(&records, &Framing::Rdw))
+    );
+
+    spec.record_len = Some(64);
+    let framed = frame_records_for(&spec, &records, &Framing::lines()).unwrap();
+    assert_eq!(framed.len(), 64 + 1);
+    assert_eq!(&framed[..record.len()], record.as_slice());
+    assert!(framed[record.len()..64].iter().all(|&byte| byte == b' '));
+
+    let framed = frame_records_for(&spec, &records, &Framing::Rdw).unwrap();
+    assert_eq!(&framed[..4], &64u32.to_be_bytes());
+
+    spec.record_len = Some(50);
+    assert_eq!(
+        frame_records_for(&spec, &[b"1644".to_vec(), record], &Framing::Rdw),
+        Err(IsoError::RecordTooLong {
+            record: 1,
+            max: 50,
+            got: 51
+        })
+    );
+}
+
+#[test]
+fn transaction_commits_linked_fields_together() {
+    let spec = IsoSpecs::new();
+    let payload = binary_payload(
+        b"1644",
+        &[0x70, 0, 0x01, 0, 0, 0, 0, 0],
+        b"165555444433332222000000000000001000200",
+    );
+    let mut iso_msg = IsoMsg::new(&spec, &payload);
+    iso_msg.set_field(35, b"5555444433332222=2512101").unwrap();
+    let before = iso_msg.to_vec().unwrap();
+
+    // the track no longer carries the new PAN
+    let mut transaction = iso_msg.transaction();
+    transaction.set_field(2, b"4111111111111111");
+    transaction.set_field(35, b"5555444433332222=2612101");
+    assert_eq!(
+        transaction.commit(),
+        Err(IsoError::LinkedFieldMismatch {
+            index: 35,
+            linked: 2
+        })
+    );
+    assert_eq!(iso_msg.to_vec().unwrap(), before);
+
+    // the second change fails on its own, the first one is rolled back
+    let mut transaction = iso_msg.transaction();
+    transaction.set_field(2, b"4111111111111111");
+    transaction.set_field(35, &[b'4'; 38]);
+    assert!(matches!(
+        transaction.commit(),
+        Err(IsoError::ValueTooLong { index: 35, .. })
+    ));
+    assert_eq!(iso_msg.to_vec().unwrap(), before);
+
+    let mut transaction = iso_msg.transaction();
+    transaction.set_field(2, b"4111111111111111");
+    transaction.rollback();
+    assert_eq!(iso_msg.to_vec().unwrap(), before);
+
+    let mut transaction = iso_msg.transaction();
+    transaction.set_field(2, b"4111111111111111");
+    transaction.set_field(35, b"4111111111111111=2612101");
+    transaction.set_field_num(27, 6);
+    transaction.set_field_str(38, "A1B2C3");
+    assert_eq!(transaction.commit(), Ok(()));
+    assert_eq!(iso_msg.get_field_str(2), Ok("4111111111111111".into()));
+    assert_eq!(iso_msg.get_field_slice(35), Ok(&b"4111111111111111=2612101"[..]));
+    assert_eq!(iso_msg.validate_linked_fields(), Ok(()));
+
+    let mut transaction = iso_msg.transaction();
+    transaction.set_field_str(38, "A1B2  ");
+    assert_eq!(
+        transaction.commit(),
+        Err(IsoError::LinkedFieldMismatch {
+            index: 38,
+            linked: 27
+        })
+    );
+    assert_eq!(iso_msg.get_field_str(38), Ok("A1B2C3".into()));
+}
+
+#[test]
+fn transaction_checks_additional_amounts_against_de_4() {
+    let spec = IsoSpecs::new();
+    let payload = binary_payload(
+        b"1644",
+        &[0x70, 0, 0x01, 0, 0, 0, 0, 0],
+        b"165555444433332222000000000000001000200",
+    );
+    let mut iso_msg = IsoMsg::new(&spec, &payload);
+    // an available balance, which is not checked, then the original amount
+    iso_msg
+        .set_field(54, b"0002840C0000000050000057840D000000001000")
+        .unwrap();
+    assert_eq!(iso_msg.validate_linked_fields(), Ok(()));
+    let before = iso_msg.to_vec().unwrap();
+
+    // the original amount still tells 10.00
+    let mut transaction = iso_msg.transaction();
+    transaction.set_field(4, b"000000002000");
+    transaction.set_field(54, b"0002840C0000000050000057840D000000001000");
+    assert_eq!(
+        transaction.commit(),
+        Err(IsoError::LinkedFieldMismatch {
+            index: 54,
+            linked: 4
+        })
+    );
+    assert_eq!(iso_msg.to_vec().unwrap(), before);
+
+    let mut transaction = iso_msg.transaction();
+    transaction.set_field(4, b"000000002000");
+    transaction.set_field(54, b"0057840D00000000X000");
+    assert_eq!(
+        transaction.commit(),
+        Err(IsoError::InvalidCharacter {
+            index: 54,
+            position: 16
+        })
+    );
+    assert_eq!(iso_msg.to_vec().unwrap(), before);
+
+    let mut transaction = iso_msg.transaction();
+    transaction.set_field(4, b"000000002000");
+    transaction.set_field(54, b"0057840D000000002000");
+    assert_eq!(transaction.commit(), Ok(()));
+    assert_eq!(iso_msg.get_field_str(4), Ok("000000002000".into()));
+
+    let mut transaction = iso_msg.transaction();
+    transaction.set_field(54, b"0057840D00000000200");
+    assert_eq!(
+        transaction.commit(),
+        Err(IsoError::InvalidLength {
+            index: 54,
+            expected: 20,
+            got: 19
+        })
+    );
+    assert_eq!(iso_msg.get_field_slice(54), Ok(&b"0057840D000000002000"[..]));
+}
+
+#[test]
+fn diff_lists_the_fields_differing_between_messages() {
+    let spec = IsoSpecs::new();
+    let mut request = IsoMsg::empty(&spec);
+    request.set_field(0, b"0100").unwrap();
+    request.set_field(2, b"5555444433332222").unwrap();
+    request.set_field(11, b"000042").unwrap();
+    request.set_field(52, &[0x01, 0x23, 0x45, 0x67, 0x89, 0xAB, 0xCD, 0xEF]).unwrap();
+    let mut response = request.clone();
+    response.set_field(0, b"0110").unwrap();
+    response.remove_field(52).unwrap();
+    response.set_field(39, b"000").unwrap();
+
+    let diffs = request.diff(&response).unwrap();
+    let indexes: Vec<usize> = diffs.iter().map(FieldDiff::index).collect();
+    assert_eq!(indexes, vec![0, 39, 52]);
+    assert_eq!(diffs[1].label(), "Action Code");
+    assert_eq!(
+        diffs[0],
+        FieldDiff::Changed {
+            index: 0,
+            iso_field: &spec.specs[0],
+            value: Cow::Borrowed(b"0100"),
+            other_value: Cow::Borrowed(b"0110"),
+        }
+    );
+
+    let lines: Vec<String> = diffs.iter().map(|diff| diff.to_string()).collect();
+    assert_eq!(
+        lines,
+        vec![
+            "~ mti Message Type Indicator: 0100 => 0110",
+            "+ 039 Action Code: 000",
+            "- 052 Personal Id Number (PIN) Data: 0123456789ABCDEF",
+        ]
+    );
+    assert_eq!(request.diff(&request.clone()).unwrap(), vec![]);
+
+    let short_spec = IsoSpecs::from_specs(spec.specs[..5].to_vec());
+    assert_eq!(
+        request.diff(&IsoMsg::empty(&short_spec)),
+        Err(IsoError::SpecMismatch {
+            fields: spec.specs.len(),
+            other_fields: 5
+        })
+    );
+}
+
+#[test]
+fn removing_the_last_secondary_field_writes_a_primary_hex_bitmap() {
+    let mut spec = IsoSpecs::new();
+    spec.bitmap_encoding = BitmapEncoding::Hex;
+    spec.specs[1].length = 32;
+    let fields = b"165555444433332222000000000000001000200";
+
+    // DE 2, 3, 4, 24 and 126
+    let mut payload = b"1100F0000100000000000000000000000004".to_vec();
+    payload.extend_from_slice(fields);
+    payload.extend_from_slice(b"003ABC");
+    let mut iso_msg = IsoMsg::new(&spec, &payload);
+    assert_eq!(iso_msg.get_field_str(126), Ok("ABC".into()));
+    assert_eq!(iso_msg.to_vec().unwrap(), payload);
+
+    iso_msg.remove_field(126).unwrap();
+    let serialized = iso_msg.to_vec().unwrap();
+    // the continuation bit is cleared and only the 16 hex chars of the primary are written
+    assert_eq!(&serialized[4..20], b"7000010000000000");
+    let mut expected = b"11007000010000000000".to_vec();
+    expected.extend_from_slice(fields);
+    assert_eq!(serialized, expected);
+}
+
+/// A 2100 authorization request carrying the fields changed by the 2003 revision
+fn iso2003_authorization() -> Vec<u8> {
+    // DE 2, 3, 4, 12, 22, 24 and 28
+    binary_payload(
+        b"2100",
+        &[0x70, 0x10, 0x05, 0x10, 0, 0, 0, 0],
+        b"16555544443333222200000000000000100020261017103000015POSDATA2003 XYZ10020261017",
+    )
+}
+
+#[test]
+fn parse_an_iso2003_authorization() {
+    let spec = iso_specs::iso2003();
+    let payload = iso2003_authorization();
+    let iso_msg = IsoMsg::new(&spec, &payload);
+
+    assert_eq!(iso_msg.get_field_str(12), Ok("20261017103000".into()));
+    assert_eq!(iso_msg.get_field_str(22), Ok("POSDATA2003 XYZ".into()));
+    assert_eq!(iso_msg.get_field_str(24), Ok("100".into()));
+    assert_eq!(iso_msg.get_field_str(28), Ok("20261017".into()));
+    assert_eq!(iso_msg.mti().unwrap().version(), MtiVersion::Iso2003);
+    assert_eq!(iso_msg.to_vec().unwrap(), payload);
+
+    // the 1993 layout reads the same bytes at the wrong offsets
+    let misread = IsoMsg::try_new(IsoSpecs::builtin(), &payload);
+    assert!(misread.map_or(true, |iso_msg| iso_msg.get_field_str(12) != Ok("20261017103000".into())));
+
+    let registry = SpecRegistry::standard();
+    let parsed = registry.parse(&payload).unwrap();
+    assert_eq!(parsed.get_field_str(22), Ok("POSDATA2003 XYZ".into()));
+    assert!(std::ptr::eq(
+        registry.spec_for(b"1644").unwrap(),
+        registry.get(MtiVersion::Iso1993).unwrap()
+    ));
+    assert_eq!(
+        registry.spec_for(b"0100").err(),
+        Some(IsoError::UnknownMtiVersion { version: 0 })
+    );
+}
+
+#[test]
+fn registry_parses_files_mixing_versions() {
+    let iso1993 = binary_payload(
+        b"1644",
+        &[0x70, 0, 0x01, 0, 0, 0, 0, 0],
+        b"165555444433332222000000000000001000200",
+    );
+    let records = vec![iso1993, iso2003_authorization()];
+    // IPM files are padded by their blocking
+    let mut payload = frame_records(&records, &Framing::Rdw);
+    payload.extend_from_slice(b"@@");
+
+    let options = iso8583::ReaderOptions {
+        registry: Some(SpecRegistry::standard()),
+        ..Default::default()
+    };
+    let iso8583_file = iso8583::parse_file_with(payload.clone(), &options).unwrap();
+    let mtis: Vec<&str> = iso8583_file.messages.iter().map(|m| m.mti.as_str()).collect();
+    assert_eq!(mtis, vec!["1644", "2100"]);
+    let local_time = &iso8583_file.messages[1].data_elements["012"];
+    assert_eq!(local_time.get_string(), "20261017103000");
+
+    // a single 1993 spec can't read the 2003 record
+    assert!(iso8583::parse_file(payload.clone()).is_err());
+
+    let mut unknown = SpecRegistry::new();
+    unknown.register(MtiVersion::Iso1993, IsoSpecs::new());
+    let options = iso8583::ReaderOptions {
+        registry: Some(unknown),
+        ..Default::default()
+    };
+    let error = iso8583::parse_file_with(payload, &options);
+    assert!(error
+        .unwrap_err()
+        .to_string()
+        .contains("record 1: no spec is registered for mti version 2"));
+}
+
+#[test]
+fn set_field_pads_fixed_values_with_the_spec_padding() {
+    let mut spec = IsoSpecs::new();
+    spec.specs[3] = spec.specs[3].clone().with_padding(FieldPadding::LeadingZeros);
+    spec.specs[41].padding = FieldPadding::TrailingSpaces;
+    let mut iso_msg = IsoMsg::empty(&spec);
+
+    iso_msg.set_field(3, b"123").unwrap();
+    assert_eq!(iso_msg.get_field_str(3), Ok("000123".into()));
+    assert_eq!(iso_msg.get_field_str_trimmed(3), Ok("123".into()));
+    iso_msg.set_field(41, b"T1").unwrap();
+    assert_eq!(iso_msg.get_field_str(41), Ok("T1      ".into()));
+    assert_eq!(iso_msg.get_field_str_trimmed(41), Ok("T1".into()));
+    // values filling the field are kept as is
+    iso_msg.set_field(3, b"654321").unwrap();
+    assert_eq!(iso_msg.get_field_str(3), Ok("654321".into()));
+
+    // without a spec padding the value is written verbatim
+    iso_msg.set_field(11, b"42").unwrap();
+    assert_eq!(iso_msg.get_field_str(11), Ok("42".into()));
+
+    assert_eq!(FieldPadding::from_str("Leading_Zeros"), Some(FieldPadding::LeadingZeros));
+    assert_eq!(FieldPadding::from_str("left"), None);
+}
+
+#[test]
+fn messages_compare_by_their_present_field_values() {
+    let spec = IsoSpecs::new();
+    let payload = binary_payload(
+        b"1644",
+        &[0x70, 0, 0x01, 0, 0, 0, 0, 0],
+        b"165555444433332222000000000000001000200",
+    );
+    let parsed = IsoMsg::new(&spec, &payload);
+    assert!(parsed == IsoMsg::new(&spec, &payload.clone()));
+
+    let mut rebuilt = IsoMsg::empty(&spec);
+    rebuilt.set_field(0, b"1644").unwrap();
+    rebuilt.set_field(24, b"200").unwrap();
+    rebuilt.set_field(4, b"000000001000").unwrap();
+    rebuilt.set_field(3, b"000000").unwrap();
+    rebuilt.set_field(2, b"5555444433332222").unwrap();
+    assert!(rebuilt == parsed);
+
+    // the encoding of the bitmap is not part of the value
+    let mut hex_bitmap = parsed.clone();
+    hex_bitmap.set_bitmap_encoding(BitmapEncoding::Hex);
+    assert_ne!(hex_bitmap.to_vec().unwrap(), payload);
+    assert!(hex_bitmap == parsed);
+
+    rebuilt.set_field(24, b"201").unwrap();
+    assert!(rebuilt != parsed);
+    rebuilt.set_field(24, b"200").unwrap();
+    rebuilt.set_field(11, b"000001").unwrap();
+    assert!(rebuilt != parsed);
+
+    let short_spec = IsoSpecs::from_specs(spec.specs[..5].to_vec());
+    assert!(IsoMsg::empty(&short_spec) != IsoMsg::empty(&spec));
+}
+
+#[test]
+fn composite_flushes_sub_elements_once() {
+    let spec = IsoSpecs::new();
+    let payload = binary_payload(
+        b"1644",
+        &[0x70, 0, 0x01, 0, 0, 0, 0, 0],
+        b"165555444433332222000000000000001000200",
+    );
+    let mut iso_msg = IsoMsg::new(&spec, &payload);
+
+    // an absent field gives an empty handle
+    let mut handle = iso_msg.composite(48).unwrap();
+    assert!(handle.is_empty());
+    handle.set("0023", "USA").unwrap();
+    handle.set("0002", "MCG").unwrap();
+    assert_eq!(handle.flush(&mut iso_msg), Ok(()));
+    assert_eq!(iso_msg.get_field_str(48), Ok("0002003MCG0023003USA".into()));
+
+    let mut handle = iso_msg.composite(48).unwrap();
+    assert_eq!(handle.get("0023"), Some("USA"));
+    assert_eq!(handle.tags().collect::<Vec<_>>(), vec!["0002", "0023"]);
+    handle.set("0003", "MCG").unwrap();
+    handle.remove("0023");
+    assert!(handle.is_dirty());
+    assert_eq!(handle.flush(&mut iso_msg), Ok(()));
+    assert!(!handle.is_dirty());
+    assert_eq!(iso_msg.get_field_str(48), Ok("0002003MCG0003003MCG".into()));
+
+    // the handle keeps working after a flush, until the field is set by other means
+    handle.set("0158", "IV").unwrap();
+    iso_msg.set_field(48, b"0002003MCS").unwrap();
+    assert_eq!(
+        handle.flush(&mut iso_msg),
+        Err(IsoError::StaleComposite { index: 48 })
+    );
+    assert_eq!(iso_msg.get_field_str(48), Ok("0002003MCS".into()));
+
+    let mut handle = iso_msg.composite(48).unwrap();
+    handle.remove("0002");
+    assert_eq!(handle.flush(&mut iso_msg), Ok(()));
+    assert_eq!(iso_msg.get_field_str(48), Err(IsoError::FieldNotSet { index: 48 }));
+}
+
+#[test]
+fn preset_1987_round_trip() {
+    let spec = Iso8583_1987Spec::spec();
+    // shared, not rebuilt on each call
+    assert!(std::ptr::eq(spec, Iso8583_1987Spec::spec()));
+    let payload = binary_payload(
+        b"0200",
+        &[0x30, 0x30, 0x04, 0x80, 0, 0x80, 0, 0],
+        b"00000000000000100012345614301505100TERM0001",
+    );
+
+    let iso_msg = IsoMsg::try_new(spec, &payload).unwrap();
+    assert_eq!(iso_msg.get_field_str(12), Ok("143015".into()));
+    assert_eq!(iso_msg.get_field_str(22), Ok("051".into()));
+    assert_eq!(iso_msg.get_field_str(25), Ok("00".into()));
+    assert_eq!(iso_msg.get_field_str(41), Ok("TERM0001".into()));
+    assert_eq!(iso_msg.to_vec().unwrap(), payload);
+    assert_eq!(Iso8583_1987Spec::VERSION, MtiVersion::Iso1987);
+}
+
+#[test]
+fn preset_1993_round_trip() {
+    let spec = Iso8583_1993Spec::spec();
+    // shared, not rebuilt on each call
+    assert!(std::ptr::eq(spec, Iso8583_1993Spec::spec()));
+    let payload = binary_payload(
+        b"1100",
+        &[0x30, 0x30, 0x05, 0, 0, 0x20, 0, 0],
+        b"00000000000000100012345626101714301551010151300110020ACME STORE LONDON GB",
+    );
+
+    let iso_msg = IsoMsg::try_new(spec, &payload).unwrap();
+    assert_eq!(iso_msg.get_field_str(12), Ok("261017143015".into()));
+    assert_eq!(iso_msg.get_field_str(22), Ok("510101513001".into()));
+    assert_eq!(iso_msg.get_field_str(24), Ok("100".into()));
+    assert_eq!(iso_msg.get_field_str(43), Ok("ACME STORE LONDON GB".into()));
+    assert_eq!(iso_msg.to_vec().unwrap(), payload);
+}
+
+#[test]
+fn preset_2003_round_trip() {
+    let spec = Iso8583_2003Spec::spec();
+    // shared, not rebuilt on each call
+    assert!(std::ptr::eq(spec, Iso8583_2003Spec::spec()));
+    let payload = binary_payload(
+        b"2100",
+        &[0x30, 0x30, 0x04, 0, 0, 0x20, 0, 0],
+        b"00000000000000100012345620261017143015015POSDATA2003 XYZ\
+          4510ACME STORE07MAIN ST06LONDON07EC1A1BB0003GBR",
+    );
+
+    let mut iso_msg = IsoMsg::try_new(spec, &payload).unwrap();
+    assert_eq!(iso_msg.get_field_str(12), Ok("20261017143015".into()));
+    assert_eq!(iso_msg.get_field_str(22), Ok("POSDATA2003 XYZ".into()));
+    let location = match iso_msg.value(43).unwrap() {
+        FieldValue::SubFields(sub_fields) => sub_fields,
+        value => panic!("unexpected value {:?}", value),
+    };
+    assert_eq!(location["name"], "ACME STORE");
+    assert_eq!(location["postal_code"], "EC1A1BB");
+    assert_eq!(location["region"], "");
+    assert_eq!(iso_msg.to_vec().unwrap(), payload);
+
+    let mut location = location;
+    location.insert("region".to_string(), "LDN".to_string());
+    iso_msg
+        .set_value(43, &FieldValue::SubFields(location))
+        .unwrap();
+    assert_eq!(
+        iso_msg.get_field_str(43),
+        Ok("10ACME STORE07MAIN ST06LONDON07EC1A1BB03LDN03GBR".into())
+    );
+
+    location = std::collections::BTreeMap::from([("name".to_string(), "ACME".to_string())]);
+    assert!(matches!(
+        iso_msg.set_value(43, &FieldValue::SubFields(location)),
+        Err(IsoError::Codec {
+            index: 43,
+            error: CodecError::MissingSubField { .. }
+        })
+    ));
+}
+
+#[test]
+fn binary_bitmap_records_serialize_back_byte_identical() {
+    let sample = std::fs::read("tests/T121_sample.ipm").unwrap();
+    let payload = iso8583::file_utils::deblock_and_remove_rdw_from(sample).unwrap();
+    let spec = IsoSpecs::builtin();
+    assert_eq!(spec.bitmap_encoding, BitmapEncoding::Binary);
+
+    let mut position = 0;
+    let mut records = 0;
+    while payload.len() > position + 2 {
+        let record = &payload[position..];
+        let iso_msg = IsoMsg::try_new(spec, record).unwrap();
+        let length = iso_msg.length();
+        assert_eq!(iso_msg.bitmap_encoding(), BitmapEncoding::Binary);
+
+        let mut buffer = vec![0; iso_msg.serialized_len()];
+        assert_eq!(iso_msg.to_byte_array(&mut buffer), Ok(length));
+        assert_eq!(buffer, record[..length].to_vec(), "record {}", records);
+
+        position += length;
+        records += 1;
+    }
+    assert!(records > 1);
+}
+
+#[test]
+fn present_de_numbers_follow_the_bitmap() {
+    let spec = IsoSpecs::new();
+    let payload = binary_payload(
+        b"1644",
+        &[0x70, 0, 0x01, 0, 0, 0, 0, 0],
+        b"165555444433332222000000000000001000200",
+    );
+    let mut iso_msg = IsoMsg::new(&spec, &payload);
+    assert_eq!(iso_msg.present_de_numbers(), vec![2, 3, 4, 24]);
+
+    // a field of the secondary bitmap sets DE 1
+    iso_msg.set_field(100, b"12345").unwrap();
+    iso_msg.remove_field(3).unwrap();
+    assert_eq!(iso_msg.present_de_numbers(), vec![1, 2, 4, 24, 100]);
+}
+
+#[test]
+#[allow(deprecated)]
+fn get_field_needs_a_buffer_of_the_value_length() {
+    let spec = IsoSpecs::new();
+    // DE 2 LLVAR, DE 3 fixed, DE 4, DE 24 and DE 48 LLLVAR
+    let payload = binary_payload(
+        b"1644",
+        &[0x70, 0, 0x01, 0, 0, 0x01, 0, 0],
+        b"165555444433332222000000000000001000200002R1",
+    );
+    let parsed = IsoMsg::new(&spec, &payload);
+    let mut overridden = IsoMsg::new(&spec, &payload);
+    overridden.set_field(2, b"4111111111111").unwrap();
+    overridden.set_field(3, b"123456").unwrap();
+    overridden.set_field(48, b"R2XYZ").unwrap();
+
+    let cases: [(&IsoMsg, usize, &[u8]); 6] = [
+        (&parsed, 3, b"000000"),
+        (&parsed, 2, b"5555444433332222"),
+        (&parsed, 48, b"R1"),
+        (&overridden, 3, b"123456"),
+        (&overridden, 2, b"4111111111111"),
+        (&overridden, 48, b"R2XYZ"),
+    ];
+    for (iso_msg, index, value) in cases {
+        for buffer_len in [value.len(), value.len() - 1, value.len() + 64] {
+            let mut buffer = vec![0u8; buffer_len];
+            let result = iso_msg.get_field(index, &mut buffer);
+            if buffer_len < value.len() {
+                assert_eq!(
+                    result,
+                    Err(IsoError::BufferTooSmall {
+                        needed: value.len(),
+                        available: buffer_len
+                    }),
+                    "field {} with a {} bytes buffer",
+                    index,
+                    buffer_len
+                );
+            } else {
+                assert_eq!(result, Ok(value.len()), "field {}", index);
+                assert_eq!(&buffer[..value.len()], value, "field {}", index);
+            }
+        }
+    }
+}
+
+/// 2026-10-17 10:30:00 UTC
+fn fixed_context() -> Context {
+    Context::fixed(UNIX_EPOCH + std::time::Duration::from_secs(1_792_233_000))
+}
+
+#[test]
+fn builder_composes_an_auth_request_from_nothing() {
+    let spec = IsoSpecs::new();
+    let context = fixed_context();
+    let builder = IsoMsgBuilder::new(&spec)
+        .set(0, b"0100")
+        .set_str(2, "4111111111111111")
+        .set(3, b"000000")
+        .set_num(4, 10000)
+        .set_transmission_date_time(&context)
+        .set_stan(&context)
+        .set(49, b"840");
+
+    let mut buffer = [0u8; 128];
+    let written = builder.build_into(&mut buffer).unwrap();
+    let expected = binary_payload(
+        b"0100",
+        &[0x72, 0x20, 0, 0, 0, 0, 0x80, 0],
+        b"1641111111111111110000000000000100001017103000000001840",
+    );
+    assert_eq!(&buffer[..written], &expected[..]);
+
+    let iso_msg = builder.build().unwrap();
+    assert_eq!(iso_msg.mti().unwrap().to_string(), "0100");
+    assert_eq!(iso_msg.present_de_numbers(), vec![2, 3, 4, 7, 11, 49]);
+
+    // the first error is kept, later values are ignored
+    let builder = IsoMsgBuilder::new(&spec).set(500, b"X").set(2, b"41111111");
+    assert_eq!(
+        builder.build_into(&mut buffer),
+        Err(IsoError::InvalidField { index: 500 })
+    );
+    assert_eq!(builder.build().err(), Some(IsoError::InvalidField { index: 500 }));
+}
+
+#[test]
+fn signed_amounts_follow_the_field_encoding() {
+    let mut spec = Iso8583_1987Spec::spec().clone();
+    // a domestic network suppressing leading zero bytes, and a fixed leading sign layout
+    let packed_field = |label: &str, label_id: &str, size_type: FieldSizeType| {
+        IsoField::new(label, label_id, FieldCharType::Iso8583_b, 10, size_type)
+    };
+    spec.specs[57] = packed_field("Amount, Net", "057", FieldSizeType::LlVar)
+        .with_packed_amount(PackedAmount::new().with_zero_suppression(1));
+    spec.specs[58] = packed_field("Amount, Fee", "058", FieldSizeType::Fixed)
+        .with_packed_amount(PackedAmount::new().with_sign(SignPosition::Leading, 0xA, 0xB));
+    let mut iso_msg = IsoMsg::empty(&spec);
+    iso_msg.set_field(0, b"0200").unwrap();
+
+    iso_msg.set_signed_amount(57, -1250).unwrap();
+    assert_eq!(iso_msg.get_field_slice(57), Ok(&[0x01, 0x25, 0x0D][..]));
+    assert_eq!(iso_msg.get_signed_amount(57), Ok(-1250));
+    iso_msg.set_signed_amount(57, 0).unwrap();
+    assert_eq!(iso_msg.get_field_slice(57), Ok(&[0x0C][..]));
+    assert_eq!(iso_msg.get_signed_amount(57), Ok(0));
+
+    for amount in [i64::MAX, -i64::MAX, 0, -1] {
+        iso_msg.set_signed_amount(58, amount).unwrap();
+        assert_eq!(iso_msg.get_field_slice(58).unwrap().len(), 10);
+        assert_eq!(iso_msg.get_signed_amount(58), Ok(amount));
+    }
+    assert_eq!(iso_msg.get_field_slice(58).unwrap()[..2], [0xB0, 0]);
+
+    iso_msg.set_field(57, &[0x01, 0x2F, 0x0D]).unwrap();
+    assert_eq!(
+        iso_msg.get_signed_amount(57),
+        Err(IsoError::Numeric(NumericError::InvalidNibble {
+            position: 3,
+            nibble: 0xF
+        }))
+    );
+    iso_msg.set_field(57, &[0x01, 0x25, 0x0F]).unwrap();
+    assert_eq!(
+        iso_msg.get_signed_amount(57),
+        Err(IsoError::Numeric(NumericError::InvalidSign {
+            position: 5,
+            nibble: 0xF
+        }))
+    );
+
+    // x+n fields carry the sign as a C or D prefix
+    iso_msg.set_signed_amount(28, -1500).unwrap();
+    assert_eq!(iso_msg.get_field_str(28), Ok("D00001500".into()));
+    assert_eq!(iso_msg.get_signed_amount(28), Ok(-1500));
+    iso_msg.set_signed_amount(28, 75).unwrap();
+    assert_eq!(iso_msg.get_field_str(28), Ok("C00000075".into()));
+
+    // plain numeric fields carry no sign
+    iso_msg.set_signed_amount(4, 1000).unwrap();
+    assert_eq!(iso_msg.get_signed_amount(4), Ok(1000));
+    assert_eq!(
+        iso_msg.set_signed_amount(4, -1),
+        Err(IsoError::Numeric(NumericError::Negative { value: -1 }))
+    );
+}
+
+#[test]
+fn present_field_indices_leave_the_bitmap_out() {
+    let spec = IsoSpecs::new();
+    let payload = binary_payload(
+        b"1644",
+        &[0x70, 0, 0x01, 0, 0, 0, 0, 0],
+        b"165555444433332222000000000000001000200",
+    );
+    let mut iso_msg = IsoMsg::new(&spec, &payload);
+    assert_eq!(iso_msg.present_field_indices(), vec![0, 2, 3, 4, 24]);
+    // the bitmap holds a value but isn't listed
+    assert!(iso_msg.has_field(1));
+
+    let bits = iso_msg.bitmap_bits();
+    let set_bits: Vec<usize> = (0..bits.len()).filter(|&bit| bits[bit]).collect();
+    assert_eq!(set_bits, vec![1, 2, 3, 23]);
+
+    iso_msg.set_field(100, b"12345").unwrap();
+    let bits = iso_msg.bitmap_bits();
+    assert!(bits[0] && bits[99]);
+    assert_eq!(iso_msg.present_field_indices(), vec![0, 2, 3, 4, 24, 100]);
+}
+
+#[test]
+fn pan_and_fields_are_masked_for_logs() {
+    let spec = IsoSpecs::new();
+    let payload = binary_payload(
+        b"1644",
+        &[0x70, 0, 0x01, 0, 0, 0, 0, 0],
+        b"165555444433332222000000000000001000200",
+    );
+    let mut iso_msg = IsoMsg::new(&spec, &payload);
+    assert_eq!(iso_msg.masked_pan(), Ok("555544******2222".to_string()));
+
+    iso_msg.set_field(2, b"4111111111111111111").unwrap();
+    assert_eq!(iso_msg.masked_pan(), Ok("411111*********1111".to_string()));
+    assert_eq!(iso_msg.mask_field(4, 0, 3, '#'), Ok("#########000".to_string()));
+    // too short to hide anything between the kept digits
+    assert_eq!(iso_msg.mask_field(24, 2, 1, '*'), Ok("***".to_string()));
+
+    iso_msg.remove_field(2).unwrap();
+    assert_eq!(iso_msg.masked_pan(), Err(IsoError::FieldNotSet { index: 2 }));
+    iso_msg.set_field(52, &[0xDE, 0xAD, 0xBE, 0xEF, 0, 1, 2, 3]).unwrap();
+    assert_eq!(
+        iso_msg.mask_field(52, 0, 0, '*'),
+        Err(IsoError::NotTextField { index: 52 })
+    );
+}
+
+#[test]
+fn field_views_follow_mutations() {
+    let spec = IsoSpecs::new();
+    let payload = binary_payload(
+        b"1644",
+        &[0x70, 0, 0x01, 0, 0, 0, 0, 0],
+        b"165555444433332222000000000000001000200",
+    );
+    let mut iso_msg = IsoMsg::new(&spec, &payload);
+    iso_msg.set_field(2, b"4111111111111111").unwrap();
+    iso_msg.remove_field(3).unwrap();
+    iso_msg.set_field(48, b"R1").unwrap();
+
+    let pan: FieldView = iso_msg.iter().find(|field| field.index() == 2).unwrap();
+    assert_eq!(pan.label(), "Primary Account Number");
+    assert_eq!(pan.iso_field().size_type, FieldSizeType::LlVar);
+    assert_eq!(pan.value(), b"4111111111111111");
+
+    let mut by_label = HashMap::new();
+    for field in &iso_msg {
+        by_label.insert(field.label(), field.value());
+    }
+    assert_eq!(by_label.len(), 6);
+    assert_eq!(by_label["Additional Data - Private"], b"R1");
+    assert!(!by_label.contains_key("Processing Code"));
+
+    let indexes: Vec<usize> = iso_msg.iter().map(|field| field.index()).collect();
+    assert_eq!(indexes, vec![0, 1, 2, 4, 24, 48]);
+}
+
+fn pipeline_payload(records: usize) -> Vec<u8> {
+    let mut payload = vec![];
+    for record in 0..records {
+        let amount = if record % 7 == 3 {
+            "00000000A000".to_string()
+        } else {
+            format!("{:012}", record * 100)
+        };
+        let mut fields = format!("165555444433332222000000{}200", amount).into_bytes();
+        let mut bitmap = [0x70, 0, 0x01, 0, 0, 0, 0, 0];
+        if record % 2 == 0 {
+            bitmap[6] = 0x80;
+            fields.extend_from_slice(b"840");
+        }
+        payload.extend(binary_payload(b"1644", &bitmap, &fields));
+    }
+    payload
+}
+
+#[derive(Default)]
+struct FieldCounter {
+    records: usize,
+    fields: usize,
+}
+
+impl FieldConsumer for FieldCounter {
+    fn begin(&mut self, _iso_msg: &IsoMsg) {
+        self.records += 1;
+    }
+
+    fn field(&mut self, _field: FieldView) {
+        self.fields += 1;
+    }
+}
+
+#[test]
+fn pipeline_matches_separate_passes() {
+    let spec = IsoSpecs::new();
+    let payload = pipeline_payload(50);
+    let projected = [2, 4, 24, 49];
+    let mut counter = FieldCounter::default();
+    let mut pipeline = Pipeline::new(&spec)
+        .validate(&DefaultValidator)
+        .project(&projected)
+        .decorate(|iso_msg: &IsoMsg| iso_msg.length())
+        .consumer(&mut counter);
+    let outputs: Vec<PipelineOutput<usize>> =
+        pipeline.run(&payload).collect::<Result<_, _>>().unwrap();
+    drop(pipeline);
+
+    let mut expected = vec![];
+    for record in RecordReader::new(&spec, &payload) {
+        let record = record.unwrap();
+        let iso_msg = IsoMsg::try_new(&spec, record.payload).unwrap();
+        let validation_issues: Vec<IsoError> = iso_msg
+            .iter()
+            .filter(|field| Some(field.index()) != spec.bitmap_index())
+            .filter_map(|field| {
+                DefaultValidator
+                    .validate(field.index(), field.iso_field(), field.value())
+                    .err()
+            })
+            .collect();
+        let projection = projected
+            .iter()
+            .map(|&index| iso_msg.get_field_slice(index).ok().map(|value| value.to_vec()))
+            .collect();
+        expected.push(PipelineOutput {
+            projection,
+            validation_issues,
+            decoration: Some(iso_msg.length()),
+        });
+    }
+
+    assert_eq!(outputs.len(), 50);
+    assert_eq!(outputs, expected);
+    assert_eq!(outputs[3].validation_issues.len(), 1);
+    assert_eq!(outputs[1].projection[3], None);
+    assert_eq!(outputs[0].projection[3], Some(b"840".to_vec()));
+    assert_eq!(counter.records, 50);
+    assert_eq!(counter.fields, 50 * 6 + 25);
+}
+
+#[test]
+fn pipeline_run_stops_at_a_malformed_record() {
+    let spec = IsoSpecs::new();
+    let mut payload = pipeline_payload(2);
+    payload.extend_from_slice(b"16");
+    let mut pipeline = Pipeline::new(&spec).project(&[2]);
+    let outputs: Vec<_> = pipeline.run(&payload).collect();
+
+    assert_eq!(outputs.len(), 3);
+    assert_eq!(
+        outputs[0].as_ref().unwrap().projection,
+        vec![Some(b"5555444433332222".to_vec())]
+    );
+    assert_eq!(outputs[0].as_ref().unwrap().decoration, None);
+    assert!(outputs[2].is_err());
+}
+
+#[test]
+fn data_elements_map_past_a_leading_header() {
+    let mut specs = vec![IsoField::new(
+        "Header",
+        "header",
+        FieldCharType::Iso8583_ans,
+        12,
+        FieldSizeType::Fixed,
+    )];
+    specs.extend(IsoSpecs::define_specs());
+    let spec = IsoSpecs::from_specs(specs);
+    assert_eq!(spec.de_to_index(2), Some(3));
+    assert_eq!(spec.de_to_index(24), Some(25));
+    assert_eq!(spec.de_to_index(1), None);
+    assert_eq!(spec.de_to_index(0), None);
+    assert_eq!(spec.de_to_index(255), None);
+    assert_eq!(IsoSpecs::new().de_to_index(2), Some(2));
+
+    let mut payload = b"IPMHEADER001".to_vec();
+    payload.extend(binary_payload(
+        b"1644",
+        &[0x70, 0, 0x01, 0, 0, 0, 0, 0],
+        b"165555444433332222000000000000001000200",
+    ));
+    let mut iso_msg = IsoMsg::new(&spec, &payload);
+    assert_eq!(iso_msg.get_de(2), Ok(&b"5555444433332222"[..]));
+    assert_eq!(iso_msg.get_de(24), Ok(&b"200"[..]));
+    assert_eq!(iso_msg.get_de(49), Err(IsoError::FieldNotSet { index: 50 }));
+
+    iso_msg.set_de(49, b"978").unwrap();
+    assert_eq!(iso_msg.get_field_slice(50), Ok(&b"978"[..]));
+    assert_eq!(iso_msg.present_de_numbers(), vec![2, 3, 4, 24, 49]);
+
+    assert_eq!(iso_msg.set_de(1, b""), Err(IsoError::ReservedDataElement { de: 1 }));
+    assert_eq!(iso_msg.set_de(0, b"1644"), Err(IsoError::ReservedDataElement { de: 0 }));
+    assert_eq!(iso_msg.get_de(0), Err(IsoError::ReservedDataElement { de: 0 }));
+    assert_eq!(iso_msg.get_de(255), Err(IsoError::UnknownDataElement { de: 255 }));
+    assert_eq!(
+        IsoError::ReservedDataElement { de: 1 }.to_string(),
+        "data element 1 is the bitmap, not a data field"
+    );
+}
+
+#[test]
+fn llllllvar_fields_use_a_six_digit_prefix() {
+    assert_eq!(FieldSizeType::from_str("LLLLLLVAR"), Some(FieldSizeType::LlllllVar));
+    assert_eq!(FieldSizeType::LlllllVar.as_str(), "llllllvar");
+
+    let mut specs = IsoSpecs::define_specs();
+    specs[48] = IsoField::new(
+        "Attachment",
+        "048",
+        FieldCharType::Iso8583_ans,
+        100_000,
+        FieldSizeType::LlllllVar,
+    );
+    let spec = IsoSpecs::from_specs(specs);
+    let attachment = vec![b'X'; 12_000];
+    let mut fields = b"165555444433332222000000000000001000200012000".to_vec();
+    fields.extend_from_slice(&attachment);
+    let payload = binary_payload(b"1644", &[0x70, 0, 0x01, 0, 0, 0x01, 0, 0], &fields);
+
+    let parsed = IsoMsg::from_byte_array(&spec, &payload).unwrap();
+    assert_eq!(parsed[48].tag_len, 6);
+    let mut iso_msg = IsoMsg::new(&spec, &payload);
+    assert_eq!(iso_msg.get_field_length_prefix(48), 6);
+    assert_eq!(iso_msg.get_field_slice(48), Ok(&attachment[..]));
+    let mut buffer = vec![0; payload.len()];
+    assert_eq!(iso_msg.to_byte_array(&mut buffer), Ok(payload.len()));
+    assert_eq!(buffer, payload);
+
+    iso_msg.set_field(48, b"SHORT").unwrap();
+    assert!(iso_msg.to_vec().unwrap().ends_with(b"200000005SHORT"));
+    assert_eq!(
+        iso_msg.set_field(48, &vec![b'X'; 100_001]),
+        Err(IsoError::ValueTooLong { index: 48, max: 100_000, got: 100_001 })
+    );
+
+    let truncated = binary_payload(
+        b"1644",
+        &[0x70, 0, 0x01, 0, 0, 0x01, 0, 0],
+        b"16555544443333222200000000000000100020000500",
+    );
+    assert_eq!(
+        IsoMsg::from_byte_array(&spec, &truncated).unwrap_err(),
+        IsoError::InvalidLengthPrefix { label_id: "048".to_string(), prefix: b"00500".to_vec() }
+    );
+}
+
+fn budget_error(error: std::io::Error) -> IsoError {
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    error.into_inner().unwrap().downcast::<IsoError>().map(|error| *error).unwrap()
+}
+
+#[test]
+fn crafted_headers_exceed_the_allocation_budget() {
+    // a 4 GiB frame announced by a 4 byte header with nothing behind it
+    let crafted = [0xFF, 0xFF, 0xFF, 0xFF];
+    let exceeded = IsoError::AllocationBudgetExceeded {
+        requested: u32::MAX as usize,
+        limit: AllocBudget::DEFAULT_MAX_ALLOCATION,
+        context: "frame",
+    };
+    let error = read_framed(&mut &crafted[..], LengthHeader::Binary4).unwrap_err();
+    assert_eq!(budget_error(error), exceeded);
+    let timeout = std::time::Duration::from_secs(5);
+    let error = read_message_timeout(&mut &crafted[..], LengthHeader::Binary4, timeout);
+    assert_eq!(budget_error(error.unwrap_err()), exceeded);
+
+    let budget = AllocBudget::new(100, 1000);
+    let error = read_framed_with(&mut &b"0101"[..], LengthHeader::Ascii4, &budget).unwrap_err();
+    assert_eq!(
+        budget_error(error),
+        IsoError::AllocationBudgetExceeded { requested: 101, limit: 100, context: "frame" }
+    );
+    let mut frame = b"0003".to_vec();
+    frame.extend_from_slice(b"abc");
+    assert_eq!(
+        read_framed_with(&mut &frame[..], LengthHeader::Ascii4, &budget).unwrap(),
+        b"abc"
+    );
+    let mut reader = &b"0101"[..];
+    let error = read_message_timeout_with(&mut reader, LengthHeader::Ascii4, timeout, &budget);
+    assert_eq!(
+        budget_error(error.unwrap_err()),
+        IsoError::AllocationBudgetExceeded { requested: 101, limit: 100, context: "frame" }
+    );
+    let mut reader = &frame[..];
+    assert_eq!(
+        read_message_timeout_with(&mut reader, LengthHeader::Ascii4, timeout, &budget).unwrap(),
+        b"abc"
+    );
+
+    // records copied into a cache are bounded the same way
+    let spec = IsoSpecs::new();
+    let record = binary_payload(
+        b"1644",
+        &[0x70, 0, 0x01, 0, 0, 0, 0, 0],
+        b"165555444433332222000000000000001000200",
+    );
+    let mut cache =
+        RecordCache::new(&spec, &record, 1000).with_alloc_budget(AllocBudget::new(50, 1000));
+    assert_eq!(
+        cache.get(0).unwrap_err(),
+        IsoError::AllocationBudgetExceeded { requested: 51, limit: 50, context: "record" }
+    );
+    assert!(!cache.contains(0));
+    let mut cache =
+        RecordCache::new(&spec, &record, 1000).with_alloc_budget(AllocBudget::new(51, 1000));
+    assert_eq!(cache.get(0).unwrap().length(), 51);
+
+    // parsing borrows field values from the payload, a LLLLLLVAR prefix announcing more
+    // than the payload holds fails the bounds check without allocating
+    let mut specs = IsoSpecs::define_specs();
+    specs[48] = IsoField::new(
+        "Attachment",
+        "048",
+        FieldCharType::Iso8583_ans,
+        999_999,
+        FieldSizeType::LlllllVar,
+    );
+    let spec = IsoSpecs::from_specs(specs);
+    let payload = binary_payload(
+        b"1644",
+        &[0x70, 0, 0x01, 0, 0, 0x01, 0, 0],
+        b"165555444433332222000000000000001000200999999ABC",
+    );
+    assert!(matches!(
+        IsoMsg::try_new(&spec, &payload),
+        Err(IsoError::UnexpectedEof { field_index: 48, .. })
+    ));
+}
+
+#[test]
+fn file_reader_charges_records_to_the_allocation_budget() {
+    let mut payload = vec![];
+    File::open("tests/ascii_records.txt").unwrap().read_to_end(&mut payload).unwrap();
+    let first_record = payload.iter().position(|&byte| byte == b'\n').unwrap();
+    let mut options = iso8583::ReaderOptions {
+        framing: Framing::lines(),
+        alloc_budget: AllocBudget::new(first_record, first_record + 1),
+        ..Default::default()
+    };
+    options.spec.detect_bitmap_encoding = true;
+
+    let error = iso8583::parse_file_with(payload.clone(), &options).unwrap_err();
+    assert!(error.to_string().starts_with("record 1: record needs"), "{}", error);
+
+    options.alloc_budget = AllocBudget::new(first_record - 1, usize::MAX);
+    let error = iso8583::parse_file_with(payload.clone(), &options).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        format!(
+            "record 0: record needs {} bytes but the allocation budget allows {} bytes",
+            first_record,
+            first_record - 1
+        )
+    );
+
+    options.alloc_budget = AllocBudget::default();
+    assert_eq!(iso8583::parse_file_with(payload, &options).unwrap().messages.len(), 2);
+}
+
+#[test]
+fn equal_messages_hash_the_same() {
+    let spec = IsoSpecs::new();
+    let payload = binary_payload(
+        b"1644",
+        &[0x70, 0, 0x01, 0, 0, 0, 0, 0],
+        b"165555444433332222000000000000001000200",
+    );
+    let parsed = IsoMsg::new(&spec, &payload);
+    let mut rebuilt = IsoMsg::empty(&spec);
+    for (index, value) in [
+        (24, &b"200"[..]),
+        (0, b"1644"),
+        (4, b"000000001000"),
+        (3, b"000000"),
+        (2, b"5555444433332222"),
+    ] {
+        rebuilt.set_field(index, value).unwrap();
+    }
+    let mut hex_bitmap = parsed.clone();
+    hex_bitmap.set_bitmap_encoding(BitmapEncoding::Hex);
+    let mut other = parsed.clone();
+    other.set_field(24, b"201").unwrap();
+
+    let mut messages = HashSet::new();
+    assert!(messages.insert(parsed));
+    assert!(!messages.insert(rebuilt));
+    assert!(!messages.insert(hex_bitmap));
+    assert!(messages.insert(other));
+    assert_eq!(messages.len(), 2);
+
+    let mut counts: HashMap<IsoMsg, usize> = HashMap::new();
+    *counts.entry(IsoMsg::new(&spec, &payload)).or_default() += 1;
+    *counts.entry(IsoMsg::new(&spec, &payload)).or_default() += 1;
+    assert_eq!(counts.values().copied().collect::<Vec<_>>(), vec![2]);
+}
+
+#[test]
+fn messages_differing_only_in_bitmap_encoding_hash_the_same() {
+    let hash = |iso_msg: &IsoMsg| {
+        let mut hasher = DefaultHasher::new();
+        iso_msg.hash(&mut hasher);
+        hasher.finish()
+    };
+    let fields = b"165555444433332222000000000000001000200";
+    let binary_spec = IsoSpecs::new();
+    let binary_payload = binary_payload(b"1644", &[0x70, 0, 0x01, 0, 0, 0, 0, 0], fields);
+    let mut hex_spec = IsoSpecs::new();
+    hex_spec.bitmap_encoding = BitmapEncoding::Hex;
+    hex_spec.specs[1].length = 32;
+    let mut hex_payload = b"16447000010000000000".to_vec();
+    hex_payload.extend_from_slice(fields);
+
+    let binary = IsoMsg::new(&binary_spec, &binary_payload);
+    let hex = IsoMsg::new(&hex_spec, &hex_payload);
+    assert_ne!(binary.to_vec().unwrap(), hex.to_vec().unwrap());
+    assert_eq!(binary, hex);
+    assert_eq!(hash(&binary), hash(&hex));
+}
+
+#[test]
+fn fields_are_reachable_by_their_label() {
+    let mut spec = IsoSpecs::new();
+    spec.specs[32].label = " Acquirer Inst Id Code".to_string();
+    assert_eq!(spec.field_index_by_name("acquirer inst id code "), Ok(32));
+    assert_eq!(
+        spec.field_index_by_name("Reserved for National use"),
+        Err(IsoError::AmbiguousLabel {
+            label: "Reserved for National use".to_string(),
+            indexes: spec.find_by_label("Reserved for National use"),
+        })
+    );
+    assert_eq!(
+        spec.field_index_by_name("  Not a label"),
+        Err(IsoError::UnknownLabel { label: "Not a label".to_string() })
+    );
+
+    let payload = binary_payload(
+        b"1644",
+        &[0x70, 0, 0x01, 0, 0, 0, 0, 0],
+        b"165555444433332222000000000000001000200",
+    );
+    let mut iso_msg = IsoMsg::new(&spec, &payload);
+    assert_eq!(
+        iso_msg.get_field_by_name("PRIMARY ACCOUNT NUMBER"),
+        Ok(&b"5555444433332222"[..])
+    );
+    assert_eq!(
+        iso_msg.get_field_by_name("Acquirer Inst Id Code"),
+        Err(IsoError::FieldNotSet { index: 32 })
+    );
+    iso_msg.set_field_by_name("acquirer inst id code", b"123456").unwrap();
+    assert_eq!(iso_msg.get_field_slice(32), Ok(&b"123456"[..]));
+    assert!(matches!(
+        iso_msg.set_field_by_name("Reserved for National use", b"X"),
+        Err(IsoError::AmbiguousLabel { .. })
+    ));
+}
+
+#[test]
+fn legacy_labels_resolve_to_the_corrected_ones() {
+    let spec = IsoSpecs::new();
+    assert_eq!(spec.specs[58].label, "Authorizing Agent Inst Id Code");
+    assert_eq!(spec.specs[124].label, "Additional Data 4");
+    assert_eq!(iso_specs::current_label("Additional Data 4)"), "Additional Data 4");
+    assert_eq!(iso_specs::current_label("Processing Code"), "Processing Code");
+
+    for (legacy, current) in iso_specs::LEGACY_LABELS {
+        assert_eq!(spec.find_by_label(legacy), spec.find_by_label(current));
+        assert_eq!(spec.find_by_label(current).len(), 1);
+        assert_eq!(spec.field_index_by_name(legacy), spec.field_index_by_name(current));
+    }
+    assert_eq!(spec.field_index_by_name(" authorizing agent inst id cod"), Ok(58));
+    assert_eq!(spec.find_by_label("ADDITIONAL DATA 4) "), vec![124]);
+    assert_eq!(spec.find_unique_by_label("additional data 4"), Ok(124));
+}
+
+#[test]
+fn copy_field_from_reencodes_the_value_for_the_target() {
+    let spec = IsoSpecs::new();
+    let payload = binary_payload(
+        b"1644",
+        &[0x70, 0, 0x01, 0, 0, 0, 0, 0],
+        b"165555444433332222000000000000001000200",
+    );
+    let request = IsoMsg::new(&spec, &payload);
+
+    let mut response = IsoMsg::empty(&spec);
+    response.set_field(0, b"1644").unwrap();
+    for index in [2, 3, 4, 24] {
+        response.copy_field_from(&request, index).unwrap();
+    }
+    assert_eq!(response.to_vec().unwrap(), payload);
+
+    // absent fields are skipped, the target keeps its own value
+    response.set_field(11, b"000042").unwrap();
+    assert_eq!(response.copy_field_from(&request, 11), Ok(()));
+    assert_eq!(response.get_field_slice(11), Ok(&b"000042"[..]));
+    assert_eq!(
+        response.copy_field_from(&request, 500),
+        Err(IsoError::InvalidField { index: 500 })
+    );
+
+    let mut specs = IsoSpecs::define_specs();
+    specs[2].length = 12;
+    specs[3].char_type = FieldCharType::Iso8583_n_bcd;
+    let narrow_spec = IsoSpecs::from_specs(specs);
+    let mut narrow = IsoMsg::empty(&narrow_spec);
+    assert_eq!(
+        narrow.copy_field_from(&request, 2),
+        Err(IsoError::ValueTooLong { index: 2, max: 12, got: 16 })
+    );
+    narrow.copy_field_from(&request, 3).unwrap();
+    assert_eq!(narrow.get_field_slice(3), Ok(&[0x00, 0x00, 0x00][..]));
+
+    // packed digits are expanded before being set on a text field
+    let mut back = IsoMsg::empty(&spec);
+    back.copy_field_from(&narrow, 3).unwrap();
+    assert_eq!(back.get_field_slice(3), Ok(&b"000000"[..]));
+}
+
+#[test]
+fn bcd_length_prefixes_take_half_the_digits() {
+    let mut specs = IsoSpecs::define_specs();
+    specs[2] = specs[2].clone().with_length_encoding(LengthEncoding::Bcd);
+    specs[48] = specs[48].clone().with_length_encoding(LengthEncoding::Bcd);
+    let spec = IsoSpecs::from_specs(specs);
+
+    // DE 2 LLVAR of 16 digits behind 0x16, DE 48 LLLVAR of 12 bytes behind 0x00 0x12
+    let mut fields = vec![0x16];
+    fields.extend_from_slice(b"5555444433332222000000000000001000200");
+    fields.extend_from_slice(&[0x00, 0x12]);
+    fields.extend_from_slice(b"0158003USA00");
+    let payload = binary_payload(b"1644", &[0x70, 0, 0x01, 0, 0, 0x01, 0, 0], &fields);
+    let iso_msg = IsoMsg::new(&spec, &payload);
+
+    assert_eq!(iso_msg.get_field_length_prefix(2), 1);
+    assert_eq!(iso_msg.get_field_length_prefix(48), 2);
+    assert_eq!(iso_msg.get_field_slice(2), Ok(&b"5555444433332222"[..]));
+    assert_eq!(iso_msg.get_field_slice(48), Ok(&b"0158003USA00"[..]));
+    assert_eq!(iso_msg.length(), payload.len());
+    assert_eq!(iso_msg.validate(), Ok(()));
+
+    let mut rebuilt = IsoMsg::empty(&spec);
+    rebuilt.set_field(0, b"1644").unwrap();
+    rebuilt.set_field(2, b"5555444433332222").unwrap();
+    rebuilt.set_field(3, b"000000").unwrap();
+    rebuilt.set_field(4, b"000000001000").unwrap();
+    rebuilt.set_field(24, b"200").unwrap();
+    rebuilt.set_field(48, b"0158003USA00").unwrap();
+    assert_eq!(rebuilt.to_vec().unwrap(), payload);
+    rebuilt.set_field(2, b"4111111111111").unwrap();
+    assert_eq!(rebuilt.to_vec().unwrap()[12], 0x13);
+
+    // a nibble above 9 is not a length
+    let mut invalid = payload.clone();
+    invalid[12] = 0x1A;
+    assert_eq!(
+        IsoMsg::from_byte_array(&spec, &invalid).unwrap_err(),
+        IsoError::InvalidLengthPrefix { label_id: "002".to_string(), prefix: vec![0x1A] }
+    );
+
+    let yaml_spec = iso8583::yaml_specs::YamlSpec::from_str(
+        r#"
+fields:
+  - { label_id: mti, label: Message Type Indicator, char_type: mti, size_type: fixed, length: 4 }
+  - { label_id: bitmaps, label: Bitmaps, char_type: bmp, size_type: bitmap, length: 16 }
+  - { label_id: "002", label: PAN, char_type: ns, size_type: llvar, length: 19,
+      length_encoding: bcd }
+"#,
+    )
+    .unwrap();
+    assert_eq!(yaml_spec.spec().specs[2].length_encoding, LengthEncoding::Bcd);
+    assert_eq!(yaml_spec.spec().specs[1].length_encoding, LengthEncoding::Ascii);
+}
+
+#[test]
+fn fixed_contexts_make_built_messages_reproducible() {
+    let spec = IsoSpecs::new();
+    let build = |context: &Context| {
+        IsoMsgBuilder::new(&spec)
+            .set(0, b"0800")
+            .set_transmission_date_time(context)
+            .set_stan(context)
+            .build()
+            .unwrap()
+            .to_vec()
+            .unwrap()
+    };
+    let first = fixed_context();
+    let second = fixed_context();
+    assert_eq!(build(&first), build(&second));
+    assert_eq!(build(&first), build(&second));
+
+    let context = fixed_context().with_sequence("stan", 999_999);
+    let iso_msg = IsoMsgBuilder::new(&spec).set_stan(&context).build().unwrap();
+    assert_eq!(iso_msg.get_de(11), Ok(&b"999999"[..]));
+    context.advance(std::time::Duration::from_secs(86_400 * 15 + 3_600 * 14 + 61));
+    let iso_msg = IsoMsgBuilder::new(&spec)
+        .set_stan(&context)
+        .set_transmission_date_time(&context)
+        .build()
+        .unwrap();
+    assert_eq!(iso_msg.get_de(11), Ok(&b"000001"[..]));
+    assert_eq!(iso_msg.get_de(7), Ok(&b"1102003101"[..]));
+
+    assert_eq!(context.next_file_id(), 1);
+    assert_eq!(context.next_file_id(), 2);
+    assert_eq!(context.next_sequence("batch"), 1);
+    #[cfg(feature = "std")]
+    assert!(Context::system().next_file_id() > 1_700_000_000);
+
+    // a spec without DE 11 reports it instead of setting another field
+    let short_spec = IsoSpecs::from_specs(spec.specs[..5].to_vec());
+    let builder = IsoMsgBuilder::new(&short_spec).set_stan(&context);
+    assert_eq!(builder.build().err(), Some(IsoError::UnknownDataElement { de: 11 }));
+}
+
+#[test]
+fn has_field_checks_presence_without_reading() {
+    let spec = IsoSpecs::new();
+    let payload = binary_payload(
+        b"1644",
+        &[0x70, 0, 0x01, 0, 0, 0, 0, 0],
+        b"165555444433332222000000000000001000200",
+    );
+    let mut iso_msg = IsoMsg::new(&spec, &payload);
+    assert!(iso_msg.has_field(0));
+    assert!(iso_msg.has_field(1));
+    assert!(iso_msg.has_field(24));
+    assert!(!iso_msg.has_field(11));
+    assert!(!iso_msg.has_field(usize::MAX));
+
+    iso_msg.remove_field(24).unwrap();
+    assert!(!iso_msg.has_field(24));
+    assert_eq!(iso_msg.get_field_slice(24), Err(IsoError::FieldNotSet { index: 24 }));
+    iso_msg.set_field(11, b"000001").unwrap();
+    assert!(iso_msg.has_field(11));
+}
+
+#[cfg(feature = "json-spec")]
+#[test]
+fn parse_with_json_spec() {
+    let json = r#"{
+        "bitmap_encoding": "binary",
+        "fields": [
+            { "label_id": "mti", "label": "Message Type Indicator", "char_type": "mti",
+              "size_type": "fixed", "length": 4 },
+            { "label_id": "bitmaps", "label": "Bitmaps", "char_type": "bmp",
+              "size_type": "bitmap", "length": 16 },
+            { "label_id": "002", "label": "Primary Account Number", "char_type": "ns",
+              "size_type": "llvar", "length": 19 },
+            { "label_id": "003", "label": "Processing Code", "char_type": "ns",
+              "size_type": "fixed", "length": 6 },
+            { "label_id": "004", "label": "Amount, Txn", "char_type": "n",
+              "size_type": "fixed", "length": 12 }
+        ]
+    }"#;
+    let spec = JsonSpec::from_str(json).unwrap();
+    let payload = binary_payload(
+        b"1644",
+        &[0x70, 0, 0, 0, 0, 0, 0, 0],
+        b"165555444433332222000000000000001000",
+    );
+
+    let iso_msg = IsoMsg::new(spec.spec(), &payload);
+    assert_eq!(iso_msg.get_field_str(2), Ok("5555444433332222".into()));
+    assert_eq!(iso_msg.get_field_str(3), Ok("000000".into()));
+    assert_eq!(iso_msg.get_field_str(4), Ok("000000001000".into()));
+    assert_eq!(iso_msg.to_vec().unwrap(), payload);
+
+    let unknown_type = json.replace("\"ns\"", "\"nx\"");
+    assert!(matches!(
+        JsonSpec::from_str(&unknown_type),
+        Err(SpecError::UnknownType { .. })
+    ));
+    assert!(matches!(JsonSpec::from_str("{"), Err(SpecError::Json(_))));
+}
+
+#[cfg(feature = "toml-spec")]
+#[test]
+fn parse_with_toml_spec() {
+    let spec = TomlSpec::from_str(
+        r#"
+bitmap_encoding = "binary"
+
+[[field]]
+index = 0
+label_id = "mti"
+label = "Message Type Indicator"
+char_type = "mti"
+size_type = "fixed"
+length = 4
+
+[[field]]
+index = 1
+label_id = "bitmaps"
+label = "Bitmaps"
+char_type = "bmp"
+size_type = "bitmap"
+length = 16
+
+# listed out of order on purpose
+[[field]]
+index = 3
+label = "Processing Code"
+char_type = "ns"
+size_type = "fixed"
+length = 6
+
+[[field]]
+index = 2
+label = "Primary Account Number"
+char_type = "ns"
+size_type = "llvar"
+length = 19
+"#,
+    )
+    .unwrap();
+    let label_ids: Vec<&str> = spec
+        .spec()
+        .specs
+        .iter()
+        .map(|spec| spec.label_id.as_str())
+        .collect();
+    assert_eq!(label_ids, vec!["mti", "bitmaps", "002", "003"]);
+
+    let payload = binary_payload(
+        b"1644",
+        &[0x60, 0, 0, 0, 0, 0, 0, 0],
+        b"165555444433332222000000",
+    );
+
+    let iso_msg = IsoMsg::new(spec.spec(), &payload);
+    assert_eq!(iso_msg.get_field_str(2), Ok("5555444433332222".into()));
+    assert_eq!(iso_msg.get_field_str(3), Ok("000000".into()));
+
+    let gap =
+        "[[field]]\nindex = 1\nlabel = \"x\"\nchar_type = \"n\"\nsize_type = \"fixed\"\nlength = 1";
+    assert!(matches!(
+        TomlSpec::from_str(gap),
+        Err(SpecError::UnexpectedIndex {
+            index: 1,
+            expected: 0
+        })
+    ));
+    assert!(matches!(
+        TomlSpec::from_str("[[field]"),
+        Err(SpecError::Toml(_))
+    ));
+}
+
+#[test]
+fn record_cache_evicts_least_recently_used() {
+    let spec = IsoSpecs::new();
+    // three records of 51 bytes differing by their PAN
+    let mut payload = vec![];
+    for pan in ["1111", "2222", "3333"] {
+        let fields = format!("16555544443333{}000000000000001000200", pan);
+        let bitmap = [0x70, 0, 0x01, 0, 0, 0, 0, 0];
+        payload.extend(binary_payload(b"1644", &bitmap, fields.as_bytes()));
+    }
+    let pan = |iso_msg: &OwnedIsoMsg| iso_msg.get_field_str(2).unwrap()[12..].to_string();
+
+    // room for two records
+    let mut cache = RecordCache::new(&spec, &payload, 110);
+    assert_eq!(pan(cache.get(0).unwrap()), "1111");
+    assert_eq!(pan(cache.get(51).unwrap()), "2222");
+    assert_eq!(pan(cache.get(0).unwrap()), "1111");
+    // the record at 51 is the least recently used
+    assert_eq!(pan(cache.get(102).unwrap()), "3333");
+    assert!(cache.contains(0) && !cache.contains(51) && cache.contains(102));
+    assert_eq!(pan(cache.get(51).unwrap()), "2222");
+    assert!(!cache.contains(0));
+
+    assert_eq!(
+        cache.stats(),
+        CacheStats {
+            hits: 1,
+            misses: 4,
+            evictions: 2,
+            cached_bytes: 102,
+        }
+    );
+
+    // a record above the budget is kept alone
+    let mut cache = RecordCache::new(&spec, &payload, 10);
+    assert_eq!(pan(cache.get(0).unwrap()), "1111");
+    assert_eq!(pan(cache.get(51).unwrap()), "2222");
+    assert_eq!(cache.stats().evictions, 1);
+    assert_eq!(cache.stats().cached_bytes, 51);
+
+    assert!(matches!(
+        cache.get(400),
+        Err(IsoError::UnexpectedEof { .. })
+    ));
+    assert!(cache.get(3).is_err());
+}
+
+const YAML_BASE_SPEC: &str = r#"
+bitmap_encoding: binary
+fields:
+  - { label_id: mti, label: Message Type Indicator, char_type: mti, size_type: fixed, length: 4 }
+  - { label_id: bitmaps, label: Bitmaps, char_type: bmp, size_type: bitmap, length: 16 }
+  - { label_id: "002", label: Primary Account Number, char_type: ns, size_type: llvar, length: 19 }
+  - { label_id: "003", label: Processing Code, char_type: ns, size_type: fixed, length: 6 }
+  - { label_id: "004", label: "Amount, Txn", char_type: n, size_type: fixed, length: 12 }
+"#;
+
+#[test]
+fn yaml_spec_matches_the_builtin_fields() {
+    let path = std::env::temp_dir().join(format!("yaml_spec_{}.yaml", std::process::id()));
+    std::fs::write(&path, YAML_BASE_SPEC).unwrap();
+    let from_path = YamlSpec::from_path(&path);
+    std::fs::remove_file(&path).unwrap();
+
+    // the yaml spec repeats the first fields of the hardcoded spec
+    let builtin = IsoSpecs::define_specs();
+    let yaml_spec = from_path.unwrap();
+    assert_eq!(yaml_spec.spec().specs, builtin[..5].to_vec());
+    assert_eq!(yaml_spec.spec().bitmap_encoding, BitmapEncoding::Binary);
+
+    assert!(matches!(
+        YamlSpec::from_path(std::path::Path::new("missing_spec.yaml")),
+        Err(SpecError::Io(_))
+    ));
+    assert!(matches!(
+        YamlSpec::from_str(&YAML_BASE_SPEC.replace("char_type: ns", "char_type: nx")),
+        Err(SpecError::UnknownType { .. })
+    ));
+}